@@ -0,0 +1,5 @@
+-- A folder's parent path alone isn't unique, since sibling folders share the same parent path. The
+-- `(owner_id, parent_name_path, name)` constraint already prevents actual conflicts.
+ALTER TABLE folders
+    DROP CONSTRAINT folders_parent_id_path_key,
+    DROP CONSTRAINT folders_parent_name_path_key;
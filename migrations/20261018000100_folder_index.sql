@@ -0,0 +1,2 @@
+ALTER TABLE folders
+    ADD COLUMN index_enabled boolean NOT NULL DEFAULT FALSE;
@@ -0,0 +1,4 @@
+ALTER TABLE folders
+    ADD COLUMN site_enabled boolean NOT NULL DEFAULT FALSE,
+    ADD COLUMN site_clean_urls boolean NOT NULL DEFAULT FALSE,
+    ADD COLUMN site_headers jsonb NOT NULL DEFAULT '{}';
@@ -0,0 +1,16 @@
+CREATE TABLE custom_domains (
+    created_at timestamptz NOT NULL DEFAULT now(),
+    verified_at timestamptz,
+    domain text NOT NULL,
+    owner_id bytea NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    folder_id bytea NOT NULL REFERENCES folders (id) ON DELETE CASCADE,
+    challenge_token bytea NOT NULL,
+
+    PRIMARY KEY (owner_id, domain)
+);
+
+-- Any number of users can claim a domain, but only one can prove they own it.
+CREATE UNIQUE INDEX verified_custom_domains ON custom_domains (domain)
+    WHERE verified_at IS NOT NULL;
+
+CREATE INDEX custom_domains_by_folder_id ON custom_domains (folder_id);
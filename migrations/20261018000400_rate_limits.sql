@@ -0,0 +1,9 @@
+-- Only used when rate limits are shared between server instances through the database.
+CREATE UNLOGGED TABLE rate_limit_buckets (
+    key text PRIMARY KEY,
+    tokens double precision NOT NULL,
+    updated_at timestamptz NOT NULL,
+    full_at timestamptz NOT NULL
+);
+
+CREATE INDEX rate_limit_buckets_by_full_at ON rate_limit_buckets (full_at);
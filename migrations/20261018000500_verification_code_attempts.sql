@@ -0,0 +1,14 @@
+ALTER TABLE unverified_emails
+    ADD COLUMN code_attempts integer NOT NULL DEFAULT 0;
+
+CREATE TABLE audit_log (
+    created_at timestamptz NOT NULL DEFAULT now(),
+    event text NOT NULL,
+    user_id bytea REFERENCES users (id) ON DELETE SET NULL,
+    email citext,
+    ip_address text
+);
+
+CREATE INDEX audit_log_by_created_at ON audit_log (created_at);
+CREATE INDEX audit_log_by_user_id ON audit_log (user_id);
+CREATE INDEX audit_log_by_email ON audit_log (email);
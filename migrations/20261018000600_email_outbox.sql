@@ -0,0 +1,16 @@
+-- Emails are queued here in the same transaction as the change that triggers them, then delivered
+-- by a background worker.
+CREATE TABLE email_outbox (
+    id bigint GENERATED ALWAYS AS IDENTITY PRIMARY KEY,
+    created_at timestamptz NOT NULL DEFAULT now(),
+    envelope jsonb NOT NULL,
+    message bytea NOT NULL,
+    attempts integer NOT NULL DEFAULT 0,
+    next_attempt_at timestamptz NOT NULL DEFAULT now(),
+    last_error text,
+    -- Set once delivery is given up on. Dead emails are kept for review and never retried.
+    dead_at timestamptz
+);
+
+CREATE INDEX email_outbox_pending ON email_outbox (next_attempt_at)
+    WHERE dead_at IS NULL;
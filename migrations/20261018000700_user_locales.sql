@@ -0,0 +1,3 @@
+-- The locale emails to the user are written in. See `Locale` in the backend.
+ALTER TABLE users
+    ADD COLUMN locale text NOT NULL DEFAULT 'en';
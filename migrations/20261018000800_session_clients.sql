@@ -0,0 +1,5 @@
+-- Approximate information about the client each session was created by, so users can be notified of
+-- sign-ins from new clients.
+ALTER TABLE sessions
+    ADD COLUMN ip_address text,
+    ADD COLUMN user_agent text;
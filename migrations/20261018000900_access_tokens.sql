@@ -0,0 +1,15 @@
+CREATE TYPE access_token_scope AS ENUM ('read_files', 'write_files', 'manage_shares');
+
+-- Tokens for programmatic access to the API, limited to specific scopes.
+CREATE TABLE access_tokens (
+    created_at timestamptz NOT NULL DEFAULT now(),
+    expires_at timestamptz,
+    last_used_at timestamptz,
+    id bytea PRIMARY KEY,
+    token_hash bytea NOT NULL UNIQUE,
+    user_id bytea NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    name text NOT NULL,
+    scopes access_token_scope[] NOT NULL
+);
+
+CREATE INDEX access_tokens_by_user_id ON access_tokens (user_id);
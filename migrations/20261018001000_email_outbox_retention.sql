@@ -0,0 +1,11 @@
+-- Dead emails only keep their envelope and last error for review, and are purged after a retention
+-- period, since their messages can contain personal data and links that are still valid.
+ALTER TABLE email_outbox
+    ALTER COLUMN message DROP NOT NULL;
+
+UPDATE email_outbox
+    SET message = NULL
+    WHERE dead_at IS NOT NULL;
+
+CREATE INDEX email_outbox_dead ON email_outbox (dead_at)
+    WHERE dead_at IS NOT NULL;
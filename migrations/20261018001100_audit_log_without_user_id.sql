@@ -0,0 +1,4 @@
+-- Every audit log event so far is about a new user's email verification, before the user exists, so
+-- this column was always null.
+ALTER TABLE audit_log
+    DROP COLUMN user_id;
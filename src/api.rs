@@ -1,30 +1,41 @@
 //! A web server for the HTTP API. File Garden exposes this via `https://filegarden.com/api/`.
 
-use std::error::Error as _;
+use std::{error::Error as _, iter, time::Duration};
 
 use axum::{
     extract::{
         rejection::{JsonRejection, QueryRejection},
         Request, State,
     },
-    http::StatusCode,
+    http::{header::RETRY_AFTER, HeaderValue, StatusCode},
+    middleware,
     response::IntoResponse,
+    Router,
 };
 use axum_macros::{FromRequest, FromRequestParts};
 use routes::ROUTER;
 use serde::Serialize;
-use strum_macros::IntoStaticStr;
+use strum::VariantNames as _;
+use strum_macros::{IntoStaticStr, VariantNames};
 use thiserror::Error;
-use tower::ServiceExt;
+use tower::{Layer as _, ServiceExt};
+use utoipa::{
+    openapi::{ObjectBuilder, Type},
+    ToSchema,
+};
 
 use crate::AppState;
 
 mod captcha;
+mod cors;
+mod csrf;
+mod rate_limit;
 pub mod routes;
+pub mod session;
 pub mod validation;
 
 /// An API error.
-#[derive(Error, IntoStaticStr, Debug)]
+#[derive(Error, IntoStaticStr, VariantNames, Debug)]
 #[strum(serialize_all = "SCREAMING_SNAKE_CASE")]
 #[non_exhaustive]
 pub enum Error {
@@ -36,6 +47,30 @@ pub enum Error {
     #[error("CAPTCHA verification failed.")]
     CaptchaFailed,
 
+    /// The CAPTCHA provider couldn't verify the CAPTCHA, such as from an outage or a misconfiguration.
+    ///
+    /// Like [`Error::Internal`], this must not expose error details to clients.
+    #[error("CAPTCHA verification is unavailable. Please try again later.")]
+    CaptchaUnavailable(#[source] Box<dyn std::error::Error>),
+
+    /// A request that could change state was sent by a browser from an origin other than the
+    /// website's. See [`csrf::protect`].
+    #[error("Requests from other websites aren't allowed.")]
+    CrossOriginRequest,
+
+    /// The custom domain was already added by the user, or another user already verified it.
+    #[error("This domain is already in use.")]
+    CustomDomainTaken,
+
+    /// Neither of the custom domain's ownership challenges was found.
+    #[error("Couldn't verify the domain. Make sure its challenge is set up, then try again.")]
+    CustomDomainVerificationFailed,
+
+    /// An email verification code specified in the request was invalidated by too many incorrect
+    /// attempts, so a new code must be generated.
+    #[error("Too many incorrect attempts. Please request a new email verification code.")]
+    EmailVerificationCodeLocked,
+
     /// An email verification code specified in the request is incorrect.
     #[error("Incorrect email verification code.")]
     EmailVerificationCodeWrong,
@@ -63,6 +98,19 @@ pub enum Error {
     #[error("Invalid JSON syntax in request body: {0}")]
     JsonSyntax(String),
 
+    /// A folder can't be moved into itself or any of its subfolders.
+    #[error("A folder can't be moved into itself.")]
+    MoveIntoSelf,
+
+    /// A file or folder with the same name already exists in the destination folder.
+    #[error("An item with the same name already exists in the destination folder.")]
+    NameTaken,
+
+    /// Too many requests were made, so the client must wait the specified duration before trying
+    /// again. See [`rate_limit::RateLimit`].
+    #[error("Too many attempts. Please wait and try again later.")]
+    RateLimited(Duration),
+
     /// The requested API route exists, but the specified resource was not found.
     #[error("Resource not found.")]
     ResourceNotFound,
@@ -71,6 +119,15 @@ pub enum Error {
     #[error("The requested API route doesn't exist.")]
     RouteNotFound,
 
+    /// The request is authenticated by an access token without the scope this requires, or the
+    /// request requires a sign-in session rather than an access token.
+    #[error("The access token isn't allowed to do this.")]
+    ScopeMissing,
+
+    /// The request requires a sign-in session or an access token, but none is valid.
+    #[error("You must be signed in.")]
+    Unauthenticated,
+
     /// Credentials specified in the request (such as email and password) don't match any user.
     #[error("The specified user credentials are incorrect.")]
     UserCredentialsWrong,
@@ -82,14 +139,24 @@ impl Error {
         match self {
             Self::BodyTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
             Self::CaptchaFailed => StatusCode::FORBIDDEN,
+            Self::CaptchaUnavailable(_) => StatusCode::BAD_GATEWAY,
+            Self::CrossOriginRequest => StatusCode::FORBIDDEN,
+            Self::CustomDomainTaken => StatusCode::CONFLICT,
+            Self::CustomDomainVerificationFailed => StatusCode::FORBIDDEN,
+            Self::EmailVerificationCodeLocked => StatusCode::FORBIDDEN,
             Self::EmailVerificationCodeWrong => StatusCode::FORBIDDEN,
             Self::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
             Self::InvalidBodyData(_) => StatusCode::BAD_REQUEST,
             Self::InvalidQueryData(_) => StatusCode::BAD_REQUEST,
             Self::JsonContentType => StatusCode::UNSUPPORTED_MEDIA_TYPE,
             Self::JsonSyntax(_) => StatusCode::BAD_REQUEST,
+            Self::MoveIntoSelf => StatusCode::CONFLICT,
+            Self::NameTaken => StatusCode::CONFLICT,
+            Self::RateLimited(_) => StatusCode::TOO_MANY_REQUESTS,
             Self::ResourceNotFound => StatusCode::NOT_FOUND,
             Self::RouteNotFound => StatusCode::NOT_FOUND,
+            Self::ScopeMissing => StatusCode::FORBIDDEN,
+            Self::Unauthenticated => StatusCode::UNAUTHORIZED,
             Self::UserCredentialsWrong => StatusCode::FORBIDDEN,
         }
     }
@@ -148,24 +215,60 @@ impl From<reqwest::Error> for Error {
 }
 
 /// An API error's response body.
-#[derive(Serialize, Debug)]
+#[derive(Serialize, ToSchema, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct ErrorBody {
     /// The computer-friendly error code in `SCREAMING_SNAKE_CASE`. See [`Error`] for error codes.
+    #[schema(schema_with = error_code_schema)]
     pub code: &'static str,
 
     /// The human-friendly error message.
     pub message: String,
 }
 
+/// Gets the OpenAPI schema for an [`Error`]'s code, enumerating every possible code.
+fn error_code_schema() -> ObjectBuilder {
+    ObjectBuilder::new()
+        .schema_type(Type::String)
+        .enum_values(Some(Error::VARIANTS.iter().copied()))
+        .description(Some(
+            "The computer-friendly error code in `SCREAMING_SNAKE_CASE`.",
+        ))
+}
+
+impl From<&Error> for ErrorBody {
+    fn from(error: &Error) -> Self {
+        Self {
+            code: error.code(),
+            message: error.to_string(),
+        }
+    }
+}
+
 impl IntoResponse for Error {
     fn into_response(self) -> axum::response::Response {
-        let body = ErrorBody {
-            code: self.code(),
-            message: self.to_string(),
-        };
+        if self.status().is_server_error() {
+            // The error's source chain is only logged, never exposed to clients.
+            let sources: Vec<String> = iter::successors(self.source(), |&error| error.source())
+                .map(ToString::to_string)
+                .collect();
 
-        (self.status(), Json(body)).into_response()
+            tracing::error!(code = self.code(), error = sources.join(": "), "{self}");
+        }
+
+        let mut response = (self.status(), Json(ErrorBody::from(&self))).into_response();
+
+        if let Self::RateLimited(retry_after) = self {
+            // Round up so clients never retry too early.
+            let retry_after_secs =
+                retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+
+            response
+                .headers_mut()
+                .insert(RETRY_AFTER, HeaderValue::from(retry_after_secs));
+        }
+
+        response
     }
 }
 
@@ -199,10 +302,96 @@ pub(super) async fn handle(
     // Calling the router needs a mutable reference to it (even though it shouldn't), so the router
     // must either have restricted access via a mutex or be cloned on each request. The former would
     // allow only one request at a time, so the latter is faster.
-    ROUTER
-        .clone()
-        .with_state(state)
+    let router = ROUTER.clone().with_state(state);
+
+    route(router, cors::Origins::from_env(), request).await
+}
+
+/// Routes a request with a router, handling CORS for the specified origins.
+async fn route(
+    router: Router,
+    origins: cors::Origins,
+    request: Request,
+) -> axum::response::Response {
+    // Unlike the router's own layers, which wrap each route separately, this wraps the entire
+    // router, so it sees the `Allow` header the router adds to `405 Method Not Allowed` responses.
+    middleware::from_fn_with_state(origins, cors::handle)
+        .layer(router)
         .oneshot(request)
         .await
         .into_response()
 }
+
+#[cfg(test)]
+#[expect(clippy::missing_errors_doc, reason = "see rust-lang/rust-clippy#13391")]
+mod tests {
+    use axum::{
+        body::Body,
+        http::{
+            header::{
+                ACCESS_CONTROL_ALLOW_METHODS, ACCESS_CONTROL_ALLOW_ORIGIN,
+                ACCESS_CONTROL_REQUEST_METHOD, ORIGIN, VARY,
+            },
+            Method,
+        },
+    };
+    use sqlx::PgPool;
+
+    use super::*;
+
+    /// The website's origin in these tests.
+    const WEBSITE_ORIGIN: &str = "https://example.com";
+
+    /// Routes a request with the API router, without a database connection.
+    async fn route_request(request: Request) -> anyhow::Result<axum::response::Response> {
+        let state = AppState {
+            db_pool: PgPool::connect_lazy("postgres://localhost/unused")?,
+        };
+        let router = routes::router(WEBSITE_ORIGIN).with_state(state);
+        let origins = cors::Origins {
+            website: WEBSITE_ORIGIN,
+            allowed: None,
+        };
+
+        Ok(route(router, origins, request).await)
+    }
+
+    #[tokio::test]
+    async fn preflight_requests_answered() -> anyhow::Result<()> {
+        let response = route_request(
+            Request::builder()
+                .method(Method::OPTIONS)
+                .uri("/api/v1/sessions")
+                .header(ORIGIN, "https://app.example")
+                .header(ACCESS_CONTROL_REQUEST_METHOD, "DELETE")
+                .body(Body::empty())?,
+        )
+        .await?;
+
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+        let headers = response.headers();
+        assert_eq!(headers[ACCESS_CONTROL_ALLOW_METHODS], "POST,DELETE");
+        assert_eq!(headers[ACCESS_CONTROL_ALLOW_ORIGIN], "*");
+        assert_eq!(headers[VARY], "Origin");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn unknown_routes_not_found() -> anyhow::Result<()> {
+        for origin in [None, Some(WEBSITE_ORIGIN)] {
+            let mut request = Request::builder().uri("/api/v1/nonexistent");
+            if let Some(origin) = origin {
+                request = request.header(ORIGIN, origin);
+            }
+
+            let response = route_request(request.body(Body::empty())?).await?;
+
+            assert_eq!(response.status(), StatusCode::NOT_FOUND, "{origin:?}");
+            assert_eq!(response.headers()[VARY], "Origin", "{origin:?}");
+        }
+
+        Ok(())
+    }
+}
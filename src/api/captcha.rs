@@ -1,34 +1,610 @@
 //! See [`verify`].
 
-use std::sync::LazyLock;
+use std::{net::IpAddr, sync::LazyLock, time::Duration};
 
-use serde_json::{json, Value};
+use serde::Deserialize;
+use uuid::Uuid;
 
-/// The mailbox automated emails are sent from.
-static SECRET_KEY: LazyLock<String> = LazyLock::new(|| {
-    dotenvy::var("TURNSTILE_SECRET_KEY")
-        .expect("environment variable `TURNSTILE_SECRET_KEY` should be a valid string")
+use crate::{api, WEBSITE_ORIGIN};
+
+/// The CAPTCHA provider, configured by the `CAPTCHA_PROVIDER` environment variable.
+static PROVIDER: LazyLock<Provider> = LazyLock::new(|| {
+    let provider = dotenvy::var("CAPTCHA_PROVIDER");
+
+    match provider.as_deref().unwrap_or("turnstile") {
+        "turnstile" => Provider::Turnstile(Remote {
+            verify_url: verify_url("https://challenges.cloudflare.com/turnstile/v0/siteverify"),
+            // `TURNSTILE_SECRET_KEY` is still read since it was used before other providers existed.
+            secret_key: dotenvy::var("TURNSTILE_SECRET_KEY").unwrap_or_else(|_| secret_key()),
+        }),
+        "hcaptcha" => Provider::HCaptcha(Remote {
+            verify_url: verify_url("https://api.hcaptcha.com/siteverify"),
+            secret_key: secret_key(),
+        }),
+        "disabled" => {
+            assert!(
+                is_local_origin(&WEBSITE_ORIGIN),
+                "`CAPTCHA_PROVIDER` should only be `disabled` when `WEBSITE_ORIGIN` is a local \
+                `http` origin",
+            );
+            Provider::Disabled
+        }
+        "fixed" => Provider::Fixed(
+            dotenvy::var("CAPTCHA_FIXED_TOKEN")
+                .expect("environment variable `CAPTCHA_FIXED_TOKEN` should be a valid string"),
+        ),
+        _ => panic!(
+            "environment variable `CAPTCHA_PROVIDER` should be `turnstile`, `hcaptcha`, `disabled`, \
+            or `fixed`"
+        ),
+    }
+});
+
+/// The HTTP client shared between all CAPTCHA verification requests. Its timeouts keep a stalled
+/// provider from holding up requests indefinitely, and let a timed out attempt be retried.
+static CLIENT: LazyLock<reqwest::Client> = LazyLock::new(|| {
+    reqwest::Client::builder()
+        .connect_timeout(CONNECT_TIMEOUT)
+        .timeout(REQUEST_TIMEOUT)
+        .build()
+        .expect("CAPTCHA client should be valid")
+});
+
+/// How long connecting to the provider can take before the attempt fails.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// How long a verification request can take in total (including connecting and reading the
+/// response) before the attempt fails.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The hostname CAPTCHAs must be solved on, which is the website's.
+static WEBSITE_HOSTNAME: LazyLock<String> = LazyLock::new(|| {
+    reqwest::Url::parse(&WEBSITE_ORIGIN)
+        .ok()
+        .and_then(|origin| origin.host_str().map(Into::into))
+        .expect("website origin should be a valid URL with a host")
 });
 
-/// Returns whether a Cloudflare Turnstile token is valid.
+/// The error codes a provider can return for tokens that simply fail verification. Any other error
+/// code means the provider couldn't verify the token, such as from a misconfigured secret key.
+const FAILURE_ERROR_CODES: [&str; 5] = [
+    "missing-input-response",
+    "invalid-input-response",
+    "timeout-or-duplicate",
+    "expired-input-response",
+    "already-seen-response",
+];
+
+/// How many times a verification request is sent before giving up if it fails to send.
+const MAX_ATTEMPTS: u32 = 2;
+
+/// A CAPTCHA provider.
+#[derive(Debug)]
+enum Provider {
+    /// Cloudflare Turnstile.
+    Turnstile(Remote),
+
+    /// hCaptcha.
+    HCaptcha(Remote),
+
+    /// Every token passes. Only for local development, so it's refused unless the website origin is
+    /// local.
+    Disabled,
+
+    /// Only the specified token passes. Only for local development and integration tests.
+    Fixed(String),
+}
+
+/// The configuration for a provider with a `siteverify` endpoint.
+#[derive(Debug)]
+struct Remote {
+    /// The URL of the provider's `siteverify` endpoint. Can be overridden by the
+    /// `CAPTCHA_VERIFY_URL` environment variable so a local mock server can stand in for the
+    /// provider.
+    verify_url: String,
+
+    /// The secret key for the provider's `siteverify` endpoint.
+    secret_key: String,
+}
+
+/// A response body from a provider's `siteverify` endpoint.
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "kebab-case")]
+struct SiteverifyResponse {
+    /// Whether the token is valid.
+    success: bool,
+
+    /// The hostname of the site the CAPTCHA was solved on.
+    hostname: Option<String>,
+
+    /// The action the CAPTCHA was solved for. Only Turnstile returns this.
+    action: Option<String>,
+
+    /// Why the token is invalid, or why verification failed.
+    #[serde(default)]
+    error_codes: Vec<String>,
+}
+
+/// Verifies a CAPTCHA token solved by the client with the specified IP address.
+///
+/// The token must have been solved on the website for the specified action, which is a unique name
+/// for the form the token was submitted from. Otherwise, a token solved for another site or form
+/// could be replayed. Only Turnstile supports actions, so the action isn't checked with hCaptcha,
+/// and an hCaptcha token solved for one form can be submitted once to any other.
+///
+/// # Errors
+///
+/// Returns [`api::Error::CaptchaFailed`] if the token is invalid, or
+/// [`api::Error::CaptchaUnavailable`] if the provider couldn't verify the token.
+pub(crate) async fn verify(token: &str, action: &str, remote_ip: IpAddr) -> Result<(), api::Error> {
+    let result = check(&PROVIDER, &WEBSITE_HOSTNAME, token, action, remote_ip).await;
+
+    let outcome = match result {
+        Ok(()) => "passed",
+        Err(api::Error::CaptchaFailed) => "failed",
+        Err(_) => "unavailable",
+    };
+
+    metrics::counter!(
+        "captcha_verifications_total",
+        "action" => action.to_owned(),
+        "outcome" => outcome,
+    )
+    .increment(1);
+
+    result
+}
+
+/// Verifies a CAPTCHA token with the specified provider without recording the outcome. The token
+/// must have been solved on the specified hostname. See [`verify`].
 ///
 /// # Errors
 ///
-/// Returns an error if the verification request fails or cannot be processed.
-pub(crate) async fn verify(token: &str) -> Result<bool, reqwest::Error> {
-    let client = reqwest::Client::new();
-
-    let outcome: Value = client
-        .post("https://challenges.cloudflare.com/turnstile/v0/siteverify")
-        .json(&json!({
-            "secret": *SECRET_KEY,
-            "response": token,
-        }))
-        .send()
-        .await?
-        .error_for_status()?
-        .json()
-        .await?;
-
-    Ok(outcome["success"] == true)
+/// See [`verify`].
+async fn check(
+    provider: &Provider,
+    website_hostname: &str,
+    token: &str,
+    action: &str,
+    remote_ip: IpAddr,
+) -> Result<(), api::Error> {
+    let (remote, check_action) = match provider {
+        Provider::Turnstile(remote) => (remote, true),
+        // hCaptcha has no actions, so only the hostname can be checked.
+        Provider::HCaptcha(remote) => (remote, false),
+        Provider::Disabled => return Ok(()),
+        Provider::Fixed(fixed_token) => {
+            return if token == fixed_token {
+                Ok(())
+            } else {
+                Err(api::Error::CaptchaFailed)
+            };
+        }
+    };
+
+    let outcome = request_siteverify(&CLIENT, remote, token, remote_ip).await?;
+
+    if !outcome.success {
+        if outcome
+            .error_codes
+            .iter()
+            .all(|code| FAILURE_ERROR_CODES.contains(&code.as_str()))
+        {
+            return Err(api::Error::CaptchaFailed);
+        }
+
+        return Err(api::Error::CaptchaUnavailable(
+            format!("error codes: {}", outcome.error_codes.join(", ")).into(),
+        ));
+    }
+
+    if outcome.hostname.as_deref() != Some(website_hostname)
+        || (check_action && outcome.action.as_deref() != Some(action))
+    {
+        return Err(api::Error::CaptchaFailed);
+    }
+
+    Ok(())
+}
+
+/// Sends a token to a provider's `siteverify` endpoint using the specified client, retrying if the
+/// request fails to send.
+///
+/// Every attempt uses the same idempotency key, so Turnstile doesn't reject a retried token as a
+/// duplicate.
+///
+/// # Errors
+///
+/// Returns [`api::Error::CaptchaUnavailable`] if the request fails.
+async fn request_siteverify(
+    client: &reqwest::Client,
+    remote: &Remote,
+    token: &str,
+    remote_ip: IpAddr,
+) -> Result<SiteverifyResponse, api::Error> {
+    let remote_ip = remote_ip.to_string();
+    let idempotency_key = Uuid::new_v4().to_string();
+
+    let mut attempt = 1;
+
+    loop {
+        let result = client
+            .post(&remote.verify_url)
+            .form(&[
+                ("secret", remote.secret_key.as_str()),
+                ("response", token),
+                ("remoteip", &remote_ip),
+                ("idempotency_key", &idempotency_key),
+            ])
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status);
+
+        let response = match result {
+            Ok(response) => response,
+            Err(error) if attempt < MAX_ATTEMPTS && (error.is_connect() || error.is_timeout()) => {
+                attempt += 1;
+                continue;
+            }
+            Err(error) => return Err(api::Error::CaptchaUnavailable(error.into())),
+        };
+
+        return response
+            .json()
+            .await
+            .map_err(|error| api::Error::CaptchaUnavailable(error.into()));
+    }
+}
+
+/// Checks if an origin is an `http` origin for `localhost` or a loopback address.
+fn is_local_origin(origin: &str) -> bool {
+    let Ok(origin) = reqwest::Url::parse(origin) else {
+        return false;
+    };
+
+    origin.scheme() == "http"
+        && origin.host_str().is_some_and(|host| {
+            host == "localhost"
+                || host
+                    .trim_start_matches('[')
+                    .trim_end_matches(']')
+                    .parse::<IpAddr>()
+                    .is_ok_and(|ip| ip.is_loopback())
+        })
+}
+
+/// Gets the `siteverify` endpoint URL from the `CAPTCHA_VERIFY_URL` environment variable, or else
+/// returns the specified default.
+fn verify_url(default: &str) -> String {
+    dotenvy::var("CAPTCHA_VERIFY_URL").unwrap_or_else(|_| default.into())
+}
+
+/// Gets the secret key from the `CAPTCHA_SECRET_KEY` environment variable.
+///
+/// # Panics
+///
+/// Panics if the environment variable isn't set.
+fn secret_key() -> String {
+    dotenvy::var("CAPTCHA_SECRET_KEY")
+        .expect("environment variable `CAPTCHA_SECRET_KEY` should be a valid string")
+}
+
+#[cfg(test)]
+#[expect(clippy::missing_errors_doc, reason = "see rust-lang/rust-clippy#13391")]
+mod tests {
+    use std::{
+        collections::HashMap,
+        future::IntoFuture,
+        net::Ipv4Addr,
+        sync::{Arc, Mutex},
+    };
+
+    use axum::{extract::State, http::StatusCode, routing::post, Form, Json, Router};
+    use serde_json::json;
+
+    use super::*;
+
+    /// The hostname CAPTCHAs must be solved on in tests.
+    const HOSTNAME: &str = "example.com";
+
+    /// The action CAPTCHAs must be solved for in tests.
+    const ACTION: &str = "sign-up";
+
+    /// The IP address of the client in tests.
+    const REMOTE_IP: IpAddr = IpAddr::V4(Ipv4Addr::LOCALHOST);
+
+    /// A mock `siteverify` endpoint.
+    #[derive(Debug, Default)]
+    struct Mock {
+        /// The response body sent for every request.
+        response: serde_json::Value,
+
+        /// How long the first request is stalled before it's answered.
+        first_delay: Duration,
+
+        /// The form bodies of the requests received so far.
+        requests: Mutex<Vec<HashMap<String, String>>>,
+    }
+
+    impl Mock {
+        /// Creates a mock that sends the specified response body for every request.
+        fn new(response: serde_json::Value) -> Arc<Self> {
+            Arc::new(Self {
+                response,
+                ..Default::default()
+            })
+        }
+
+        /// Gets the form bodies of the requests received so far.
+        fn requests(&self) -> Vec<HashMap<String, String>> {
+            self.requests
+                .lock()
+                .expect("mock requests shouldn't be poisoned")
+                .clone()
+        }
+    }
+
+    /// Handles a request to the mock `siteverify` endpoint.
+    async fn siteverify(
+        State(mock): State<Arc<Mock>>,
+        Form(form): Form<HashMap<String, String>>,
+    ) -> Json<serde_json::Value> {
+        let is_first = {
+            let mut requests = mock
+                .requests
+                .lock()
+                .expect("mock requests shouldn't be poisoned");
+            requests.push(form);
+            requests.len() == 1
+        };
+
+        if is_first {
+            tokio::time::sleep(mock.first_delay).await;
+        }
+
+        Json(mock.response.clone())
+    }
+
+    /// Serves the specified mock on a local port, and returns a provider configuration using it.
+    async fn serve(mock: Arc<Mock>) -> anyhow::Result<Remote> {
+        let listener = tokio::net::TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await?;
+        let address = listener.local_addr()?;
+
+        let router = Router::new()
+            .route("/siteverify", post(siteverify))
+            .with_state(mock);
+        tokio::spawn(axum::serve(listener, router).into_future());
+
+        Ok(Remote {
+            verify_url: format!("http://{address}/siteverify"),
+            secret_key: "secret".into(),
+        })
+    }
+
+    /// Verifies a token with the specified provider, returning the error code if it's rejected.
+    async fn check_token(provider: &Provider, token: &str) -> Result<(), &'static str> {
+        check(provider, HOSTNAME, token, ACTION, REMOTE_IP)
+            .await
+            .map_err(|error| error.code())
+    }
+
+    #[tokio::test]
+    async fn fixed_provider_only_passes_its_token() {
+        let provider = Provider::Fixed("test".into());
+
+        assert_eq!(check_token(&provider, "test").await, Ok(()));
+        assert_eq!(check_token(&provider, "other").await, Err("CAPTCHA_FAILED"),);
+        assert_eq!(check_token(&provider, "").await, Err("CAPTCHA_FAILED"));
+    }
+
+    #[tokio::test]
+    async fn disabled_provider_passes_any_token() {
+        for token in ["test", ""] {
+            assert_eq!(
+                check_token(&Provider::Disabled, token).await,
+                Ok(()),
+                "{token:?}",
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn siteverify_outcomes_mapped() -> anyhow::Result<()> {
+        // Each case is a response body from the provider, and the expected result.
+        let cases = [
+            (
+                json!({ "success": true, "hostname": HOSTNAME, "action": ACTION }),
+                Ok(()),
+            ),
+            (
+                json!({ "success": false, "error-codes": ["invalid-input-response"] }),
+                Err("CAPTCHA_FAILED"),
+            ),
+        ];
+
+        for case in cases {
+            let (response, expected) = &case;
+
+            let mock = Mock::new(response.clone());
+            let provider = Provider::Turnstile(serve(Arc::clone(&mock)).await?);
+
+            assert_eq!(check_token(&provider, "token").await, *expected, "{case:?}");
+
+            let requests = mock.requests();
+            assert_eq!(requests.len(), 1, "{case:?}");
+            assert_eq!(requests[0]["secret"], "secret", "{case:?}");
+            assert_eq!(requests[0]["response"], "token", "{case:?}");
+            assert_eq!(requests[0]["remoteip"], "127.0.0.1", "{case:?}");
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn mismatched_hostnames_and_actions_rejected() -> anyhow::Result<()> {
+        /// Creates a provider from its configuration.
+        type NewProvider = fn(Remote) -> Provider;
+
+        // Each case is a provider, a response body from it, and the expected result.
+        let cases: [(NewProvider, _, _); 8] = [
+            (
+                Provider::Turnstile,
+                json!({ "success": true, "hostname": "other.example", "action": ACTION }),
+                Err("CAPTCHA_FAILED"),
+            ),
+            (
+                Provider::Turnstile,
+                json!({ "success": true, "action": ACTION }),
+                Err("CAPTCHA_FAILED"),
+            ),
+            (
+                Provider::Turnstile,
+                json!({ "success": true, "hostname": HOSTNAME, "action": "sign-in" }),
+                Err("CAPTCHA_FAILED"),
+            ),
+            (
+                Provider::Turnstile,
+                json!({ "success": true, "hostname": HOSTNAME }),
+                Err("CAPTCHA_FAILED"),
+            ),
+            (
+                Provider::HCaptcha,
+                json!({ "success": true, "hostname": "other.example" }),
+                Err("CAPTCHA_FAILED"),
+            ),
+            (
+                Provider::HCaptcha,
+                json!({ "success": true, "hostname": HOSTNAME }),
+                Ok(()),
+            ),
+            (
+                Provider::HCaptcha,
+                json!({ "success": true, "hostname": HOSTNAME, "action": "sign-in" }),
+                Ok(()),
+            ),
+            (
+                Provider::HCaptcha,
+                json!({ "success": true, "hostname": HOSTNAME, "action": ACTION }),
+                Ok(()),
+            ),
+        ];
+
+        for case in cases {
+            let (new_provider, response, expected) = &case;
+
+            let provider = new_provider(serve(Mock::new(response.clone())).await?);
+
+            assert_eq!(check_token(&provider, "token").await, *expected, "{case:?}");
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn failures_distinguished_from_unavailability() -> anyhow::Result<()> {
+        // Each case is the error codes of an unsuccessful response, and the expected error code and
+        // status.
+        let cases = [
+            (vec![], ("CAPTCHA_FAILED", StatusCode::FORBIDDEN)),
+            (
+                vec!["invalid-input-response"],
+                ("CAPTCHA_FAILED", StatusCode::FORBIDDEN),
+            ),
+            (
+                vec!["timeout-or-duplicate", "expired-input-response"],
+                ("CAPTCHA_FAILED", StatusCode::FORBIDDEN),
+            ),
+            (
+                vec!["invalid-input-secret"],
+                ("CAPTCHA_UNAVAILABLE", StatusCode::BAD_GATEWAY),
+            ),
+            (
+                vec!["invalid-input-response", "internal-error"],
+                ("CAPTCHA_UNAVAILABLE", StatusCode::BAD_GATEWAY),
+            ),
+        ];
+
+        for case in cases {
+            let (error_codes, expected) = &case;
+
+            let mock = Mock::new(json!({ "success": false, "error-codes": error_codes }));
+            let provider = Provider::Turnstile(serve(mock).await?);
+
+            let result = check(&provider, HOSTNAME, "token", ACTION, REMOTE_IP)
+                .await
+                .map_err(|error| (error.code(), error.status()));
+
+            assert_eq!(result, Err(*expected), "{case:?}");
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn timed_out_requests_retried_with_same_idempotency_key() -> anyhow::Result<()> {
+        let timeout = Duration::from_millis(200);
+
+        let mock = Arc::new(Mock {
+            response: json!({ "success": true, "hostname": HOSTNAME, "action": ACTION }),
+            first_delay: timeout * 5,
+            ..Default::default()
+        });
+        let remote = serve(Arc::clone(&mock)).await?;
+        let client = reqwest::Client::builder().timeout(timeout).build()?;
+
+        let outcome = request_siteverify(&client, &remote, "token", REMOTE_IP)
+            .await
+            .map_err(|error| anyhow::anyhow!("{error:?}"))?;
+
+        assert!(outcome.success, "retried request should succeed");
+
+        let requests = mock.requests();
+        assert_eq!(requests.len(), 2, "request should be retried once");
+        assert_eq!(
+            requests[0]["idempotency_key"], requests[1]["idempotency_key"],
+            "retried request should reuse the idempotency key",
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn unreachable_provider_unavailable() -> anyhow::Result<()> {
+        // Bind and then drop a listener to find a local port nothing is listening on.
+        let address = tokio::net::TcpListener::bind((Ipv4Addr::LOCALHOST, 0))
+            .await?
+            .local_addr()?;
+
+        let provider = Provider::Turnstile(Remote {
+            verify_url: format!("http://{address}/siteverify"),
+            secret_key: "secret".into(),
+        });
+
+        assert_eq!(
+            check_token(&provider, "token").await,
+            Err("CAPTCHA_UNAVAILABLE"),
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn local_origins_detected() {
+        // Each case is an origin, and whether it's local.
+        let cases = [
+            ("http://localhost", true),
+            ("http://localhost:3000", true),
+            ("http://127.0.0.1:3000", true),
+            ("http://[::1]:3000", true),
+            ("https://localhost", false),
+            ("http://localhost.example.com", false),
+            ("http://example.com", false),
+            ("http://10.0.0.1", false),
+            ("not a URL", false),
+        ];
+
+        for case @ (origin, expected) in cases {
+            assert_eq!(is_local_origin(origin), expected, "{case:?}");
+        }
+    }
 }
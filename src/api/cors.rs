@@ -0,0 +1,237 @@
+//! Cross-origin resource sharing (CORS), letting browsers call the API from other origins. See
+//! [`handle`].
+
+use std::sync::LazyLock;
+
+use axum::{
+    extract::{Request, State},
+    http::{
+        header::{
+            ACCESS_CONTROL_ALLOW_CREDENTIALS, ACCESS_CONTROL_ALLOW_HEADERS,
+            ACCESS_CONTROL_ALLOW_METHODS, ACCESS_CONTROL_ALLOW_ORIGIN,
+            ACCESS_CONTROL_EXPOSE_HEADERS, ACCESS_CONTROL_MAX_AGE, ACCESS_CONTROL_REQUEST_METHOD,
+            ALLOW, ORIGIN, VARY,
+        },
+        HeaderValue, Method, StatusCode,
+    },
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+use crate::WEBSITE_ORIGIN;
+
+/// The origins other than the website's that browsers can call the API from, configured by the
+/// comma-separated `CORS_ALLOWED_ORIGINS` environment variable, or `None` if any origin can. Any
+/// origin can by default or if the variable is `*`.
+///
+/// Only the website's origin can send credentials (the session cookie), so other origins can only
+/// authenticate with access tokens.
+static ALLOWED_ORIGINS: LazyLock<Option<Vec<String>>> = LazyLock::new(|| {
+    let Ok(allowed_origins) = dotenvy::var("CORS_ALLOWED_ORIGINS") else {
+        return None;
+    };
+
+    if allowed_origins.trim() == "*" {
+        return None;
+    }
+
+    Some(
+        allowed_origins
+            .split(',')
+            .map(|origin| origin.trim().trim_end_matches('/'))
+            .filter(|origin| !origin.is_empty())
+            .map(str::to_owned)
+            .collect(),
+    )
+});
+
+/// The request headers browsers are allowed to send from other origins, besides the ones they can
+/// always send.
+const ALLOWED_HEADERS: &str = "Authorization, Content-Type";
+
+/// The response headers browsers let other origins read, besides the ones they always can.
+const EXPOSED_HEADERS: &str = "Retry-After, X-Request-ID";
+
+/// How many seconds browsers can cache a preflight response for.
+const MAX_AGE: &str = "7200";
+
+/// The origins browsers can call the API from.
+#[derive(Clone, Copy, Debug)]
+pub(super) struct Origins {
+    /// The website's origin, which is the only one that can send credentials.
+    pub(super) website: &'static str,
+
+    /// The other allowed origins, or `None` if any origin is allowed.
+    pub(super) allowed: Option<&'static [String]>,
+}
+
+impl Origins {
+    /// Gets the origins configured by the environment. See [`ALLOWED_ORIGINS`].
+    pub(super) fn from_env() -> Self {
+        Self {
+            website: &WEBSITE_ORIGIN,
+            allowed: ALLOWED_ORIGINS.as_deref(),
+        }
+    }
+}
+
+/// How an origin is allowed to call the API.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Policy {
+    /// The origin can send credentials. Only the website's origin has this policy.
+    Credentialed,
+
+    /// The origin can't send credentials, so it can only authenticate with access tokens.
+    Public,
+}
+
+/// Adds CORS headers to API responses for requests from allowed origins, and answers preflight
+/// requests for every API route.
+///
+/// Preflight requests are passed to the router like any other `OPTIONS` request. No route handles
+/// `OPTIONS`, so the router responds to it with `405 Method Not Allowed` and an `Allow` header
+/// listing the methods the route does handle, which is what the preflight response allows. This
+/// keeps preflight responses in sync with the router's routes, and nonexistent routes still fail
+/// with [`crate::api::Error::RouteNotFound`].
+pub(super) async fn handle(
+    State(origins): State<Origins>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let origin = request.headers().get(ORIGIN).cloned();
+
+    let is_preflight = request.method() == Method::OPTIONS
+        && request
+            .headers()
+            .contains_key(ACCESS_CONTROL_REQUEST_METHOD);
+
+    let mut response = next.run(request).await;
+
+    let policy = origin
+        .as_ref()
+        .and_then(|origin| policy(origin, origins.website, origins.allowed));
+
+    if let (Some(origin), Some(policy)) = (origin, policy) {
+        if is_preflight && response.status() == StatusCode::METHOD_NOT_ALLOWED {
+            let allowed_methods = response.headers().get(ALLOW).cloned();
+
+            response = StatusCode::NO_CONTENT.into_response();
+
+            let headers = response.headers_mut();
+
+            if let Some(allowed_methods) = allowed_methods {
+                headers.insert(ACCESS_CONTROL_ALLOW_METHODS, allowed_methods);
+            }
+            headers.insert(
+                ACCESS_CONTROL_ALLOW_HEADERS,
+                HeaderValue::from_static(ALLOWED_HEADERS),
+            );
+            headers.insert(ACCESS_CONTROL_MAX_AGE, HeaderValue::from_static(MAX_AGE));
+        } else {
+            response.headers_mut().insert(
+                ACCESS_CONTROL_EXPOSE_HEADERS,
+                HeaderValue::from_static(EXPOSED_HEADERS),
+            );
+        }
+
+        let headers = response.headers_mut();
+
+        match policy {
+            Policy::Credentialed => {
+                headers.insert(ACCESS_CONTROL_ALLOW_ORIGIN, origin);
+                headers.insert(
+                    ACCESS_CONTROL_ALLOW_CREDENTIALS,
+                    HeaderValue::from_static("true"),
+                );
+            }
+            Policy::Public => {
+                // A wildcard is only possible if any origin is allowed. Browsers never send
+                // credentials to it.
+                let allowed_origin = if origins.allowed.is_some() {
+                    origin
+                } else {
+                    HeaderValue::from_static("*")
+                };
+
+                headers.insert(ACCESS_CONTROL_ALLOW_ORIGIN, allowed_origin);
+            }
+        }
+    }
+
+    // Which headers are set depends on the origin (or its absence), so caches mustn't reuse this
+    // response for other origins.
+    response
+        .headers_mut()
+        .append(VARY, HeaderValue::from_static("Origin"));
+
+    response
+}
+
+/// Gets how a request's `Origin` header value is allowed to call the API, given the website's
+/// origin and the other allowed origins (or `None` if any origin is allowed). Returns `None` if the
+/// origin isn't allowed.
+fn policy(
+    origin: &HeaderValue,
+    website_origin: &str,
+    allowed_origins: Option<&[String]>,
+) -> Option<Policy> {
+    if origin == website_origin {
+        return Some(Policy::Credentialed);
+    }
+
+    match allowed_origins {
+        None => Some(Policy::Public),
+        Some(allowed_origins) => allowed_origins
+            .iter()
+            .any(|allowed_origin| origin == allowed_origin.as_str())
+            .then_some(Policy::Public),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_website_origin_credentialed() {
+        let website_origin = "https://example.com";
+        let allowed_origins = ["https://app.example".to_owned()];
+
+        // Each case is an origin, its policy when any origin is allowed, and its policy when only
+        // `allowed_origins` are.
+        let cases = [
+            (
+                website_origin,
+                Some(Policy::Credentialed),
+                Some(Policy::Credentialed),
+            ),
+            (
+                "https://app.example",
+                Some(Policy::Public),
+                Some(Policy::Public),
+            ),
+            ("https://evil.example", Some(Policy::Public), None),
+            (
+                "https://example.com.evil.example",
+                Some(Policy::Public),
+                None,
+            ),
+            ("null", Some(Policy::Public), None),
+        ];
+
+        for (origin, any_policy, restricted_policy) in cases {
+            let origin = HeaderValue::from_static(origin);
+
+            assert_eq!(
+                policy(&origin, website_origin, None),
+                any_policy,
+                "{origin:?}"
+            );
+            assert_eq!(
+                policy(&origin, website_origin, Some(&allowed_origins)),
+                restricted_policy,
+                "{origin:?}",
+            );
+        }
+    }
+}
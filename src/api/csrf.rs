@@ -0,0 +1,135 @@
+//! Protection against cross-site request forgery (CSRF). See [`protect`].
+
+use axum::{
+    extract::{Request, State},
+    http::{
+        header::{AUTHORIZATION, ORIGIN},
+        HeaderMap, HeaderName, Method,
+    },
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+use crate::api::{self, session::bearer_token};
+
+/// The request header browsers set to the relationship between the request's origin and the origin
+/// it's sent to.
+static SEC_FETCH_SITE: HeaderName = HeaderName::from_static("sec-fetch-site");
+
+/// Rejects API requests that could change state with [`api::Error::CrossOriginRequest`] if a
+/// browser sent them from any origin but the website's, which is this middleware's state.
+///
+/// The session cookie alone (despite being `SameSite=Lax`) doesn't stop forged requests from other
+/// origins on the same site, such as user content served from a subdomain.
+pub(super) async fn protect(
+    State(website_origin): State<&'static str>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if !is_allowed(request.method(), request.headers(), website_origin) {
+        return api::Error::CrossOriginRequest.into_response();
+    }
+
+    next.run(request).await
+}
+
+/// Checks whether a request is safe from CSRF, given the website's origin.
+fn is_allowed(method: &Method, headers: &HeaderMap, website_origin: &str) -> bool {
+    // Safe methods can't change state.
+    if method.is_safe() {
+        return true;
+    }
+
+    // Browsers never set this header to an access token on their own, and the session extractors
+    // ignore cookies on requests with it, so these requests can only be authenticated by an access
+    // token. Other values aren't exempt, since nothing authenticates requests by them.
+    if headers
+        .get(AUTHORIZATION)
+        .is_some_and(|authorization| bearer_token(authorization).is_some())
+    {
+        return true;
+    }
+
+    // Every modern browser sets this header, so it's preferred. `none` means the user initiated the
+    // request directly rather than another site.
+    if let Some(site) = headers.get(&SEC_FETCH_SITE) {
+        return site == "same-origin" || site == "none";
+    }
+
+    // Older browsers set this header on cross-origin requests instead.
+    match headers.get(ORIGIN) {
+        Some(origin) => origin == website_origin,
+
+        // Without either header, the request wasn't sent by a browser, so it can't be forged by one.
+        None => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::HeaderValue;
+
+    use super::*;
+    use crate::id::Token;
+
+    #[test]
+    fn cross_origin_requests_rejected() {
+        let website_origin = "https://example.com";
+        let bearer_authorization = HeaderValue::try_from(format!("Bearer {}", Token::generate()))
+            .expect("test header should be valid");
+
+        // Each case is a method, `Sec-Fetch-Site` header, `Origin` header, and whether it's allowed.
+        let cases = [
+            (Method::GET, Some("cross-site"), None, true),
+            (Method::POST, None, None, true),
+            (Method::POST, Some("same-origin"), None, true),
+            (Method::POST, Some("none"), None, true),
+            (Method::POST, Some("same-site"), None, false),
+            (Method::DELETE, Some("cross-site"), None, false),
+            (
+                Method::PATCH,
+                Some("cross-site"),
+                Some(website_origin),
+                false,
+            ),
+            (Method::POST, None, Some(website_origin), true),
+            (Method::POST, None, Some("https://evil.example"), false),
+            (Method::POST, None, Some("null"), false),
+        ];
+
+        for (method, site, origin, allowed) in cases {
+            let mut headers = HeaderMap::new();
+
+            if let Some(site) = site {
+                headers.insert(&SEC_FETCH_SITE, HeaderValue::from_static(site));
+            }
+            if let Some(origin) = origin {
+                headers.insert(ORIGIN, HeaderValue::from_static(origin));
+            }
+
+            assert_eq!(
+                is_allowed(&method, &headers, website_origin),
+                allowed,
+                "{method} with {headers:?}",
+            );
+
+            // Only access tokens exempt requests.
+            for authorization in ["Bearer token", "Basic dXNlcjpwYXNzd29yZA==", ""] {
+                headers.insert(AUTHORIZATION, HeaderValue::from_static(authorization));
+
+                assert_eq!(
+                    is_allowed(&method, &headers, website_origin),
+                    allowed,
+                    "{method} with {headers:?}",
+                );
+            }
+
+            headers.insert(AUTHORIZATION, bearer_authorization.clone());
+
+            assert!(
+                is_allowed(&method, &headers, website_origin),
+                "{method} with {headers:?}",
+            );
+        }
+    }
+}
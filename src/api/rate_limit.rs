@@ -0,0 +1,368 @@
+//! Token bucket rate limiting for abusable API routes. See [`RateLimit`].
+
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    net::IpAddr,
+    sync::{LazyLock, Mutex},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    api,
+    db::{self, TxResult},
+    AppState,
+};
+
+/// Where rate limit buckets are stored, configured by the `RATE_LIMIT_STORE` environment variable.
+static STORE: LazyLock<Store> =
+    LazyLock::new(|| match dotenvy::var("RATE_LIMIT_STORE").as_deref() {
+        Err(_) | Ok("memory") => Store::Memory(Mutex::new(MemoryBuckets::new(Instant::now()))),
+        Ok("postgres") => Store::Postgres,
+        Ok(_) => panic!("environment variable `RATE_LIMIT_STORE` should be `memory` or `postgres`"),
+    });
+
+/// How often full buckets are pruned from memory. Pruning scans every bucket, so it's done
+/// periodically rather than on every check.
+const MEMORY_PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// The chance (out of this number) of pruning full buckets from the database on each check.
+const POSTGRES_PRUNE_CHANCE: u32 = 100;
+
+/// A storage backend for rate limit buckets.
+#[derive(Debug)]
+enum Store {
+    /// Buckets are kept in this server instance's memory. This is fastest, but each server
+    /// instance has its own separate limits.
+    Memory(Mutex<MemoryBuckets>),
+
+    /// Buckets are kept in the database so all server instances share the same limits.
+    Postgres,
+}
+
+/// The rate limit buckets stored in memory.
+#[derive(Debug)]
+struct MemoryBuckets {
+    /// The buckets by key.
+    buckets: HashMap<String, MemoryBucket>,
+
+    /// When full buckets were last pruned.
+    pruned_at: Instant,
+}
+
+impl MemoryBuckets {
+    /// Constructs an empty set of buckets, considered last pruned at the specified time.
+    fn new(now: Instant) -> Self {
+        Self {
+            buckets: HashMap::new(),
+            pruned_at: now,
+        }
+    }
+
+    /// Removes full buckets, which are equivalent to having no bucket, if they weren't already
+    /// pruned within [`MEMORY_PRUNE_INTERVAL`].
+    fn prune(&mut self, now: Instant) {
+        if now - self.pruned_at < MEMORY_PRUNE_INTERVAL {
+            return;
+        }
+
+        self.buckets.retain(|_, bucket| bucket.full_at > now);
+        self.pruned_at = now;
+    }
+}
+
+/// A rate limit bucket stored in memory.
+#[derive(Debug)]
+struct MemoryBucket {
+    /// The number of tokens in the bucket as of `updated_at`.
+    tokens: f64,
+
+    /// When the bucket was last updated.
+    updated_at: Instant,
+
+    /// When the bucket will be full again, at which point it's equivalent to having no bucket.
+    full_at: Instant,
+}
+
+/// A token bucket rate limit. Each request to a limited route takes a token from the bucket of the
+/// subject it's limited by (such as the client's IP address or the targeted email address), and is
+/// rejected with [`api::Error::RateLimited`] if the bucket is empty. Buckets refill over time.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct RateLimit {
+    /// A unique name for the limit, so different limits' buckets for the same subject are separate.
+    name: &'static str,
+
+    /// The maximum number of tokens a bucket can hold, so the number of requests allowed in a burst.
+    capacity: u32,
+
+    /// How long it takes a bucket to regain one token.
+    refill_interval: Duration,
+}
+
+impl RateLimit {
+    /// Limits sign-in attempts per client IP address.
+    pub(crate) const SIGN_IN_PER_IP: Self = Self::new("sign_in_ip", 20, 30);
+
+    /// Limits sign-in attempts per email address.
+    pub(crate) const SIGN_IN_PER_EMAIL: Self = Self::new("sign_in_email", 10, 60);
+
+    /// Limits sending emails (such as for verification or password resets) per client IP address.
+    pub(crate) const EMAIL_PER_IP: Self = Self::new("email_ip", 10, 60);
+
+    /// Limits sending emails (such as for verification or password resets) per recipient.
+    pub(crate) const EMAIL_PER_RECIPIENT: Self = Self::new("email_recipient", 3, 300);
+
+    /// Limits attempts to enter email verification codes per client IP address.
+    pub(crate) const CODE_PER_IP: Self = Self::new("code_ip", 20, 30);
+
+    /// Limits attempts to enter email verification codes per email address.
+    pub(crate) const CODE_PER_EMAIL: Self = Self::new("code_email", 10, 60);
+
+    /// Constructs a [`RateLimit`] with a refill interval in seconds.
+    const fn new(name: &'static str, capacity: u32, refill_interval_secs: u64) -> Self {
+        Self {
+            name,
+            capacity,
+            refill_interval: Duration::from_secs(refill_interval_secs),
+        }
+    }
+
+    /// Takes a token from the specified subject's bucket.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`api::Error::RateLimited`] if the bucket is empty, or another error if the bucket
+    /// can't be accessed.
+    pub(crate) async fn check(
+        &self,
+        state: &AppState,
+        subject: impl Display,
+    ) -> Result<(), api::Error> {
+        let key = format!("{}:{subject}", self.name);
+
+        let result = match &*STORE {
+            Store::Memory(buckets) => self.check_memory(
+                &mut buckets
+                    .lock()
+                    .expect("rate limit buckets shouldn't be poisoned"),
+                key,
+                Instant::now(),
+            ),
+            Store::Postgres => self.check_postgres(state, key).await?,
+        };
+
+        result.map_err(api::Error::RateLimited)
+    }
+
+    /// Takes a token from the bucket with the specified key in memory at the specified time.
+    ///
+    /// # Errors
+    ///
+    /// Returns how long until a token is available if the bucket is empty.
+    fn check_memory(
+        &self,
+        buckets: &mut MemoryBuckets,
+        key: String,
+        now: Instant,
+    ) -> Result<(), Duration> {
+        buckets.prune(now);
+
+        let (tokens, elapsed) = buckets
+            .buckets
+            .get(&key)
+            .map_or((self.capacity.into(), Duration::ZERO), |bucket| {
+                (bucket.tokens, now - bucket.updated_at)
+            });
+
+        let tokens = self.take(tokens, elapsed)?;
+
+        buckets.buckets.insert(
+            key,
+            MemoryBucket {
+                tokens,
+                updated_at: now,
+                full_at: now + self.time_until_full(tokens),
+            },
+        );
+
+        Ok(())
+    }
+
+    /// Takes a token from the bucket with the specified key in the database. The inner result is
+    /// an error of how long until a token is available if the bucket is empty.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a database query fails.
+    async fn check_postgres(
+        &self,
+        state: &AppState,
+        key: String,
+    ) -> Result<Result<(), Duration>, sqlx::Error> {
+        db::transaction!(state.db_pool, async |tx| -> TxResult<_, sqlx::Error> {
+            if rand::random_ratio(1, POSTGRES_PRUNE_CHANCE) {
+                sqlx::query!(
+                    "DELETE FROM rate_limit_buckets
+                        WHERE full_at <= now()",
+                )
+                .execute(tx.as_mut())
+                .await?;
+            }
+
+            let bucket = sqlx::query!(
+                r#"SELECT tokens, EXTRACT(EPOCH FROM now() - updated_at)::float8 as "elapsed_secs!"
+                    FROM rate_limit_buckets
+                    WHERE key = $1"#,
+                key,
+            )
+            .fetch_optional(tx.as_mut())
+            .await?;
+
+            let (tokens, elapsed) =
+                bucket.map_or((self.capacity.into(), Duration::ZERO), |bucket| {
+                    (
+                        bucket.tokens,
+                        Duration::from_secs_f64(bucket.elapsed_secs.max(0.0)),
+                    )
+                });
+
+            let tokens = match self.take(tokens, elapsed) {
+                Ok(tokens) => tokens,
+                Err(retry_after) => return Ok(Err(retry_after)),
+            };
+
+            sqlx::query!(
+                "INSERT INTO rate_limit_buckets (key, tokens, updated_at, full_at)
+                    VALUES ($1, $2, now(), now() + make_interval(secs => $3))
+                    ON CONFLICT (key) DO UPDATE
+                        SET tokens = excluded.tokens,
+                            updated_at = excluded.updated_at,
+                            full_at = excluded.full_at",
+                key,
+                tokens,
+                self.time_until_full(tokens).as_secs_f64(),
+            )
+            .execute(tx.as_mut())
+            .await?;
+
+            Ok(Ok(()))
+        })
+        .await
+    }
+
+    /// Takes a token from a bucket that had the specified number of tokens the specified duration
+    /// ago, returning the bucket's new number of tokens.
+    ///
+    /// # Errors
+    ///
+    /// Returns how long until a token is available if the bucket is empty.
+    fn take(&self, tokens: f64, elapsed: Duration) -> Result<f64, Duration> {
+        let refilled = elapsed.as_secs_f64() / self.refill_interval.as_secs_f64();
+        let tokens = (tokens + refilled).min(self.capacity.into());
+
+        if tokens < 1.0 {
+            return Err(self.refill_interval.mul_f64(1.0 - tokens));
+        }
+
+        Ok(tokens - 1.0)
+    }
+
+    /// Returns how long a bucket with the specified number of tokens takes to refill completely.
+    fn time_until_full(&self, tokens: f64) -> Duration {
+        self.refill_interval
+            .mul_f64((f64::from(self.capacity) - tokens).max(0.0))
+    }
+}
+
+/// Returns the rate limit subject for a client IP address.
+///
+/// IPv6 addresses are grouped by their /64 prefix, since that's the smallest block typically
+/// assigned to a single client.
+pub(crate) fn ip_subject(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V4(_) => ip,
+        IpAddr::V6(ipv6) => match ipv6.to_ipv4_mapped() {
+            Some(ipv4) => ipv4.into(),
+            None => IpAddr::V6((ipv6.to_bits() & !u128::from(u64::MAX)).into()),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    use super::*;
+
+    /// A limit allowing bursts of 2 requests, regaining a token every 10 seconds.
+    const LIMIT: RateLimit = RateLimit::new("test", 2, 10);
+
+    #[test]
+    fn tokens_taken_and_refilled() {
+        let secs = Duration::from_secs;
+
+        assert_eq!(LIMIT.take(2.0, Duration::ZERO), Ok(1.0));
+        assert_eq!(LIMIT.take(1.0, Duration::ZERO), Ok(0.0));
+        assert_eq!(LIMIT.take(0.0, Duration::ZERO), Err(secs(10)));
+        assert_eq!(LIMIT.take(0.0, secs(5)), Err(secs(5)));
+        assert_eq!(LIMIT.take(0.0, secs(10)), Ok(0.0));
+        assert_eq!(LIMIT.take(0.5, secs(5)), Ok(0.0));
+
+        // Buckets never refill past their capacity.
+        assert_eq!(LIMIT.take(0.0, secs(1000)), Ok(1.0));
+
+        assert_eq!(LIMIT.time_until_full(0.0), secs(20));
+        assert_eq!(LIMIT.time_until_full(2.0), Duration::ZERO);
+    }
+
+    #[test]
+    fn memory_buckets_limited_and_pruned() {
+        let start = Instant::now();
+        let mut buckets = MemoryBuckets::new(start);
+
+        assert_eq!(LIMIT.check_memory(&mut buckets, "a".into(), start), Ok(()));
+        assert_eq!(LIMIT.check_memory(&mut buckets, "a".into(), start), Ok(()));
+        assert_eq!(
+            LIMIT.check_memory(&mut buckets, "a".into(), start),
+            Err(Duration::from_secs(10)),
+        );
+
+        // Other subjects have their own buckets.
+        assert_eq!(LIMIT.check_memory(&mut buckets, "b".into(), start), Ok(()));
+        assert_eq!(buckets.buckets.len(), 2);
+
+        // Both buckets are full again by now, but they aren't pruned until the interval passes.
+        let later = start + Duration::from_secs(30);
+        buckets.prune(later);
+        assert_eq!(buckets.buckets.len(), 2);
+
+        let later = start + MEMORY_PRUNE_INTERVAL;
+        assert_eq!(LIMIT.check_memory(&mut buckets, "c".into(), later), Ok(()));
+        assert_eq!(buckets.buckets.keys().collect::<Vec<_>>(), ["c"]);
+    }
+
+    #[test]
+    fn ipv6_subjects_grouped_by_prefix() {
+        let ipv4 = IpAddr::V4(Ipv4Addr::new(203, 0, 113, 7));
+        assert_eq!(ip_subject(ipv4), ipv4);
+
+        let ipv4_mapped = IpAddr::V6(Ipv4Addr::new(203, 0, 113, 7).to_ipv6_mapped());
+        assert_eq!(ip_subject(ipv4_mapped), ipv4);
+
+        let ipv6 = IpAddr::V6(Ipv6Addr::new(0x2001, 0xdb8, 1, 2, 3, 4, 5, 6));
+        assert_eq!(
+            ip_subject(ipv6),
+            IpAddr::V6(Ipv6Addr::new(0x2001, 0xdb8, 1, 2, 0, 0, 0, 0)),
+        );
+        assert_eq!(
+            ip_subject(IpAddr::V6(Ipv6Addr::new(
+                0x2001, 0xdb8, 1, 2, 0xff, 0, 0, 1
+            ))),
+            ip_subject(ipv6),
+        );
+        assert_ne!(
+            ip_subject(IpAddr::V6(Ipv6Addr::new(0x2001, 0xdb8, 1, 3, 3, 4, 5, 6))),
+            ip_subject(ipv6),
+        );
+    }
+}
@@ -3,25 +3,57 @@
 use std::sync::LazyLock;
 
 use axum::{
+    extract::MatchedPath,
+    middleware,
+    response::Response,
     routing::{get, post},
     Router,
 };
 use tower_cookies::CookieManagerLayer;
 
-use crate::{api, AppState};
+use crate::{
+    api::{self, csrf},
+    AppState, WEBSITE_ORIGIN,
+};
 
 pub mod v1 {
     //! The routes for version 1 of the HTTP API.
 
+    pub mod access_tokens;
+    pub mod batch;
+    pub mod custom_domains;
     pub mod email_verification;
+    pub mod folder_settings;
+    pub mod openapi;
     pub mod password_reset;
     pub mod sessions;
+    pub mod user_settings;
     pub mod users;
 }
 
 /// The API router.
-pub(super) static ROUTER: LazyLock<Router<AppState>> = LazyLock::new(|| {
+pub(super) static ROUTER: LazyLock<Router<AppState>> = LazyLock::new(|| router(&WEBSITE_ORIGIN));
+
+/// Builds the API router, given the website's origin.
+pub(super) fn router(website_origin: &'static str) -> Router<AppState> {
     Router::new()
+        .route(
+            "/api/v1/access-tokens",
+            get(v1::access_tokens::get)
+                .post(v1::access_tokens::post)
+                .delete(v1::access_tokens::delete),
+        )
+        .route("/api/v1/batch", post(v1::batch::post))
+        .route(
+            "/api/v1/custom-domains",
+            get(v1::custom_domains::get)
+                .post(v1::custom_domains::post)
+                .delete(v1::custom_domains::delete),
+        )
+        .route(
+            "/api/v1/custom-domains/verification",
+            post(v1::custom_domains::verification::post),
+        )
         .route(
             "/api/v1/email-verification",
             get(v1::email_verification::get).post(v1::email_verification::post),
@@ -30,6 +62,11 @@ pub(super) static ROUTER: LazyLock<Router<AppState>> = LazyLock::new(|| {
             "/api/v1/email-verification/code",
             post(v1::email_verification::code::post),
         )
+        .route(
+            "/api/v1/folder-settings",
+            get(v1::folder_settings::get).patch(v1::folder_settings::patch),
+        )
+        .route("/api/v1/openapi.json", get(v1::openapi::get))
         .route(
             "/api/v1/password-reset",
             get(v1::password_reset::get).post(v1::password_reset::post),
@@ -38,8 +75,27 @@ pub(super) static ROUTER: LazyLock<Router<AppState>> = LazyLock::new(|| {
             "/api/v1/password-reset/password",
             post(v1::password_reset::password::post),
         )
-        .route("/api/v1/sessions", post(v1::sessions::post))
+        .route(
+            "/api/v1/sessions",
+            post(v1::sessions::post).delete(v1::sessions::delete),
+        )
+        .route(
+            "/api/v1/user-settings",
+            get(v1::user_settings::get).patch(v1::user_settings::patch),
+        )
         .route("/api/v1/users", post(v1::users::post))
+        .route_layer(middleware::map_response(expose_matched_path))
         .fallback(|| async { api::Error::RouteNotFound })
         .layer(CookieManagerLayer::new())
-});
+        .layer(middleware::from_fn_with_state(
+            website_origin,
+            csrf::protect,
+        ))
+}
+
+/// Copies the path of the route that matched the request into the response's extensions, so the
+/// route can be labeled in request metrics outside the router.
+async fn expose_matched_path(matched_path: MatchedPath, mut response: Response) -> Response {
+    response.extensions_mut().insert(matched_path);
+    response
+}
@@ -0,0 +1,286 @@
+//! The set of the signed-in user's access tokens, which authenticate scripts and other programs
+//! through the `Authorization` header rather than a sign-in session.
+
+use axum::{extract::State, http::StatusCode};
+use axum_macros::debug_handler;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::Acquire;
+use utoipa::{IntoParams, ToSchema};
+
+use crate::{
+    api::{
+        self,
+        session::{Scope, Session},
+        validation::AccessTokenName,
+        Json, Query, Response,
+    },
+    crypto::hash_without_salt,
+    db::{self, TxError, TxResult},
+    id::{Id, NewAccessTokenId, Token},
+    AppState,
+};
+
+/// Lists the signed-in user's access tokens. Access tokens can't manage access tokens, so this
+/// requires a sign-in session.
+///
+/// # Errors
+///
+/// See [`crate::api::Error`].
+#[utoipa::path(
+    get,
+    path = "/api/v1/access-tokens",
+    responses((status = OK, description = "The user's access tokens.", body = GetResponse)),
+    security(("session" = [])),
+)]
+#[debug_handler]
+pub async fn get(State(state): State<AppState>, session: Session) -> Response<GetResponse> {
+    let tokens = db::transaction!(state.db_pool, async |tx| -> TxResult<_, api::Error> {
+        Ok(sqlx::query!(
+            r#"SELECT id, name, scopes as "scopes: Vec<Scope>", created_at, expires_at,
+                    last_used_at
+                FROM access_tokens
+                WHERE user_id = $1
+                ORDER BY created_at"#,
+            session.user_id,
+        )
+        .fetch_all(tx.as_mut())
+        .await?)
+    })
+    .await?;
+
+    let tokens = tokens
+        .into_iter()
+        .map(|token| AccessToken {
+            id: token.id.into(),
+            name: token.name,
+            scopes: token.scopes,
+            created_at: token.created_at,
+            expires_at: token.expires_at,
+            last_used_at: token.last_used_at,
+        })
+        .collect();
+
+    Ok((StatusCode::OK, Json(GetResponse { tokens })))
+}
+
+/// A `GET` response body for this API route.
+#[derive(Serialize, ToSchema, Debug)]
+#[serde(rename_all = "camelCase")]
+#[schema(as = access_tokens::GetResponse)]
+pub struct GetResponse {
+    /// The user's access tokens, from oldest to newest.
+    pub tokens: Vec<AccessToken>,
+}
+
+/// A `POST` request body for this API route.
+#[derive(Deserialize, ToSchema, Debug)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+#[schema(as = access_tokens::PostRequest)]
+pub struct PostRequest {
+    /// The access token's name.
+    pub name: AccessTokenName,
+
+    /// What the access token can be used for. At least one scope is required.
+    pub scopes: Vec<Scope>,
+
+    /// When the access token should expire, or `None` if it should never expire.
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+/// Creates an access token for the signed-in user. Access tokens can't manage access tokens, so
+/// this requires a sign-in session.
+///
+/// The token is only ever returned in this response, since only its hash is stored. It's revoked
+/// when the user signs out everywhere or resets their password.
+///
+/// # Errors
+///
+/// See [`crate::api::Error`].
+#[utoipa::path(
+    post,
+    path = "/api/v1/access-tokens",
+    request_body = PostRequest,
+    responses((
+        status = CREATED,
+        description = "The access token was created.",
+        body = PostResponse,
+    )),
+    security(("session" = [])),
+)]
+#[debug_handler]
+pub async fn post(
+    State(state): State<AppState>,
+    session: Session,
+    Json(body): Json<PostRequest>,
+) -> Response<PostResponse> {
+    if body.scopes.is_empty() {
+        return Err(api::Error::InvalidBodyData(
+            "expected at least one scope".into(),
+        ));
+    }
+
+    if body
+        .expires_at
+        .is_some_and(|expires_at| expires_at <= Utc::now())
+    {
+        return Err(api::Error::InvalidBodyData(
+            "expected expiration time to be in the future".into(),
+        ));
+    }
+
+    let mut scopes = body.scopes;
+    scopes.sort_unstable();
+    scopes.dedup();
+
+    let mut id = NewAccessTokenId::generate();
+    let mut token = Token::generate();
+
+    let created_at = db::transaction!(state.db_pool, async |tx| -> TxResult<_, api::Error> {
+        loop {
+            // If this loop's query fails from an ID or token conflict, this savepoint is rolled
+            // back to rather than aborting the entire transaction.
+            let mut savepoint = tx.begin().await?;
+
+            let token_hash = hash_without_salt(&token);
+
+            match sqlx::query!(
+                "INSERT INTO access_tokens (id, token_hash, user_id, name, scopes, expires_at)
+                    VALUES ($1, $2, $3, $4, $5, $6)
+                    RETURNING created_at",
+                id.as_slice(),
+                token_hash.as_ref(),
+                session.user_id,
+                *body.name,
+                &scopes as &[Scope],
+                body.expires_at,
+            )
+            .fetch_one(savepoint.as_mut())
+            .await
+            {
+                Err(sqlx::Error::Database(error))
+                    if error.constraint() == Some("access_tokens_pkey") =>
+                {
+                    id.reroll();
+                }
+                Err(sqlx::Error::Database(error))
+                    if error.constraint() == Some("access_tokens_token_hash_key") =>
+                {
+                    token.reroll();
+                }
+                result => {
+                    let created_at = result?.created_at;
+                    savepoint.commit().await?;
+                    break Ok(created_at);
+                }
+            }
+        }
+    })
+    .await?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(PostResponse {
+            token: token.to_string(),
+            access_token: AccessToken {
+                id: id.to_vec().into(),
+                name: body.name.into_inner(),
+                scopes,
+                created_at,
+                expires_at: body.expires_at,
+                last_used_at: None,
+            },
+        }),
+    ))
+}
+
+/// A `POST` response body for this API route.
+#[derive(Serialize, ToSchema, Debug)]
+#[serde(rename_all = "camelCase")]
+#[schema(as = access_tokens::PostResponse)]
+pub struct PostResponse {
+    /// The secret access token, to be sent in the `Authorization` header with the `Bearer` scheme.
+    pub token: String,
+
+    /// The created access token's details.
+    pub access_token: AccessToken,
+}
+
+/// A request query identifying one of the signed-in user's access tokens.
+#[derive(Deserialize, IntoParams, Debug)]
+#[serde(rename_all = "camelCase")]
+#[into_params(parameter_in = Query)]
+pub struct AccessTokenQuery {
+    /// The access token's ID.
+    pub id: Id,
+}
+
+/// Revokes one of the signed-in user's access tokens. Access tokens can't manage access tokens, so
+/// this requires a sign-in session.
+///
+/// # Errors
+///
+/// See [`crate::api::Error`].
+#[utoipa::path(
+    delete,
+    path = "/api/v1/access-tokens",
+    params(AccessTokenQuery),
+    responses((status = OK, description = "The access token was revoked.", body = DeleteResponse)),
+    security(("session" = [])),
+)]
+#[debug_handler]
+pub async fn delete(
+    State(state): State<AppState>,
+    session: Session,
+    Query(query): Query<AccessTokenQuery>,
+) -> Response<DeleteResponse> {
+    db::transaction!(state.db_pool, async |tx| -> TxResult<_, api::Error> {
+        let result = sqlx::query!(
+            "DELETE FROM access_tokens
+                WHERE id = $1 AND user_id = $2",
+            query.id.as_slice(),
+            session.user_id,
+        )
+        .execute(tx.as_mut())
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(TxError::Abort(api::Error::ResourceNotFound));
+        }
+
+        Ok(())
+    })
+    .await?;
+
+    Ok((StatusCode::OK, Json(DeleteResponse {})))
+}
+
+/// A `DELETE` response body for this API route.
+#[derive(Serialize, ToSchema, Debug)]
+#[serde(rename_all = "camelCase")]
+#[schema(as = access_tokens::DeleteResponse)]
+pub struct DeleteResponse {}
+
+/// An access token, as returned by this API route. The secret token itself is never included.
+#[derive(Serialize, ToSchema, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct AccessToken {
+    /// The access token's ID.
+    pub id: Id,
+
+    /// The access token's name.
+    pub name: String,
+
+    /// What the access token can be used for.
+    pub scopes: Vec<Scope>,
+
+    /// When the access token was created.
+    pub created_at: DateTime<Utc>,
+
+    /// When the access token expires, or `None` if it never expires.
+    pub expires_at: Option<DateTime<Utc>>,
+
+    /// When the access token was last used to authenticate a request, or `None` if it never was.
+    /// It's only updated about once a minute, so it can be up to a minute out of date.
+    pub last_used_at: Option<DateTime<Utc>>,
+}
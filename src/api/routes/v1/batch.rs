@@ -0,0 +1,903 @@
+//! Operations applied to many of a user's files and folders at once.
+
+use std::collections::HashSet;
+
+use axum::{extract::State, http::StatusCode};
+use axum_macros::debug_handler;
+use serde::{Deserialize, Serialize};
+use sqlx::{Acquire, PgConnection};
+use utoipa::ToSchema;
+
+use crate::{
+    api::{
+        self,
+        session::{Scope, ScopedSession},
+        ErrorBody, Json, Response,
+    },
+    db::{self, TxError, TxResult},
+    id::{Id, NewShareKey},
+    storage, AppState,
+};
+
+/// The maximum number of items a single batch request can operate on.
+pub const MAX_ITEMS: usize = 1000;
+
+/// Whether an item is a file or a folder.
+#[derive(Deserialize, Serialize, ToSchema, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+#[serde(rename_all = "camelCase")]
+pub enum ItemType {
+    /// A file.
+    File,
+
+    /// A folder.
+    Folder,
+}
+
+/// A file or folder to apply the batch operation to.
+#[derive(Deserialize, ToSchema, Debug)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct Item {
+    /// Whether the item is a file or a folder.
+    pub r#type: ItemType,
+
+    /// The item's ID.
+    pub id: Id,
+}
+
+/// An operation to apply to every item in a batch.
+#[derive(Deserialize, ToSchema, Debug)]
+#[serde(
+    tag = "type",
+    rename_all = "camelCase",
+    rename_all_fields = "camelCase",
+    deny_unknown_fields
+)]
+pub enum Operation {
+    /// Moves each item into a folder.
+    // `utoipa` doesn't support `rename_all_fields`, so the schema's fields are renamed separately.
+    #[schema(rename_all = "camelCase")]
+    Move {
+        /// The ID of the destination folder, or `None` for the top level of the user's files.
+        parent_id: Option<Id>,
+    },
+
+    /// Deletes each item, including the contents of folders.
+    Delete,
+
+    /// Sets whether each item is shared. Sharing a folder gives it a share key if it doesn't
+    /// already have one, and unsharing it removes its share key.
+    SetShared {
+        /// Whether the items should be shared.
+        shared: bool,
+    },
+}
+
+/// A `POST` request body for this API route.
+#[derive(Deserialize, ToSchema, Debug)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+#[schema(as = batch::PostRequest)]
+pub struct PostRequest {
+    /// The files and folders to operate on.
+    pub items: Vec<Item>,
+
+    /// The operation to apply to every item.
+    pub operation: Operation,
+}
+
+/// Applies one operation to many of the signed-in user's files and folders at once.
+///
+/// The batch is atomic: if the operation fails for any item, no item is changed, and the response
+/// status is the status of the first failed item's error. Either way, the response reports the
+/// result for every item.
+///
+/// Deleting a folder along with items inside it succeeds, since those items are already deleted
+/// with the folder.
+///
+/// With an access token, moving and deleting require the [`Scope::WriteFiles`] scope, and sharing
+/// requires the [`Scope::ManageShares`] scope.
+///
+/// # Errors
+///
+/// See [`crate::api::Error`].
+#[utoipa::path(
+    post,
+    path = "/api/v1/batch",
+    request_body = PostRequest,
+    responses(
+        (status = OK, description = "The operation succeeded for every item.", body = PostResponse),
+        (
+            status = "4XX",
+            description = "The operation failed for at least one item, so no item was changed.",
+            body = PostResponse,
+        ),
+    ),
+    security(
+        ("session" = []),
+        ("accessToken" = ["writeFiles"]),
+        ("accessToken" = ["manageShares"]),
+    ),
+)]
+#[debug_handler]
+pub async fn post(
+    State(state): State<AppState>,
+    session: ScopedSession,
+    Json(body): Json<PostRequest>,
+) -> Response<PostResponse> {
+    let session = session.require_scope(match body.operation {
+        Operation::Move { .. } | Operation::Delete => Scope::WriteFiles,
+        Operation::SetShared { .. } => Scope::ManageShares,
+    })?;
+
+    if body.items.len() > MAX_ITEMS {
+        return Err(api::Error::InvalidBodyData(format!(
+            "expected at most {MAX_ITEMS} items, found {}",
+            body.items.len(),
+        )));
+    }
+
+    let outcome = db::transaction!(state.db_pool, async |tx| -> TxResult<_, api::Error> {
+        let action = match &body.operation {
+            Operation::Move { parent_id } => {
+                Action::Move(destination(tx.as_mut(), &session.user_id, parent_id.as_ref()).await?)
+            }
+            Operation::Delete => Action::Delete,
+            Operation::SetShared { shared } => Action::SetShared(*shared),
+        };
+
+        // Every item is applied in this one savepoint so they can all be rolled back together if any
+        // fail, while still trying every item so each one's result can be reported.
+        let mut batch = tx.begin().await?;
+
+        let mut results = Vec::with_capacity(body.items.len());
+        let mut status = StatusCode::OK;
+        let mut deleted = Deleted::default();
+
+        for item in &body.items {
+            // Items inside a folder deleted earlier in the batch were already deleted with it.
+            if deleted.items.contains(&(item.r#type, item.id.to_vec())) {
+                results.push(ItemResult {
+                    r#type: item.r#type,
+                    id: item.id.clone(),
+                    error: None,
+                });
+                continue;
+            }
+
+            let mut savepoint = batch.begin().await?;
+
+            // `api::Error` isn't `Send`, so it must be converted into a response body within this
+            // block, before the next `await`.
+            let error = {
+                let result = match item.r#type {
+                    ItemType::File => {
+                        apply_to_file(
+                            savepoint.as_mut(),
+                            &session.user_id,
+                            &item.id,
+                            &action,
+                            &mut deleted,
+                        )
+                        .await
+                    }
+                    ItemType::Folder => {
+                        apply_to_folder(
+                            savepoint.as_mut(),
+                            &session.user_id,
+                            &item.id,
+                            &action,
+                            &mut deleted,
+                        )
+                        .await
+                    }
+                };
+
+                match result {
+                    Ok(()) => None,
+                    Err(TxError::Abort(error)) => {
+                        if status == StatusCode::OK {
+                            status = error.status();
+                        }
+
+                        Some(ErrorBody::from(&error))
+                    }
+                    Err(TxError::Retry) => return Err(TxError::Retry),
+                }
+            };
+
+            if error.is_some() {
+                savepoint.rollback().await?;
+            } else {
+                savepoint.commit().await?;
+            }
+
+            results.push(ItemResult {
+                r#type: item.r#type,
+                id: item.id.clone(),
+                error,
+            });
+        }
+
+        if status == StatusCode::OK {
+            batch.commit().await?;
+        } else {
+            batch.rollback().await?;
+            deleted.files.clear();
+        }
+
+        Ok((status, results, deleted.files))
+    })
+    .await?;
+    let (status, results, deleted_files) = outcome;
+
+    // File contents can only be deleted once the transaction is committed, in case it's rolled back.
+    for (file_id, parts) in deleted_files {
+        storage::delete(&file_id, parts).await;
+    }
+
+    Ok((status, Json(PostResponse { results })))
+}
+
+/// A `POST` response body for this API route.
+#[derive(Serialize, ToSchema, Debug)]
+#[serde(rename_all = "camelCase")]
+#[schema(as = batch::PostResponse)]
+pub struct PostResponse {
+    /// The result for each item, in the same order as the request's items.
+    pub results: Vec<ItemResult>,
+}
+
+/// The result of applying a batch operation to one item.
+#[derive(Serialize, ToSchema, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ItemResult {
+    /// Whether the item is a file or a folder.
+    pub r#type: ItemType,
+
+    /// The item's ID.
+    pub id: Id,
+
+    /// Why the operation failed for this item, or `None` if it would have succeeded.
+    pub error: Option<ErrorBody>,
+}
+
+/// An [`Operation`] with what it needs from the database resolved ahead of time, so it can be
+/// applied to each item.
+#[derive(Debug)]
+enum Action {
+    /// See [`Operation::Move`].
+    Move(Destination),
+
+    /// See [`Operation::Delete`].
+    Delete,
+
+    /// See [`Operation::SetShared`].
+    SetShared(bool),
+}
+
+/// The folder items are being moved into.
+#[derive(Debug)]
+struct Destination {
+    /// The IDs of the destination folder and its ancestors, from the top level down.
+    id_path: Vec<Vec<u8>>,
+
+    /// The names of the destination folder and its ancestors, from the top level down.
+    name_path: Vec<String>,
+}
+
+/// The files and folders deleted so far in a batch, including the contents of deleted folders.
+#[derive(Default, Debug)]
+struct Deleted {
+    /// The type and ID of each deleted item.
+    items: HashSet<(ItemType, Vec<u8>)>,
+
+    /// The ID and number of stored parts of each deleted file, so the file's contents can be
+    /// deleted from storage once the batch is committed.
+    files: Vec<(Vec<u8>, i32)>,
+}
+
+/// Gets the folder items are being moved into, given its ID or `None` for the top level of the
+/// user's files.
+///
+/// # Errors
+///
+/// Returns [`api::Error::ResourceNotFound`] if the user has no such folder.
+async fn destination(
+    conn: &mut PgConnection,
+    owner_id: &[u8],
+    parent_id: Option<&Id>,
+) -> TxResult<Destination, api::Error> {
+    let Some(parent_id) = parent_id else {
+        return Ok(Destination {
+            id_path: Vec::new(),
+            name_path: Vec::new(),
+        });
+    };
+
+    let Some(parent) = sqlx::query!(
+        "SELECT id, name, parent_id_path, parent_name_path FROM folders
+            WHERE id = $1 AND owner_id = $2",
+        parent_id.as_slice(),
+        owner_id,
+    )
+    .fetch_optional(conn)
+    .await?
+    else {
+        return Err(TxError::Abort(api::Error::ResourceNotFound));
+    };
+
+    let mut id_path = parent.parent_id_path;
+    id_path.push(parent.id);
+
+    let mut name_path = parent.parent_name_path;
+    name_path.push(parent.name);
+
+    Ok(Destination { id_path, name_path })
+}
+
+/// The constraint violated when a file's name is already taken in its folder.
+const FILE_NAME_CONSTRAINT: &str = "files_owner_id_parent_name_path_name_key";
+
+/// The constraint violated when a folder's name is already taken in its folder.
+const FOLDER_NAME_CONSTRAINT: &str = "folders_owner_id_parent_name_path_name_key";
+
+/// Applies a batch operation to one of the user's files.
+///
+/// # Errors
+///
+/// Returns [`api::Error::ResourceNotFound`] if the user has no such file, or any error from the
+/// operation itself.
+async fn apply_to_file(
+    conn: &mut PgConnection,
+    owner_id: &[u8],
+    id: &[u8],
+    action: &Action,
+    deleted: &mut Deleted,
+) -> TxResult<(), api::Error> {
+    let Some(file) = sqlx::query!(
+        "SELECT parent_id_path, size, parts FROM files
+            WHERE id = $1 AND owner_id = $2",
+        id,
+        owner_id,
+    )
+    .fetch_optional(&mut *conn)
+    .await?
+    else {
+        return Err(TxError::Abort(api::Error::ResourceNotFound));
+    };
+
+    match action {
+        Action::Move(destination) => {
+            match sqlx::query!(
+                "UPDATE files
+                    SET parent_id_path = $1, parent_name_path = $2
+                    WHERE id = $3",
+                &destination.id_path,
+                &destination.name_path,
+                id,
+            )
+            .execute(&mut *conn)
+            .await
+            {
+                Err(sqlx::Error::Database(error))
+                    if error.constraint() == Some(FILE_NAME_CONSTRAINT) =>
+                {
+                    return Err(TxError::Abort(api::Error::NameTaken));
+                }
+                result => result?,
+            };
+
+            add_folder_sizes(conn, &file.parent_id_path, -file.size).await?;
+            add_folder_sizes(conn, &destination.id_path, file.size).await?;
+        }
+
+        Action::Delete => {
+            sqlx::query!(
+                "DELETE FROM files
+                    WHERE id = $1",
+                id,
+            )
+            .execute(&mut *conn)
+            .await?;
+
+            add_folder_sizes(conn, &file.parent_id_path, -file.size).await?;
+
+            deleted.items.insert((ItemType::File, id.to_vec()));
+            deleted.files.push((id.to_vec(), file.parts));
+        }
+
+        Action::SetShared(shared) => {
+            sqlx::query!(
+                "UPDATE files
+                    SET shared = $1
+                    WHERE id = $2",
+                shared,
+                id,
+            )
+            .execute(&mut *conn)
+            .await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Applies a batch operation to one of the user's folders, including its contents where relevant.
+///
+/// # Errors
+///
+/// Returns [`api::Error::ResourceNotFound`] if the user has no such folder, or any error from the
+/// operation itself.
+async fn apply_to_folder(
+    conn: &mut PgConnection,
+    owner_id: &[u8],
+    id: &[u8],
+    action: &Action,
+    deleted: &mut Deleted,
+) -> TxResult<(), api::Error> {
+    let Some(folder) = sqlx::query!(
+        "SELECT name, parent_id_path, size FROM folders
+            WHERE id = $1 AND owner_id = $2",
+        id,
+        owner_id,
+    )
+    .fetch_optional(&mut *conn)
+    .await?
+    else {
+        return Err(TxError::Abort(api::Error::ResourceNotFound));
+    };
+
+    // The parent ID path of everything directly inside this folder.
+    let mut contents_id_path = folder.parent_id_path.clone();
+    contents_id_path.push(id.to_vec());
+
+    match action {
+        Action::Move(destination) => {
+            if destination
+                .id_path
+                .iter()
+                .any(|ancestor_id| ancestor_id == id)
+            {
+                return Err(TxError::Abort(api::Error::MoveIntoSelf));
+            }
+
+            match sqlx::query!(
+                "UPDATE folders
+                    SET parent_id_path = $1, parent_name_path = $2
+                    WHERE id = $3",
+                &destination.id_path,
+                &destination.name_path,
+                id,
+            )
+            .execute(&mut *conn)
+            .await
+            {
+                Err(sqlx::Error::Database(error))
+                    if error.constraint() == Some(FOLDER_NAME_CONSTRAINT) =>
+                {
+                    return Err(TxError::Abort(api::Error::NameTaken));
+                }
+                result => result?,
+            };
+
+            let mut new_contents_id_path = destination.id_path.clone();
+            new_contents_id_path.push(id.to_vec());
+
+            let mut new_contents_name_path = destination.name_path.clone();
+            new_contents_name_path.push(folder.name);
+
+            // Replace the start of each descendant's paths with the folder's new paths.
+            sqlx::query!(
+                "UPDATE folders
+                    SET parent_id_path = $1 || parent_id_path[cardinality($3::bytea[]) + 1:],
+                        parent_name_path = $2 || parent_name_path[cardinality($3::bytea[]) + 1:]
+                    WHERE owner_id = $4 AND parent_id_path[:cardinality($3::bytea[])] = $3",
+                &new_contents_id_path,
+                &new_contents_name_path,
+                &contents_id_path,
+                owner_id,
+            )
+            .execute(&mut *conn)
+            .await?;
+
+            sqlx::query!(
+                "UPDATE files
+                    SET parent_id_path = $1 || parent_id_path[cardinality($3::bytea[]) + 1:],
+                        parent_name_path = $2 || parent_name_path[cardinality($3::bytea[]) + 1:]
+                    WHERE owner_id = $4 AND parent_id_path[:cardinality($3::bytea[])] = $3",
+                &new_contents_id_path,
+                &new_contents_name_path,
+                &contents_id_path,
+                owner_id,
+            )
+            .execute(&mut *conn)
+            .await?;
+
+            add_folder_sizes(conn, &folder.parent_id_path, -folder.size).await?;
+            add_folder_sizes(conn, &destination.id_path, folder.size).await?;
+        }
+
+        Action::Delete => {
+            let deleted_files = sqlx::query!(
+                "DELETE FROM files
+                    WHERE owner_id = $1 AND parent_id_path[:cardinality($2::bytea[])] = $2
+                    RETURNING id, parts",
+                owner_id,
+                &contents_id_path,
+            )
+            .fetch_all(&mut *conn)
+            .await?;
+
+            let deleted_folders = sqlx::query!(
+                "DELETE FROM folders
+                    WHERE id = $1
+                        OR (owner_id = $2 AND parent_id_path[:cardinality($3::bytea[])] = $3)
+                    RETURNING id",
+                id,
+                owner_id,
+                &contents_id_path,
+            )
+            .fetch_all(&mut *conn)
+            .await?;
+
+            for file in deleted_files {
+                deleted.items.insert((ItemType::File, file.id.clone()));
+                deleted.files.push((file.id, file.parts));
+            }
+            deleted.items.extend(
+                deleted_folders
+                    .into_iter()
+                    .map(|folder| (ItemType::Folder, folder.id)),
+            );
+
+            add_folder_sizes(conn, &folder.parent_id_path, -folder.size).await?;
+        }
+
+        Action::SetShared(false) => {
+            sqlx::query!(
+                "UPDATE folders
+                    SET share_key = NULL
+                    WHERE id = $1",
+                id,
+            )
+            .execute(&mut *conn)
+            .await?;
+        }
+
+        Action::SetShared(true) => {
+            let mut share_key = NewShareKey::generate();
+
+            loop {
+                // If this loop's query fails from a share key conflict, this savepoint is rolled
+                // back to rather than aborting the entire transaction.
+                let mut savepoint = conn.begin().await?;
+
+                match sqlx::query!(
+                    "UPDATE folders
+                        SET share_key = COALESCE(share_key, $1)
+                        WHERE id = $2",
+                    share_key.as_slice(),
+                    id,
+                )
+                .execute(savepoint.as_mut())
+                .await
+                {
+                    Err(sqlx::Error::Database(error))
+                        if error.constraint() == Some("folders_share_key_key") =>
+                    {
+                        share_key.reroll();
+                        continue;
+                    }
+                    result => result?,
+                };
+
+                savepoint.commit().await?;
+                break;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Adds to the sizes of the specified folders. Used to keep each folder's size equal to the total
+/// size of its contents.
+///
+/// # Errors
+///
+/// Returns an error if the database query fails.
+async fn add_folder_sizes(
+    conn: &mut PgConnection,
+    folder_ids: &[Vec<u8>],
+    size: i64,
+) -> sqlx::Result<()> {
+    sqlx::query!(
+        "UPDATE folders
+            SET size = size + $1
+            WHERE id = ANY($2)",
+        size,
+        folder_ids,
+    )
+    .execute(conn)
+    .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+#[expect(clippy::missing_errors_doc, reason = "see rust-lang/rust-clippy#13391")]
+mod tests {
+    use sqlx::PgPool;
+
+    use super::*;
+    use crate::api::session::Session;
+
+    /// The ID of the user who owns every test item.
+    const USER_ID: &[u8] = b"u";
+
+    /// Creates the test user's items: folder `a` containing folder `b` containing the 10-byte file
+    /// `f`, and the empty folder `c`. Each item's ID is its name.
+    async fn create_items(pool: &PgPool) -> sqlx::Result<()> {
+        sqlx::raw_sql(
+            r"INSERT INTO users (id, email, name, password_hash)
+                VALUES ('\x75', 'user@example.com', 'User', '');
+
+            INSERT INTO folders (id, name, owner_id, parent_id_path, parent_name_path, size)
+                VALUES ('\x61', 'a', '\x75', '{}', '{}', 10),
+                    ('\x62', 'b', '\x75', '{\\x61}', '{a}', 10),
+                    ('\x63', 'c', '\x75', '{}', '{}', 0);
+
+            INSERT INTO files (id, name, owner_id, parent_id_path, parent_name_path, size,
+                    encoded_size, type)
+                VALUES ('\x66', 'f', '\x75', '{\\x61,\\x62}', '{a,b}', 10, 10, 'text/plain');",
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Sends a batch request as the test user.
+    async fn post_batch(
+        pool: &PgPool,
+        items: &[(ItemType, &str)],
+        operation: Operation,
+    ) -> anyhow::Result<(StatusCode, PostResponse)> {
+        let items = items
+            .iter()
+            .map(|(r#type, id)| Item {
+                r#type: *r#type,
+                id: id.as_bytes().to_vec().into(),
+            })
+            .collect();
+
+        let (status, Json(response)) = post(
+            State(AppState {
+                db_pool: pool.clone(),
+            }),
+            Session {
+                user_id: USER_ID.to_vec(),
+            }
+            .into(),
+            Json(PostRequest { items, operation }),
+        )
+        .await
+        // `api::Error` isn't `Send`, so it can't be converted into an `anyhow::Error` directly.
+        .map_err(|error| anyhow::anyhow!("{error:?}"))?;
+
+        Ok((status, response))
+    }
+
+    /// Gets each item's error code from a batch response.
+    fn error_codes(response: &PostResponse) -> Vec<Option<&'static str>> {
+        response
+            .results
+            .iter()
+            .map(|result| result.error.as_ref().map(|error| error.code))
+            .collect()
+    }
+
+    #[test]
+    fn operations_deserialize() -> anyhow::Result<()> {
+        let operation = serde_json::from_str(r#"{"type":"move","parentId":"Yw"}"#)?;
+        let Operation::Move {
+            parent_id: Some(parent_id),
+        } = operation
+        else {
+            panic!("{operation:?} should be a move into a folder");
+        };
+        assert_eq!(parent_id.as_slice(), b"c");
+
+        let operation = serde_json::from_str(r#"{"type":"move","parentId":null}"#)?;
+        assert!(
+            matches!(operation, Operation::Move { parent_id: None }),
+            "{operation:?}",
+        );
+
+        let operation = serde_json::from_str(r#"{"type":"setShared","shared":true}"#)?;
+        assert!(
+            matches!(operation, Operation::SetShared { shared: true }),
+            "{operation:?}",
+        );
+
+        for invalid in [
+            r#"{"type":"move","parent_id":null}"#,
+            r#"{"type":"rename"}"#,
+        ] {
+            assert!(
+                serde_json::from_str::<Operation>(invalid).is_err(),
+                "{invalid} should be rejected",
+            );
+        }
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    #[ignore = "requires a database at `DATABASE_URL`"]
+    async fn failed_batch_status_from_first_error(pool: PgPool) -> anyhow::Result<()> {
+        create_items(&pool).await?;
+
+        let (status, response) = post_batch(
+            &pool,
+            &[(ItemType::File, "f"), (ItemType::Folder, "x")],
+            Operation::Move {
+                parent_id: Some(b"c".to_vec().into()),
+            },
+        )
+        .await?;
+
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(error_codes(&response), [None, Some("RESOURCE_NOT_FOUND")]);
+
+        let (status, response) = post_batch(
+            &pool,
+            &[(ItemType::Folder, "c"), (ItemType::Folder, "a")],
+            Operation::Move {
+                parent_id: Some(b"b".to_vec().into()),
+            },
+        )
+        .await?;
+
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert_eq!(error_codes(&response), [None, Some("MOVE_INTO_SELF")]);
+
+        // Neither batch should have moved anything.
+        let parent_id_path: Vec<Vec<u8>> =
+            sqlx::query_scalar("SELECT parent_id_path FROM files WHERE id = '\\x66'")
+                .fetch_one(&pool)
+                .await?;
+        assert_eq!(parent_id_path, [b"a".to_vec(), b"b".to_vec()]);
+
+        let parent_id_path: Vec<Vec<u8>> =
+            sqlx::query_scalar("SELECT parent_id_path FROM folders WHERE id = '\\x63'")
+                .fetch_one(&pool)
+                .await?;
+        assert!(parent_id_path.is_empty());
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    #[ignore = "requires a database at `DATABASE_URL`"]
+    async fn move_updates_paths_and_sizes(pool: PgPool) -> anyhow::Result<()> {
+        create_items(&pool).await?;
+
+        let (status, response) = post_batch(
+            &pool,
+            &[(ItemType::Folder, "b")],
+            Operation::Move {
+                parent_id: Some(b"c".to_vec().into()),
+            },
+        )
+        .await?;
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(error_codes(&response), [None]);
+
+        let (parent_name_path,): (Vec<String>,) =
+            sqlx::query_as("SELECT parent_name_path FROM files WHERE id = '\\x66'")
+                .fetch_one(&pool)
+                .await?;
+        assert_eq!(parent_name_path, ["c", "b"]);
+
+        let sizes: Vec<(Vec<u8>, i64)> = sqlx::query_as("SELECT id, size FROM folders ORDER BY id")
+            .fetch_all(&pool)
+            .await?;
+        assert_eq!(
+            sizes,
+            [(b"a".to_vec(), 0), (b"b".to_vec(), 10), (b"c".to_vec(), 10)],
+        );
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    #[ignore = "requires a database at `DATABASE_URL`"]
+    async fn delete_folder_with_contents(pool: PgPool) -> anyhow::Result<()> {
+        // Contents listed either after or before their folder are deleted with it.
+        let orders = [
+            [
+                (ItemType::Folder, "a"),
+                (ItemType::Folder, "b"),
+                (ItemType::File, "f"),
+            ],
+            [
+                (ItemType::File, "f"),
+                (ItemType::Folder, "b"),
+                (ItemType::Folder, "a"),
+            ],
+        ];
+
+        for items in orders {
+            sqlx::raw_sql("DELETE FROM users").execute(&pool).await?;
+            create_items(&pool).await?;
+
+            let (status, response) = post_batch(&pool, &items, Operation::Delete).await?;
+
+            assert_eq!(status, StatusCode::OK, "{items:?}");
+            assert_eq!(error_codes(&response), [None, None, None], "{items:?}");
+
+            let folder_ids: Vec<Vec<u8>> = sqlx::query_scalar("SELECT id FROM folders")
+                .fetch_all(&pool)
+                .await?;
+            assert_eq!(folder_ids, [b"c".to_vec()], "{items:?}");
+        }
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    #[ignore = "requires a database at `DATABASE_URL`"]
+    async fn delete_removes_stored_contents(pool: PgPool) -> anyhow::Result<()> {
+        create_items(&pool).await?;
+
+        // The empty files `g` with two parts at the top level, and `h` in folder `b`. No other test
+        // stores contents for these IDs.
+        sqlx::raw_sql(
+            r"INSERT INTO files (id, name, owner_id, parent_id_path, parent_name_path, size,
+                    encoded_size, type, parts)
+                VALUES ('\x67', 'g', '\x75', '{}', '{}', 0, 0, 'text/plain', 2),
+                    ('\x68', 'h', '\x75', '{\\x61,\\x62}', '{a,b}', 0, 0, 'text/plain', 1);",
+        )
+        .execute(&pool)
+        .await?;
+
+        let parts: [(&[u8], i32); 3] = [(b"g", 0), (b"g", 1), (b"h", 0)];
+        for (file_id, part) in parts {
+            storage::write_part(file_id, part, b"contents").await?;
+        }
+
+        let (status, _) = post_batch(
+            &pool,
+            &[(ItemType::File, "g"), (ItemType::Folder, "x")],
+            Operation::Delete,
+        )
+        .await?;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        // The failed batch was rolled back, so its contents should still be stored.
+        for (file_id, part) in parts {
+            assert!(
+                storage::part_exists(file_id, part).await?,
+                "{file_id:?} part {part} should be kept",
+            );
+        }
+
+        let (status, _) = post_batch(
+            &pool,
+            &[(ItemType::File, "g"), (ItemType::Folder, "a")],
+            Operation::Delete,
+        )
+        .await?;
+        assert_eq!(status, StatusCode::OK);
+
+        for (file_id, part) in parts {
+            assert!(
+                !storage::part_exists(file_id, part).await?,
+                "{file_id:?} part {part} should be deleted",
+            );
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,262 @@
+//! The set of custom domains the signed-in user has added to serve their folders from.
+
+use axum::{extract::State, http::StatusCode};
+use axum_macros::debug_handler;
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::{
+    api::{
+        self,
+        session::{Scope, ScopedSession},
+        validation::CustomDomainName,
+        Json, Query, Response,
+    },
+    custom_domain::{self, HTTP_CHALLENGE_PATH, TXT_CHALLENGE_PREFIX},
+    db::{self, TxError, TxResult},
+    id::{Id, NewChallengeToken},
+    AppState,
+};
+
+pub mod verification;
+
+/// Lists the signed-in user's custom domains.
+///
+/// # Errors
+///
+/// See [`crate::api::Error`].
+#[utoipa::path(
+    get,
+    path = "/api/v1/custom-domains",
+    responses((status = OK, description = "The user's custom domains.", body = GetResponse)),
+    security(("session" = []), ("accessToken" = ["readFiles"])),
+)]
+#[debug_handler]
+pub async fn get(State(state): State<AppState>, session: ScopedSession) -> Response<GetResponse> {
+    let session = session.require_scope(Scope::ReadFiles)?;
+
+    let domains = db::transaction!(state.db_pool, async |tx| -> TxResult<_, api::Error> {
+        Ok(sqlx::query!(
+            "SELECT domain, folder_id, challenge_token, verified_at IS NOT NULL as verified
+                FROM custom_domains
+                WHERE owner_id = $1
+                ORDER BY domain",
+            session.user_id,
+        )
+        .fetch_all(tx.as_mut())
+        .await?)
+    })
+    .await?;
+
+    let domains = domains
+        .into_iter()
+        .map(|domain| {
+            CustomDomain::new(
+                domain.domain,
+                domain.folder_id.into(),
+                &domain.challenge_token,
+                domain.verified.unwrap_or(false),
+            )
+        })
+        .collect();
+
+    Ok((StatusCode::OK, Json(GetResponse { domains })))
+}
+
+/// A `GET` response body for this API route.
+#[derive(Serialize, ToSchema, Debug)]
+#[serde(rename_all = "camelCase")]
+#[schema(as = custom_domains::GetResponse)]
+pub struct GetResponse {
+    /// The user's custom domains, sorted by domain name.
+    pub domains: Vec<CustomDomain>,
+}
+
+/// A `POST` request body for this API route.
+#[derive(Deserialize, ToSchema, Debug)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+#[schema(as = custom_domains::PostRequest)]
+pub struct PostRequest {
+    /// The domain name to add.
+    pub domain: CustomDomainName,
+
+    /// The ID of the folder the domain should serve.
+    pub folder_id: Id,
+}
+
+/// Adds an unverified custom domain for the signed-in user. The domain serves nothing until its
+/// ownership is verified through [`verification`].
+///
+/// # Errors
+///
+/// See [`crate::api::Error`].
+#[utoipa::path(
+    post,
+    path = "/api/v1/custom-domains",
+    request_body = PostRequest,
+    responses((status = CREATED, description = "The domain was added.", body = CustomDomain)),
+    security(("session" = []), ("accessToken" = ["manageShares"])),
+)]
+#[debug_handler]
+pub async fn post(
+    State(state): State<AppState>,
+    session: ScopedSession,
+    Json(body): Json<PostRequest>,
+) -> Response<CustomDomain> {
+    let session = session.require_scope(Scope::ManageShares)?;
+
+    let challenge_token = NewChallengeToken::generate();
+
+    db::transaction!(state.db_pool, async |tx| -> TxResult<_, api::Error> {
+        let result = sqlx::query!(
+            "INSERT INTO custom_domains (domain, owner_id, folder_id, challenge_token)
+                SELECT $1, owner_id, id, $2 FROM folders
+                    WHERE id = $3 AND owner_id = $4",
+            body.domain.as_str(),
+            challenge_token.as_slice(),
+            body.folder_id.as_slice(),
+            session.user_id,
+        )
+        .execute(tx.as_mut())
+        .await;
+
+        match result {
+            Err(sqlx::Error::Database(error))
+                if error.constraint() == Some("custom_domains_pkey") =>
+            {
+                Err(TxError::Abort(api::Error::CustomDomainTaken))
+            }
+            Ok(result) if result.rows_affected() == 0 => {
+                Err(TxError::Abort(api::Error::ResourceNotFound))
+            }
+            result => {
+                result?;
+                Ok(())
+            }
+        }
+    })
+    .await?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(CustomDomain::new(
+            body.domain.into_inner(),
+            body.folder_id,
+            challenge_token.as_slice(),
+            false,
+        )),
+    ))
+}
+
+/// A request query identifying one of the signed-in user's custom domains.
+#[derive(Deserialize, IntoParams, Debug)]
+#[serde(rename_all = "camelCase")]
+#[into_params(parameter_in = Query)]
+pub struct DomainQuery {
+    /// The custom domain's name.
+    pub domain: CustomDomainName,
+}
+
+/// Removes one of the signed-in user's custom domains.
+///
+/// # Errors
+///
+/// See [`crate::api::Error`].
+#[utoipa::path(
+    delete,
+    path = "/api/v1/custom-domains",
+    params(DomainQuery),
+    responses((status = OK, description = "The domain was removed.", body = DeleteResponse)),
+    security(("session" = []), ("accessToken" = ["manageShares"])),
+)]
+#[debug_handler]
+pub async fn delete(
+    State(state): State<AppState>,
+    session: ScopedSession,
+    Query(query): Query<DomainQuery>,
+) -> Response<DeleteResponse> {
+    let session = session.require_scope(Scope::ManageShares)?;
+
+    let was_deleted = db::transaction!(state.db_pool, async |tx| -> TxResult<_, api::Error> {
+        Ok(sqlx::query!(
+            "DELETE FROM custom_domains
+                WHERE owner_id = $1 AND domain = $2",
+            session.user_id,
+            query.domain.as_str(),
+        )
+        .execute(tx.as_mut())
+        .await?
+        .rows_affected()
+            != 0)
+    })
+    .await?;
+
+    if !was_deleted {
+        return Err(api::Error::ResourceNotFound);
+    }
+
+    custom_domain::invalidate(&query.domain).await;
+
+    Ok((StatusCode::OK, Json(DeleteResponse {})))
+}
+
+/// A `DELETE` response body for this API route.
+#[derive(Serialize, ToSchema, Debug)]
+#[serde(rename_all = "camelCase")]
+#[schema(as = custom_domains::DeleteResponse)]
+pub struct DeleteResponse {}
+
+/// A custom domain, as returned by this API route.
+#[derive(Serialize, ToSchema, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct CustomDomain {
+    /// The domain name, in lowercase ASCII.
+    pub domain: String,
+
+    /// The ID of the folder the domain serves.
+    pub folder_id: Id,
+
+    /// Whether the user proved they own the domain. Only verified domains are served.
+    pub verified: bool,
+
+    /// The ways the user can prove they own the domain. Either one is enough.
+    pub challenge: Challenge,
+}
+
+impl CustomDomain {
+    /// Constructs a [`CustomDomain`], deriving its [`Challenge`] from its challenge token.
+    pub(crate) fn new(
+        domain: String,
+        folder_id: Id,
+        challenge_token: &[u8],
+        verified: bool,
+    ) -> Self {
+        let challenge_token = Id::from(challenge_token).to_string();
+
+        Self {
+            challenge: Challenge {
+                txt_name: format!("{TXT_CHALLENGE_PREFIX}{domain}"),
+                http_url: format!("http://{domain}{HTTP_CHALLENGE_PATH}/{challenge_token}"),
+                value: challenge_token,
+            },
+            domain,
+            folder_id,
+            verified,
+        }
+    }
+}
+
+/// The ways a user can prove they own a custom domain.
+#[derive(Serialize, ToSchema, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Challenge {
+    /// The DNS name to set a TXT record on, with the record's value set to [`Challenge::value`].
+    pub txt_name: String,
+
+    /// The URL to serve a file from, with the file's contents set to [`Challenge::value`]. This
+    /// only works before the domain is pointed at File Garden.
+    pub http_url: String,
+
+    /// The challenge token proving ownership.
+    pub value: String,
+}
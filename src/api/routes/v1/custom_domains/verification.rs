@@ -0,0 +1,103 @@
+//! The ownership verification of one of the signed-in user's custom domains.
+
+use axum::{extract::State, http::StatusCode};
+use axum_macros::debug_handler;
+
+use super::{CustomDomain, DomainQuery};
+use crate::{
+    api::{
+        self,
+        session::{Scope, ScopedSession},
+        Json, Query, Response,
+    },
+    custom_domain,
+    db::{self, TxError, TxResult},
+    id::Id,
+    AppState,
+};
+
+/// Checks the challenges of one of the signed-in user's custom domains, and marks the domain
+/// verified if either challenge is set up. Once verified, the domain serves its folder.
+///
+/// # Errors
+///
+/// See [`crate::api::Error`].
+#[utoipa::path(
+    post,
+    path = "/api/v1/custom-domains/verification",
+    params(DomainQuery),
+    responses((status = OK, description = "The domain was verified.", body = CustomDomain)),
+    security(("session" = []), ("accessToken" = ["manageShares"])),
+)]
+#[debug_handler]
+pub async fn post(
+    State(state): State<AppState>,
+    session: ScopedSession,
+    Query(query): Query<DomainQuery>,
+) -> Response<CustomDomain> {
+    let session = session.require_scope(Scope::ManageShares)?;
+
+    let Some(domain) = db::transaction!(state.db_pool, async |tx| -> TxResult<_, api::Error> {
+        Ok(sqlx::query!(
+            "SELECT folder_id, challenge_token, verified_at IS NOT NULL as verified
+                FROM custom_domains
+                WHERE owner_id = $1 AND domain = $2",
+            session.user_id,
+            query.domain.as_str(),
+        )
+        .fetch_optional(tx.as_mut())
+        .await?)
+    })
+    .await?
+    else {
+        return Err(api::Error::ResourceNotFound);
+    };
+
+    if domain.verified != Some(true) {
+        let challenge_token = Id::from(domain.challenge_token.as_slice()).to_string();
+
+        if !custom_domain::verify(&query.domain, &challenge_token).await {
+            return Err(api::Error::CustomDomainVerificationFailed);
+        }
+
+        db::transaction!(state.db_pool, async |tx| -> TxResult<_, api::Error> {
+            let result = sqlx::query!(
+                "UPDATE custom_domains
+                    SET verified_at = now()
+                    WHERE owner_id = $1 AND domain = $2",
+                session.user_id,
+                query.domain.as_str(),
+            )
+            .execute(tx.as_mut())
+            .await;
+
+            match result {
+                Err(sqlx::Error::Database(error))
+                    if error.constraint() == Some("verified_custom_domains") =>
+                {
+                    Err(TxError::Abort(api::Error::CustomDomainTaken))
+                }
+                Ok(result) if result.rows_affected() == 0 => {
+                    Err(TxError::Abort(api::Error::ResourceNotFound))
+                }
+                result => {
+                    result?;
+                    Ok(())
+                }
+            }
+        })
+        .await?;
+
+        custom_domain::invalidate(&query.domain).await;
+    }
+
+    Ok((
+        StatusCode::OK,
+        Json(CustomDomain::new(
+            query.domain.into_inner(),
+            domain.folder_id.into(),
+            &domain.challenge_token,
+            true,
+        )),
+    ))
+}
@@ -1,21 +1,28 @@
 //! The set of email verification requests for new users.
 
+use std::net::IpAddr;
+
 use axum::{extract::State, http::StatusCode};
 use axum_macros::debug_handler;
 use lettre::message::Mailbox;
 use serde::{Deserialize, Serialize};
-use sqlx::Acquire;
+use sqlx::{Acquire, PgConnection};
+use utoipa::ToSchema;
 
 use crate::{
     api::{
         self, captcha,
+        rate_limit::{ip_subject, RateLimit},
         validation::{CaptchaToken, EmailVerificationCode, UserEmail},
         Json, Query, Response,
     },
+    audit,
+    client_ip::ClientIp,
     crypto::{hash_without_salt, verify_hash},
     db::{self, TxResult},
     email::{EmailTakenMessage, MessageTemplate, SendMessage, VerificationMessage},
     id::Token,
+    locale::{Locale, PreferredLocale},
     AppState, WEBSITE_ORIGIN,
 };
 
@@ -46,9 +53,35 @@ pub enum GetQuery {
 /// # Errors
 ///
 /// See [`crate::api::Error`].
+#[utoipa::path(
+    get,
+    path = "/api/v1/email-verification",
+    params(
+        (
+            "token" = Option<Token>,
+            Query,
+            description = "The email verification token. Required unless `email` and `code` are \
+                specified.",
+        ),
+        (
+            "email" = Option<UserEmail>,
+            Query,
+            description = "The email address to verify. Required with `code` unless `token` is \
+                specified.",
+        ),
+        (
+            "code" = Option<EmailVerificationCode>,
+            Query,
+            description = "The email verification code. Required with `email` unless `token` is \
+                specified.",
+        ),
+    ),
+    responses((status = OK, description = "The request exists.", body = GetResponse)),
+)]
 #[debug_handler]
 pub async fn get(
     State(state): State<AppState>,
+    ClientIp(client_ip): ClientIp,
     Query(query): Query<GetQuery>,
 ) -> Response<GetResponse> {
     let email = match query {
@@ -74,23 +107,25 @@ pub async fn get(
         }
 
         GetQuery::EmailAndCode { email, code } => {
-            let Some(unverified_email) =
+            // Verification codes are short, so we don't want them to be brute-forced.
+            RateLimit::CODE_PER_IP
+                .check(&state, ip_subject(client_ip))
+                .await?;
+            RateLimit::CODE_PER_EMAIL
+                .check(&state, email.as_str().to_lowercase())
+                .await?;
+
+            let code_check =
                 db::transaction!(state.db_pool, async |tx| -> TxResult<_, api::Error> {
-                    Ok(sqlx::query!(
-                        r#"SELECT email, code_hash as "code_hash!" FROM unverified_emails
-                            WHERE user_id IS NULL AND email = $1 AND code_hash IS NOT NULL"#,
-                        email.as_str(),
-                    )
-                    .fetch_optional(tx.as_mut())
-                    .await?)
+                    Ok(check_code(tx.as_mut(), &email, &code, client_ip).await?)
                 })
-                .await?
-                .filter(|unverified_email| verify_hash(&code, &unverified_email.code_hash))
-            else {
-                return Err(api::Error::ResourceNotFound);
-            };
+                .await?;
 
-            unverified_email.email
+            match code_check {
+                CodeCheck::Correct { email } => email,
+                CodeCheck::Locked => return Err(api::Error::EmailVerificationCodeLocked),
+                CodeCheck::Wrong | CodeCheck::Missing => return Err(api::Error::ResourceNotFound),
+            }
         }
     };
 
@@ -98,21 +133,24 @@ pub async fn get(
 }
 
 /// A `GET` response body for this API route.
-#[derive(Serialize, Debug)]
+#[derive(Serialize, ToSchema, Debug)]
 #[serde(rename_all = "camelCase")]
+#[schema(as = email_verification::GetResponse)]
 pub struct GetResponse {
     /// The email address to verify.
     pub email: String,
 }
 
 /// A `POST` request body for this API route.
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, ToSchema, Debug)]
 #[serde(rename_all = "camelCase", deny_unknown_fields)]
+#[schema(as = email_verification::PostRequest)]
 pub struct PostRequest {
     /// The email address to verify.
     pub email: UserEmail,
 
-    /// A token to verify this request was submitted manually.
+    /// A token to verify this request was submitted manually. With Turnstile, it must be solved
+    /// with the action `email_verification`.
     pub captcha_token: CaptchaToken,
 }
 
@@ -121,19 +159,32 @@ pub struct PostRequest {
 /// # Errors
 ///
 /// See [`crate::api::Error`].
+#[utoipa::path(
+    post,
+    path = "/api/v1/email-verification",
+    request_body = PostRequest,
+    responses((status = OK, description = "The request was accepted.", body = PostResponse)),
+)]
 #[debug_handler]
 pub async fn post(
     State(state): State<AppState>,
+    ClientIp(client_ip): ClientIp,
+    PreferredLocale(locale): PreferredLocale,
     Json(body): Json<PostRequest>,
 ) -> Response<PostResponse> {
+    RateLimit::EMAIL_PER_IP
+        .check(&state, ip_subject(client_ip))
+        .await?;
+    RateLimit::EMAIL_PER_RECIPIENT
+        .check(&state, body.email.as_str().to_lowercase())
+        .await?;
+
     // We don't want bots creating accounts or spamming people with verification emails.
-    if !captcha::verify(&body.captcha_token).await? {
-        return Err(api::Error::CaptchaFailed);
-    }
+    captcha::verify(&body.captcha_token, "email_verification", client_ip).await?;
 
     db::transaction!(state.db_pool, async |tx| -> TxResult<_, api::Error> {
         let existing_user = sqlx::query!(
-            "SELECT name FROM users
+            "SELECT name, locale FROM users
                 WHERE email = $1",
             body.email.as_str(),
         )
@@ -143,9 +194,12 @@ pub async fn post(
         if let Some(user) = existing_user {
             EmailTakenMessage {
                 email: body.email.as_str(),
+                website_origin: &WEBSITE_ORIGIN,
+                locale: Locale::from_stored(&user.locale),
             }
             .to(Mailbox::new(Some(user.name), (*body.email).clone()))
-            .send();
+            .send(tx.as_mut())
+            .await?;
 
             return Ok(());
         }
@@ -193,9 +247,12 @@ pub async fn post(
         VerificationMessage {
             email: body.email.as_str(),
             verification_url: &format!("{}/verify-email?token={}", *WEBSITE_ORIGIN, token),
+            website_origin: &WEBSITE_ORIGIN,
+            locale,
         }
         .to(Mailbox::new(None, (*body.email).clone()))
-        .send();
+        .send(tx.as_mut())
+        .await?;
 
         Ok(())
     })
@@ -206,9 +263,268 @@ pub async fn post(
 }
 
 /// A `POST` response body for this API route.
-#[derive(Serialize, Debug)]
+#[derive(Serialize, ToSchema, Debug)]
 #[serde(rename_all = "camelCase")]
+#[schema(as = email_verification::PostResponse)]
 pub struct PostResponse {
     /// The email address to verify.
     pub email: UserEmail,
 }
+
+/// The maximum number of incorrect attempts to enter an email verification code before the code is
+/// invalidated and a new one must be generated.
+const MAX_CODE_ATTEMPTS: i32 = 5;
+
+/// The result of [`check_code`].
+#[derive(Debug)]
+pub(crate) enum CodeCheck {
+    /// The code is correct.
+    Correct {
+        /// The email address to verify, as stored.
+        email: String,
+    },
+
+    /// The code is incorrect.
+    Wrong,
+
+    /// The code was invalidated by too many incorrect attempts, so a new code must be generated.
+    Locked,
+
+    /// There's no email verification request with a code for the email address.
+    Missing,
+}
+
+/// Checks an email verification code for a new user's email verification request, recording the
+/// attempt in the audit log. Each incorrect attempt is counted, and the code is invalidated after
+/// [`MAX_CODE_ATTEMPTS`] of them.
+///
+/// The attempt is recorded even if the caller fails afterward, as long as the transaction is
+/// committed, so callers must commit it for incorrect attempts too.
+///
+/// # Errors
+///
+/// Returns an error if a database query fails.
+pub(crate) async fn check_code(
+    conn: &mut PgConnection,
+    email: &UserEmail,
+    code: &EmailVerificationCode,
+    ip_address: IpAddr,
+) -> sqlx::Result<CodeCheck> {
+    let Some(unverified_email) = sqlx::query!(
+        "SELECT email, code_hash, code_attempts FROM unverified_emails
+            WHERE user_id IS NULL AND email = $1",
+        email.as_str(),
+    )
+    .fetch_optional(&mut *conn)
+    .await?
+    else {
+        return Ok(CodeCheck::Missing);
+    };
+
+    let mut entry = audit::Entry {
+        event: audit::Event::EmailVerificationCodeLocked,
+        email: Some(&unverified_email.email),
+        ip_address: Some(ip_address),
+    };
+
+    let Some(code_hash) = unverified_email.code_hash else {
+        if unverified_email.code_attempts < MAX_CODE_ATTEMPTS {
+            return Ok(CodeCheck::Missing);
+        }
+
+        audit::record(conn, entry).await?;
+        return Ok(CodeCheck::Locked);
+    };
+
+    if verify_hash(code, &code_hash) {
+        entry.event = audit::Event::EmailVerificationCodeCorrect;
+        audit::record(conn, entry).await?;
+
+        return Ok(CodeCheck::Correct {
+            email: unverified_email.email,
+        });
+    }
+
+    let is_locked = sqlx::query_scalar!(
+        r#"UPDATE unverified_emails
+            SET code_attempts = code_attempts + 1,
+                code_hash = CASE WHEN code_attempts + 1 >= $2 THEN NULL ELSE code_hash END
+            WHERE user_id IS NULL AND email = $1
+            RETURNING code_hash IS NULL as "is_locked!""#,
+        email.as_str(),
+        MAX_CODE_ATTEMPTS,
+    )
+    .fetch_one(&mut *conn)
+    .await?;
+
+    if is_locked {
+        audit::record(conn, entry).await?;
+        return Ok(CodeCheck::Locked);
+    }
+
+    entry.event = audit::Event::EmailVerificationCodeWrong;
+    audit::record(conn, entry).await?;
+
+    Ok(CodeCheck::Wrong)
+}
+
+#[cfg(test)]
+#[expect(clippy::missing_errors_doc, reason = "see rust-lang/rust-clippy#13391")]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use sqlx::PgPool;
+
+    use super::*;
+    use crate::crypto::generate_short_code;
+
+    /// The email address verified in these tests.
+    const EMAIL: &str = "lockout@example.com";
+
+    /// The IP address of the client in these tests. No other test uses it, so its rate limits
+    /// aren't shared.
+    const CLIENT_IP: IpAddr = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 32));
+
+    /// Creates an email verification request for [`EMAIL`], returning its token.
+    async fn create_request(pool: &PgPool) -> sqlx::Result<Token> {
+        let token = Token::generate();
+
+        sqlx::query("INSERT INTO unverified_emails (token_hash, email) VALUES ($1, $2)")
+            .bind(hash_without_salt(&token).as_ref())
+            .bind(EMAIL)
+            .execute(pool)
+            .await?;
+
+        Ok(token)
+    }
+
+    /// Generates a new code for the email verification request with the specified token.
+    async fn generate_code(pool: &PgPool, token: &Token) -> anyhow::Result<String> {
+        let (_, Json(response)) = code::post(
+            State(AppState {
+                db_pool: pool.clone(),
+            }),
+            Query(code::PostQuery {
+                token: token.clone(),
+            }),
+        )
+        .await
+        // `api::Error` isn't `Send`, so it can't be converted into an `anyhow::Error` directly.
+        .map_err(|error| anyhow::anyhow!("{error:?}"))?;
+
+        Ok(response.code)
+    }
+
+    /// Checks a code for [`EMAIL`] through this API route, returning the error code if it's
+    /// rejected.
+    async fn check(pool: &PgPool, code: &str) -> anyhow::Result<Result<(), &'static str>> {
+        let query = GetQuery::EmailAndCode {
+            email: EMAIL.parse()?,
+            code: code.to_owned().try_into()?,
+        };
+
+        Ok(get(
+            State(AppState {
+                db_pool: pool.clone(),
+            }),
+            ClientIp(CLIENT_IP),
+            Query(query),
+        )
+        .await
+        .map(|_| ())
+        .map_err(|error| error.code()))
+    }
+
+    /// Gets the number of incorrect attempts at the request's code, and whether it still has one.
+    async fn code_state(pool: &PgPool) -> sqlx::Result<(i32, bool)> {
+        sqlx::query_as(
+            "SELECT code_attempts, code_hash IS NOT NULL FROM unverified_emails WHERE email = $1",
+        )
+        .bind(EMAIL)
+        .fetch_one(pool)
+        .await
+    }
+
+    #[sqlx::test]
+    #[ignore = "requires a database at `DATABASE_URL`"]
+    async fn codes_locked_after_max_attempts(pool: PgPool) -> anyhow::Result<()> {
+        let token = create_request(&pool).await?;
+        let code = generate_code(&pool, &token).await?;
+
+        let wrong_code = loop {
+            let wrong_code = generate_short_code();
+            if wrong_code != code {
+                break wrong_code;
+            }
+        };
+
+        // Each incorrect attempt should be committed even though the request fails.
+        for attempts in 1..MAX_CODE_ATTEMPTS {
+            assert_eq!(
+                check(&pool, &wrong_code).await?,
+                Err("RESOURCE_NOT_FOUND"),
+                "attempt {attempts}",
+            );
+            assert_eq!(
+                code_state(&pool).await?,
+                (attempts, true),
+                "attempt {attempts}"
+            );
+        }
+
+        assert_eq!(
+            check(&pool, &wrong_code).await?,
+            Err("EMAIL_VERIFICATION_CODE_LOCKED"),
+        );
+        assert_eq!(code_state(&pool).await?, (MAX_CODE_ATTEMPTS, false));
+
+        // Once the code is invalidated, even the correct code should be rejected.
+        assert_eq!(
+            check(&pool, &code).await?,
+            Err("EMAIL_VERIFICATION_CODE_LOCKED"),
+        );
+        assert_eq!(code_state(&pool).await?, (MAX_CODE_ATTEMPTS, false));
+
+        let events: Vec<String> =
+            sqlx::query_scalar("SELECT event FROM audit_log ORDER BY created_at")
+                .fetch_all(&pool)
+                .await?;
+        assert_eq!(
+            events,
+            [
+                "email_verification_code_wrong",
+                "email_verification_code_wrong",
+                "email_verification_code_wrong",
+                "email_verification_code_wrong",
+                "email_verification_code_locked",
+                "email_verification_code_locked",
+            ],
+        );
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    #[ignore = "requires a database at `DATABASE_URL`"]
+    async fn regenerated_codes_reset_attempts(pool: PgPool) -> anyhow::Result<()> {
+        let token = create_request(&pool).await?;
+        let old_code = generate_code(&pool, &token).await?;
+
+        sqlx::query("UPDATE unverified_emails SET code_attempts = $1, code_hash = NULL")
+            .bind(MAX_CODE_ATTEMPTS)
+            .execute(&pool)
+            .await?;
+
+        let code = generate_code(&pool, &token).await?;
+        assert_eq!(code_state(&pool).await?, (0, true));
+
+        if code != old_code {
+            assert_eq!(check(&pool, &old_code).await?, Err("RESOURCE_NOT_FOUND"));
+            assert_eq!(code_state(&pool).await?, (1, true));
+        }
+
+        assert_eq!(check(&pool, &code).await?, Ok(()));
+
+        Ok(())
+    }
+}
@@ -3,6 +3,7 @@
 use axum::{extract::State, http::StatusCode};
 use axum_macros::debug_handler;
 use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
 
 use crate::{
     api::{self, Json, Query, Response},
@@ -13,18 +14,26 @@ use crate::{
 };
 
 /// A `POST` request query for this API route.
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, IntoParams, Debug)]
 #[serde(rename_all = "camelCase")]
+#[into_params(parameter_in = Query)]
 pub struct PostQuery {
     /// The email verification token.
     pub token: Token,
 }
 
-/// Generates a new email verification code for a new user.
+/// Generates a new email verification code for a new user, replacing any previous code (including
+/// one invalidated by too many incorrect attempts).
 ///
 /// # Errors
 ///
 /// See [`crate::api::Error`].
+#[utoipa::path(
+    post,
+    path = "/api/v1/email-verification/code",
+    params(PostQuery),
+    responses((status = OK, description = "The new code was generated.", body = PostResponse)),
+)]
 #[debug_handler]
 pub async fn post(
     State(state): State<AppState>,
@@ -39,7 +48,7 @@ pub async fn post(
         db::transaction!(state.db_pool, async |tx| -> TxResult<_, api::Error> {
             Ok(sqlx::query!(
                 "UPDATE unverified_emails
-                    SET code_hash = $1
+                    SET code_hash = $1, code_attempts = 0
                     WHERE token_hash = $2 AND user_id IS NULL
                     RETURNING email",
                 code_hash,
@@ -63,8 +72,9 @@ pub async fn post(
 }
 
 /// A `POST` response body for this API route.
-#[derive(Serialize, Debug)]
+#[derive(Serialize, ToSchema, Debug)]
 #[serde(rename_all = "camelCase")]
+#[schema(as = email_verification::code::PostResponse)]
 pub struct PostResponse {
     /// The email address to verify.
     pub email: String,
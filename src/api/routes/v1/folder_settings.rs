@@ -0,0 +1,170 @@
+//! The settings of a user's folder.
+
+use std::collections::BTreeMap;
+
+use axum::{extract::State, http::StatusCode};
+use axum_macros::debug_handler;
+use serde::{Deserialize, Serialize};
+use sqlx::types::Json as SqlJson;
+use utoipa::{IntoParams, ToSchema};
+
+use crate::{
+    api::{
+        self,
+        session::{Scope, ScopedSession},
+        validation::SiteHeaders,
+        Json, Query, Response,
+    },
+    db::{self, TxError, TxResult},
+    id::Id,
+    AppState,
+};
+
+/// A request query for this API route.
+#[derive(Deserialize, IntoParams, Debug)]
+#[serde(rename_all = "camelCase")]
+#[into_params(parameter_in = Query)]
+pub struct FolderQuery {
+    /// The folder's ID.
+    pub id: Id,
+}
+
+/// Gets the settings of one of the signed-in user's folders.
+///
+/// # Errors
+///
+/// See [`crate::api::Error`].
+#[utoipa::path(
+    get,
+    path = "/api/v1/folder-settings",
+    params(FolderQuery),
+    responses((status = OK, description = "The folder's settings.", body = FolderSettings)),
+    security(("session" = []), ("accessToken" = ["readFiles"])),
+)]
+#[debug_handler]
+pub async fn get(
+    State(state): State<AppState>,
+    session: ScopedSession,
+    Query(query): Query<FolderQuery>,
+) -> Response<FolderSettings> {
+    let session = session.require_scope(Scope::ReadFiles)?;
+
+    let Some(settings) = db::transaction!(state.db_pool, async |tx| -> TxResult<_, api::Error> {
+        Ok(sqlx::query_as!(
+            FolderSettings,
+            r#"SELECT index_enabled, site_enabled, site_clean_urls,
+                site_headers as "site_headers: SqlJson<BTreeMap<String, String>>"
+                FROM folders
+                WHERE id = $1 AND owner_id = $2"#,
+            query.id.as_slice(),
+            session.user_id,
+        )
+        .fetch_optional(tx.as_mut())
+        .await?)
+    })
+    .await?
+    else {
+        return Err(api::Error::ResourceNotFound);
+    };
+
+    Ok((StatusCode::OK, Json(settings)))
+}
+
+/// A `PATCH` request body for this API route. Settings left unset are unchanged.
+#[derive(Deserialize, ToSchema, Debug)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+#[schema(as = folder_settings::PatchRequest)]
+pub struct PatchRequest {
+    /// Whether the folder's index is served publicly by the content server. See [`FolderSettings`].
+    pub index_enabled: Option<bool>,
+
+    /// Whether the folder is served as a static site. See [`FolderSettings`].
+    pub site_enabled: Option<bool>,
+
+    /// Whether the folder's site resolves clean URLs. See [`FolderSettings`].
+    pub site_clean_urls: Option<bool>,
+
+    /// The custom headers set on responses from the folder's site. See [`FolderSettings`].
+    pub site_headers: Option<SiteHeaders>,
+}
+
+/// Changes the settings of one of the signed-in user's folders.
+///
+/// # Errors
+///
+/// See [`crate::api::Error`].
+#[utoipa::path(
+    patch,
+    path = "/api/v1/folder-settings",
+    params(FolderQuery),
+    request_body = PatchRequest,
+    responses((status = OK, description = "The folder's updated settings.", body = FolderSettings)),
+    security(("session" = []), ("accessToken" = ["manageShares"])),
+)]
+#[debug_handler]
+pub async fn patch(
+    State(state): State<AppState>,
+    session: ScopedSession,
+    Query(query): Query<FolderQuery>,
+    Json(body): Json<PatchRequest>,
+) -> Response<FolderSettings> {
+    let session = session.require_scope(Scope::ManageShares)?;
+
+    let site_headers = body
+        .site_headers
+        .map(|headers| SqlJson(headers.into_inner()));
+
+    let settings = db::transaction!(state.db_pool, async |tx| -> TxResult<_, api::Error> {
+        let Some(settings) = sqlx::query_as!(
+            FolderSettings,
+            r#"UPDATE folders
+                SET index_enabled = COALESCE($1, index_enabled),
+                    site_enabled = COALESCE($2, site_enabled),
+                    site_clean_urls = COALESCE($3, site_clean_urls),
+                    site_headers = COALESCE($4, site_headers)
+                WHERE id = $5 AND owner_id = $6
+                RETURNING index_enabled, site_enabled, site_clean_urls,
+                    site_headers as "site_headers: SqlJson<BTreeMap<String, String>>""#,
+            body.index_enabled,
+            body.site_enabled,
+            body.site_clean_urls,
+            site_headers as _,
+            query.id.as_slice(),
+            session.user_id,
+        )
+        .fetch_optional(tx.as_mut())
+        .await?
+        else {
+            return Err(TxError::Abort(api::Error::ResourceNotFound));
+        };
+
+        Ok(settings)
+    })
+    .await?;
+
+    Ok((StatusCode::OK, Json(settings)))
+}
+
+/// A folder's settings, as returned by this API route.
+#[derive(Serialize, ToSchema, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct FolderSettings {
+    /// Whether the folder's index is served publicly by the content server when its URL is visited.
+    /// The index is the folder's shared `index.html` file if it has one, or else a generated page
+    /// listing its shared files and its subfolders that also have their indexes enabled.
+    pub index_enabled: bool,
+
+    /// Whether the folder is served by the content server as a static site. All of a site's
+    /// contents are public, regardless of whether they're shared. Folder URLs serve their
+    /// `index.html` files, and URLs that aren't found serve the site's `404.html` file if it has
+    /// one. Sites are still served with the content server's strict `Content-Security-Policy`.
+    pub site_enabled: bool,
+
+    /// Whether extensionless URLs in the folder's site that aren't found resolve to `.html` files,
+    /// so `/about` serves `about.html`.
+    pub site_clean_urls: bool,
+
+    /// The custom headers set on every response from the folder's site. See [`SiteHeaders`].
+    #[schema(value_type = SiteHeaders)]
+    pub site_headers: SqlJson<BTreeMap<String, String>>,
+}
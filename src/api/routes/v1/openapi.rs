@@ -0,0 +1,235 @@
+//! The OpenAPI document describing this version of the API, generated from its routes' types so
+//! clients can generate typed API clients from it.
+
+use std::sync::LazyLock;
+
+use axum_macros::debug_handler;
+use utoipa::{
+    openapi::{
+        security::{ApiKey, ApiKeyValue, HttpAuthScheme, HttpBuilder, SecurityScheme},
+        ContentBuilder, OpenApi as Document, Ref, ResponseBuilder,
+    },
+    Modify, OpenApi,
+};
+
+use super::{
+    access_tokens, batch, custom_domains, email_verification, folder_settings, password_reset,
+    sessions, user_settings, users,
+};
+use crate::api::{ErrorBody, Json};
+
+/// The generated OpenAPI document. It never changes while the server is running.
+static DOCUMENT: LazyLock<Document> = LazyLock::new(ApiDoc::openapi);
+
+/// The prefix of every path in this version of the API.
+const PATH_PREFIX: &str = "/api/v1/";
+
+/// The name of the error response every operation can fail with.
+const ERROR_RESPONSE: &str = "Error";
+
+/// The name of the security scheme for sign-in sessions.
+const SESSION_SECURITY_SCHEME: &str = "session";
+
+/// The name of the security scheme for access tokens. Each route's requirement for it lists the
+/// scopes the access token needs.
+const ACCESS_TOKEN_SECURITY_SCHEME: &str = "accessToken";
+
+/// The definition of the OpenAPI document.
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "File Garden API",
+        version = "1",
+        description = "The HTTP API for File Garden, served from `https://filegarden.com/api/`.",
+    ),
+    paths(
+        access_tokens::get,
+        access_tokens::post,
+        access_tokens::delete,
+        batch::post,
+        custom_domains::get,
+        custom_domains::post,
+        custom_domains::delete,
+        custom_domains::verification::post,
+        email_verification::get,
+        email_verification::post,
+        email_verification::code::post,
+        folder_settings::get,
+        folder_settings::patch,
+        password_reset::get,
+        password_reset::post,
+        password_reset::password::post,
+        sessions::post,
+        sessions::delete,
+        user_settings::get,
+        user_settings::patch,
+        users::post,
+    ),
+    components(schemas(ErrorBody)),
+    modifiers(&RouteDetails),
+)]
+struct ApiDoc;
+
+/// Fills in what can't be derived from each route: the components shared by every route (the
+/// security schemes and an error response that every operation can return), and each operation's
+/// ID and tag.
+struct RouteDetails;
+
+impl Modify for RouteDetails {
+    fn modify(&self, document: &mut Document) {
+        // The package doesn't specify a license, so Cargo's empty one would be invalid here.
+        document.info.license = None;
+
+        let components = document.components.get_or_insert_with(Default::default);
+
+        components.add_security_scheme(
+            SESSION_SECURITY_SCHEME,
+            SecurityScheme::ApiKey(ApiKey::Cookie(ApiKeyValue::with_description(
+                "token",
+                "The session cookie set by signing in.",
+            ))),
+        );
+        components.add_security_scheme(
+            ACCESS_TOKEN_SECURITY_SCHEME,
+            SecurityScheme::Http(
+                HttpBuilder::new()
+                    .scheme(HttpAuthScheme::Bearer)
+                    .description(Some(
+                        "An access token created through `/api/v1/access-tokens`.",
+                    ))
+                    .build(),
+            ),
+        );
+
+        components.responses.insert(
+            ERROR_RESPONSE.into(),
+            ResponseBuilder::new()
+                .description("An API error. See the error code for details.")
+                .content(
+                    "application/json",
+                    ContentBuilder::new()
+                        .schema(Some(Ref::from_schema_name("ErrorBody")))
+                        .build(),
+                )
+                .into(),
+        );
+
+        for (path, path_item) in &mut document.paths.paths {
+            let resource = path.strip_prefix(PATH_PREFIX).unwrap_or(path);
+            let tag = resource.split('/').next().unwrap_or(resource);
+
+            let operations = [
+                ("get", &mut path_item.get),
+                ("post", &mut path_item.post),
+                ("patch", &mut path_item.patch),
+                ("delete", &mut path_item.delete),
+            ];
+
+            for (method, operation) in operations {
+                let Some(operation) = operation else {
+                    continue;
+                };
+
+                operation.operation_id = Some(operation_id(method, resource));
+                operation.tags = Some(vec![tag.into()]);
+
+                // Every handler's doc comment ends with an `# Errors` section linking to the Rust
+                // docs, which is replaced by the error response here.
+                operation.description = operation.description.take().and_then(|description| {
+                    let description = description
+                        .split("# Errors")
+                        .next()
+                        .unwrap_or_default()
+                        .trim_end();
+
+                    (!description.is_empty()).then(|| description.to_owned())
+                });
+
+                operation.responses.responses.insert(
+                    "default".into(),
+                    Ref::from_response_name(ERROR_RESPONSE).into(),
+                );
+            }
+        }
+    }
+}
+
+/// Gets the operation ID for a method on an API resource in `camelCase`. For example, `POST` on
+/// `custom-domains/verification` is `postCustomDomainsVerification`.
+fn operation_id(method: &str, resource: &str) -> String {
+    let mut operation_id = method.to_owned();
+
+    for word in resource.split(['/', '-']) {
+        let mut chars = word.chars();
+
+        if let Some(first_char) = chars.next() {
+            operation_id.extend(first_char.to_uppercase());
+            operation_id.push_str(chars.as_str());
+        }
+    }
+
+    operation_id
+}
+
+/// Gets the OpenAPI document describing this version of the API.
+#[debug_handler]
+pub async fn get() -> Json<&'static Document> {
+    Json(&DOCUMENT)
+}
+
+#[cfg(test)]
+#[expect(clippy::missing_errors_doc, reason = "see rust-lang/rust-clippy#13391")]
+mod tests {
+    use utoipa::openapi::{RefOr, Schema};
+
+    use super::*;
+
+    #[test]
+    fn references_resolve() -> anyhow::Result<()> {
+        let json = DOCUMENT.to_json()?;
+        let components = DOCUMENT
+            .components
+            .as_ref()
+            .expect("document should have components");
+
+        for reference in json.split(r#""$ref":""#).skip(1) {
+            let reference = reference.split('"').next().unwrap_or_default();
+
+            let resolves = if let Some(name) = reference.strip_prefix("#/components/schemas/") {
+                components.schemas.contains_key(name)
+            } else if let Some(name) = reference.strip_prefix("#/components/responses/") {
+                components.responses.contains_key(name)
+            } else {
+                false
+            };
+
+            assert!(resolves, "reference {reference:?} should resolve");
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn validation_bounds_included() {
+        let components = DOCUMENT
+            .components
+            .as_ref()
+            .expect("document should have components");
+
+        let cases = [
+            ("UserName", Some(1), Some(64)),
+            ("NewUserPassword", Some(8), Some(256)),
+            ("EmailVerificationCode", Some(6), Some(6)),
+            ("UserEmail", None, Some(254)),
+        ];
+
+        for (name, min_length, max_length) in cases {
+            let Some(RefOr::T(Schema::Object(schema))) = components.schemas.get(name) else {
+                panic!("schema {name:?} should be an object schema");
+            };
+
+            assert_eq!(schema.min_length, min_length, "{name} minimum length");
+            assert_eq!(schema.max_length, max_length, "{name} maximum length");
+        }
+    }
+}
@@ -5,25 +5,30 @@ use axum_macros::debug_handler;
 use lettre::message::Mailbox;
 use serde::{Deserialize, Serialize};
 use sqlx::Acquire;
+use utoipa::{IntoParams, ToSchema};
 
 use crate::{
     api::{
         self, captcha,
+        rate_limit::{ip_subject, RateLimit},
         validation::{CaptchaToken, UserEmail},
         Json, Query, Response,
     },
+    client_ip::ClientIp,
     crypto::hash_without_salt,
     db::{self, TxResult},
     email::{MessageTemplate, PasswordResetFailedMessage, PasswordResetMessage, SendMessage},
     id::Token,
+    locale::{Locale, PreferredLocale},
     AppState, WEBSITE_ORIGIN,
 };
 
 pub mod password;
 
 /// A `GET` request query for this API route.
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, IntoParams, Debug)]
 #[serde(rename_all = "camelCase")]
+#[into_params(parameter_in = Query)]
 pub struct GetQuery {
     /// The password reset token.
     token: Token,
@@ -34,6 +39,12 @@ pub struct GetQuery {
 /// # Errors
 ///
 /// See [`crate::api::Error`].
+#[utoipa::path(
+    get,
+    path = "/api/v1/password-reset",
+    params(GetQuery),
+    responses((status = OK, description = "The request exists.", body = GetResponse)),
+)]
 #[debug_handler]
 pub async fn get(
     State(state): State<AppState>,
@@ -66,21 +77,24 @@ pub async fn get(
 }
 
 /// A `GET` response body for this API route.
-#[derive(Serialize, Debug)]
+#[derive(Serialize, ToSchema, Debug)]
 #[serde(rename_all = "camelCase")]
+#[schema(as = password_reset::GetResponse)]
 pub struct GetResponse {
     /// The email of the user whose password reset was requested.
     pub email: String,
 }
 
 /// A `POST` request body for this API route.
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, ToSchema, Debug)]
 #[serde(rename_all = "camelCase", deny_unknown_fields)]
+#[schema(as = password_reset::PostRequest)]
 pub struct PostRequest {
     /// The email address of the user to request a password reset for.
     pub email: UserEmail,
 
-    /// A token to verify this request was submitted manually.
+    /// A token to verify this request was submitted manually. With Turnstile, it must be solved
+    /// with the action `password_reset`.
     pub captcha_token: CaptchaToken,
 }
 
@@ -90,19 +104,32 @@ pub struct PostRequest {
 /// # Errors
 ///
 /// See [`crate::api::Error`].
+#[utoipa::path(
+    post,
+    path = "/api/v1/password-reset",
+    request_body = PostRequest,
+    responses((status = OK, description = "The request was accepted.", body = PostResponse)),
+)]
 #[debug_handler]
 pub async fn post(
     State(state): State<AppState>,
+    ClientIp(client_ip): ClientIp,
+    PreferredLocale(locale): PreferredLocale,
     Json(body): Json<PostRequest>,
 ) -> Response<PostResponse> {
+    RateLimit::EMAIL_PER_IP
+        .check(&state, ip_subject(client_ip))
+        .await?;
+    RateLimit::EMAIL_PER_RECIPIENT
+        .check(&state, body.email.as_str().to_lowercase())
+        .await?;
+
     // We don't want bots spamming people with password reset emails.
-    if !captcha::verify(&body.captcha_token).await? {
-        return Err(api::Error::CaptchaFailed);
-    }
+    captcha::verify(&body.captcha_token, "password_reset", client_ip).await?;
 
     db::transaction!(state.db_pool, async |tx| -> TxResult<_, api::Error> {
         let Some(user) = sqlx::query!(
-            "SELECT id, name FROM users
+            "SELECT id, name, locale FROM users
                 WHERE email = $1",
             body.email.as_str(),
         )
@@ -111,9 +138,12 @@ pub async fn post(
         else {
             PasswordResetFailedMessage {
                 email: body.email.as_str(),
+                website_origin: &WEBSITE_ORIGIN,
+                locale,
             }
             .to(Mailbox::new(None, (*body.email).clone()))
-            .send();
+            .send(tx.as_mut())
+            .await?;
 
             return Ok(());
         };
@@ -161,9 +191,12 @@ pub async fn post(
         PasswordResetMessage {
             email: body.email.as_str(),
             password_reset_url: &format!("{}/password-reset?token={}", *WEBSITE_ORIGIN, token),
+            website_origin: &WEBSITE_ORIGIN,
+            locale: Locale::from_stored(&user.locale),
         }
         .to(Mailbox::new(Some(user.name), (*body.email).clone()))
-        .send();
+        .send(tx.as_mut())
+        .await?;
 
         Ok(())
     })
@@ -175,6 +208,7 @@ pub async fn post(
 }
 
 /// A `POST` response body for this API route.
-#[derive(Serialize, Debug)]
+#[derive(Serialize, ToSchema, Debug)]
 #[serde(rename_all = "camelCase")]
+#[schema(as = password_reset::PostResponse)]
 pub struct PostResponse {}
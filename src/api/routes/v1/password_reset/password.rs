@@ -1,41 +1,62 @@
 //! The new password for a user's password reset request.
 
-use axum::{extract::State, http::StatusCode};
+use axum::{
+    extract::State,
+    http::{HeaderMap, StatusCode},
+};
 use axum_macros::debug_handler;
+use chrono::Utc;
+use lettre::{message::Mailbox, Address};
 use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
 
 use crate::{
     api::{self, validation::NewUserPassword, Json, Query, Response},
+    client_ip::ClientIp,
     crypto::{hash_with_salt, hash_without_salt},
     db::{self, TxError, TxResult},
+    email::{ClientInfo, MessageTemplate, PasswordChangedMessage, SendMessage},
     id::Token,
-    AppState,
+    locale::Locale,
+    AppState, WEBSITE_ORIGIN,
 };
 
 /// A `POST` request query for this API route.
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, IntoParams, Debug)]
 #[serde(rename_all = "camelCase")]
+#[into_params(parameter_in = Query)]
 pub struct PostQuery {
     /// The password reset token.
     pub token: Token,
 }
 
 /// A `POST` request body for this API route.
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, ToSchema, Debug)]
 #[serde(rename_all = "camelCase", deny_unknown_fields)]
+#[schema(as = password_reset::password::PostRequest)]
 pub struct PostRequest {
     /// The user's new password in plain text.
     pub password: NewUserPassword,
 }
 
-/// Sets a new password to fulfill a user's password reset request.
+/// Sets a new password to fulfill a user's password reset request, revoking all of the user's access
+/// tokens and notifying the user by email.
 ///
 /// # Errors
 ///
 /// See [`crate::api::Error`].
+#[utoipa::path(
+    post,
+    path = "/api/v1/password-reset/password",
+    params(PostQuery),
+    request_body = PostRequest,
+    responses((status = OK, description = "The password was changed.", body = PostResponse)),
+)]
 #[debug_handler]
 pub async fn post(
     State(state): State<AppState>,
+    ClientIp(client_ip): ClientIp,
+    headers: HeaderMap,
     Query(query): Query<PostQuery>,
     Json(body): Json<PostRequest>,
 ) -> Response<PostResponse> {
@@ -43,6 +64,9 @@ pub async fn post(
 
     let password_hash = hash_with_salt(&body.password);
 
+    let client = ClientInfo::new(client_ip, &headers);
+    let time = Utc::now();
+
     db::transaction!(state.db_pool, async |tx| -> TxResult<_, api::Error> {
         let Some(password_reset) = sqlx::query!(
             "DELETE FROM password_resets
@@ -56,16 +80,42 @@ pub async fn post(
             return Err(TxError::Abort(api::Error::ResourceNotFound));
         };
 
-        sqlx::query!(
-            "UPDATE users
+        let user = sqlx::query!(
+            r#"UPDATE users
                 SET password_hash = $1
-                WHERE id = $2",
+                WHERE id = $2
+                RETURNING name, email::text as "email!", locale"#,
             password_hash,
             password_reset.user_id,
         )
+        .fetch_one(tx.as_mut())
+        .await?;
+
+        // Access tokens could have been created by whoever knew the old password.
+        sqlx::query!(
+            "DELETE FROM access_tokens
+                WHERE user_id = $1",
+            password_reset.user_id,
+        )
         .execute(tx.as_mut())
         .await?;
 
+        let address: Address = match user.email.parse() {
+            Ok(address) => address,
+            Err(error) => return Err(TxError::Abort(api::Error::Internal(error.into()))),
+        };
+
+        PasswordChangedMessage {
+            email: &user.email,
+            time,
+            client: &client,
+            website_origin: &WEBSITE_ORIGIN,
+            locale: Locale::from_stored(&user.locale),
+        }
+        .to(Mailbox::new(Some(user.name), address))
+        .send(tx.as_mut())
+        .await?;
+
         Ok(())
     })
     .await?;
@@ -74,6 +124,7 @@ pub async fn post(
 }
 
 /// A `POST` response body for this API route.
-#[derive(Serialize, Debug)]
+#[derive(Serialize, ToSchema, Debug)]
 #[serde(rename_all = "camelCase")]
+#[schema(as = password_reset::password::PostResponse)]
 pub struct PostResponse {}
@@ -2,24 +2,35 @@
 
 use std::sync::LazyLock;
 
-use axum::{extract::State, http::StatusCode};
+use axum::{
+    extract::State,
+    http::{HeaderMap, StatusCode},
+};
 use axum_macros::debug_handler;
+use chrono::Utc;
+use lettre::message::Mailbox;
 use serde::{Deserialize, Serialize};
 use sqlx::Acquire;
 use tower_cookies::{
     cookie::{time::Duration, SameSite},
     Cookie, Cookies,
 };
+use utoipa::ToSchema;
 
 use crate::{
     api::{
         self,
+        rate_limit::{ip_subject, RateLimit},
+        session::Session,
         validation::{UserEmail, UserPassword},
         Json, Response,
     },
+    client_ip::ClientIp,
     crypto::{hash_without_salt, verify_hash},
     db::{self, TxResult},
+    email::{ClientInfo, MessageTemplate, NewSignInMessage, SendMessage},
     id::Token,
+    locale::Locale,
     AppState, WEBSITE_ORIGIN,
 };
 
@@ -30,8 +41,9 @@ static WEBSITE_DOMAIN: LazyLock<&str> = LazyLock::new(|| domain_from_origin(&WEB
 const SESSION_MAX_AGE: Duration = Duration::days(60);
 
 /// A `POST` request body for this API route.
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, ToSchema, Debug)]
 #[serde(rename_all = "camelCase", deny_unknown_fields)]
+#[schema(as = sessions::PostRequest)]
 pub struct PostRequest {
     /// The email address of the user signing in.
     pub email: UserEmail,
@@ -42,18 +54,44 @@ pub struct PostRequest {
 
 /// Signs a user in, creating a sign-in session and returning a session cookie.
 ///
+/// If none of the user's sessions were created from the same IP address and device, the user is
+/// notified by email.
+///
 /// # Errors
 ///
 /// See [`crate::api::Error`].
+#[utoipa::path(
+    post,
+    path = "/api/v1/sessions",
+    request_body = PostRequest,
+    responses((
+        status = OK,
+        description = "The user was signed in, and the session cookie was set.",
+        body = PostResponse,
+    )),
+)]
 #[debug_handler]
 pub async fn post(
     State(state): State<AppState>,
+    ClientIp(client_ip): ClientIp,
+    headers: HeaderMap,
     cookies: Cookies,
     Json(body): Json<PostRequest>,
 ) -> Response<PostResponse> {
+    // Verifying passwords is intentionally slow, and we don't want passwords to be brute-forced.
+    RateLimit::SIGN_IN_PER_IP
+        .check(&state, ip_subject(client_ip))
+        .await?;
+    RateLimit::SIGN_IN_PER_EMAIL
+        .check(&state, body.email.as_str().to_lowercase())
+        .await?;
+
+    let client = ClientInfo::new(client_ip, &headers);
+    let time = Utc::now();
+
     let token = db::transaction!(state.db_pool, async |tx| -> TxResult<_, api::Error> {
         let Some(user) = sqlx::query!(
-            "SELECT id, password_hash FROM users
+            "SELECT id, name, password_hash, locale FROM users
                 WHERE email = $1",
             body.email.as_str(),
         )
@@ -65,6 +103,22 @@ pub async fn post(
             return Err(db::TxError::Abort(api::Error::UserCredentialsWrong));
         };
 
+        // A client is only known if one of the user's sessions has both its IP address and its user
+        // agent, not if they're each from different sessions.
+        let known_client = sqlx::query_scalar!(
+            r#"SELECT coalesce(
+                bool_or(ip_address = $2 AND user_agent IS NOT DISTINCT FROM $3),
+                false
+            ) as "known_client!"
+                FROM sessions
+                WHERE user_id = $1"#,
+            user.id,
+            client.ip_address.to_string(),
+            client.user_agent,
+        )
+        .fetch_one(tx.as_mut())
+        .await?;
+
         let mut token = Token::generate();
 
         loop {
@@ -75,10 +129,12 @@ pub async fn post(
             let token_hash = hash_without_salt(&token);
 
             match sqlx::query!(
-                "INSERT INTO sessions (token_hash, user_id)
-                    VALUES ($1, $2)",
+                "INSERT INTO sessions (token_hash, user_id, ip_address, user_agent)
+                    VALUES ($1, $2, $3, $4)",
                 token_hash.as_ref(),
                 user.id,
+                client.ip_address.to_string(),
+                client.user_agent,
             )
             .execute(savepoint.as_mut())
             .await
@@ -96,6 +152,19 @@ pub async fn post(
             break;
         }
 
+        if !known_client {
+            NewSignInMessage {
+                email: body.email.as_str(),
+                time,
+                client: &client,
+                website_origin: &WEBSITE_ORIGIN,
+                locale: Locale::from_stored(&user.locale),
+            }
+            .to(Mailbox::new(Some(user.name), (*body.email).clone()))
+            .send(tx.as_mut())
+            .await?;
+        }
+
         Ok(token)
     })
     .await?;
@@ -115,13 +184,73 @@ pub async fn post(
 }
 
 /// A `POST` response body for this API route.
-#[derive(Serialize, Debug)]
+#[derive(Serialize, ToSchema, Debug)]
 #[serde(rename_all = "camelCase")]
+#[schema(as = sessions::PostResponse)]
 pub struct PostResponse {
     // To reduce the session token's attack surface, it isn't included in the response. It's set as
     // an `HttpOnly` cookie instead so browser scripts can't access it.
 }
 
+/// Signs a user out of every one of their sessions, including the current one, and revokes all of
+/// their access tokens.
+///
+/// # Errors
+///
+/// See [`crate::api::Error`].
+#[utoipa::path(
+    delete,
+    path = "/api/v1/sessions",
+    responses((
+        status = OK,
+        description = "The user was signed out and their access tokens were revoked.",
+        body = DeleteResponse,
+    )),
+    security(("session" = [])),
+)]
+#[debug_handler]
+pub async fn delete(
+    State(state): State<AppState>,
+    session: Session,
+    cookies: Cookies,
+) -> Response<DeleteResponse> {
+    db::transaction!(state.db_pool, async |tx| -> TxResult<_, api::Error> {
+        sqlx::query!(
+            "DELETE FROM sessions
+                WHERE user_id = $1",
+            session.user_id,
+        )
+        .execute(tx.as_mut())
+        .await?;
+
+        sqlx::query!(
+            "DELETE FROM access_tokens
+                WHERE user_id = $1",
+            session.user_id,
+        )
+        .execute(tx.as_mut())
+        .await?;
+
+        Ok(())
+    })
+    .await?;
+
+    cookies.remove(
+        Cookie::build(("token", ""))
+            .domain(*WEBSITE_DOMAIN)
+            .path("/")
+            .into(),
+    );
+
+    Ok((StatusCode::OK, Json(DeleteResponse {})))
+}
+
+/// A `DELETE` response body for this API route.
+#[derive(Serialize, ToSchema, Debug)]
+#[serde(rename_all = "camelCase")]
+#[schema(as = sessions::DeleteResponse)]
+pub struct DeleteResponse {}
+
 /// Returns the domain from an origin URI string.
 ///
 /// # Panics
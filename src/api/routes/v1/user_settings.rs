@@ -0,0 +1,115 @@
+//! The settings of the signed-in user.
+
+use axum::{extract::State, http::StatusCode};
+use axum_macros::debug_handler;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::{
+    api::{self, session::Session, Json, Response},
+    db::{self, TxError, TxResult},
+    locale::Locale,
+    AppState,
+};
+
+/// Gets the signed-in user's settings.
+///
+/// # Errors
+///
+/// See [`crate::api::Error`].
+#[utoipa::path(
+    get,
+    path = "/api/v1/user-settings",
+    responses((status = OK, description = "The user's settings.", body = UserSettings)),
+    security(("session" = [])),
+)]
+#[debug_handler]
+pub async fn get(State(state): State<AppState>, session: Session) -> Response<UserSettings> {
+    let user = db::transaction!(state.db_pool, async |tx| -> TxResult<_, api::Error> {
+        Ok(sqlx::query!(
+            "SELECT locale FROM users
+                WHERE id = $1",
+            session.user_id,
+        )
+        .fetch_optional(tx.as_mut())
+        .await?)
+    })
+    .await?;
+
+    // The session's user can only be missing if it was deleted during this request.
+    let Some(user) = user else {
+        return Err(api::Error::Unauthenticated);
+    };
+
+    Ok((
+        StatusCode::OK,
+        Json(UserSettings {
+            locale: Locale::from_stored(&user.locale),
+        }),
+    ))
+}
+
+/// A `PATCH` request body for this API route. Settings left unset are unchanged.
+#[derive(Deserialize, ToSchema, Debug)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+#[schema(as = user_settings::PatchRequest)]
+pub struct PatchRequest {
+    /// The locale emails to the user are written in. See [`UserSettings`].
+    pub locale: Option<Locale>,
+}
+
+/// Changes the signed-in user's settings.
+///
+/// # Errors
+///
+/// See [`crate::api::Error`].
+#[utoipa::path(
+    patch,
+    path = "/api/v1/user-settings",
+    request_body = PatchRequest,
+    responses((status = OK, description = "The user's updated settings.", body = UserSettings)),
+    security(("session" = [])),
+)]
+#[debug_handler]
+pub async fn patch(
+    State(state): State<AppState>,
+    session: Session,
+    Json(body): Json<PatchRequest>,
+) -> Response<UserSettings> {
+    let locale = body.locale.map(Locale::as_str);
+
+    let user = db::transaction!(state.db_pool, async |tx| -> TxResult<_, api::Error> {
+        let Some(user) = sqlx::query!(
+            "UPDATE users
+                SET locale = COALESCE($1, locale)
+                WHERE id = $2
+                RETURNING locale",
+            locale,
+            session.user_id,
+        )
+        .fetch_optional(tx.as_mut())
+        .await?
+        else {
+            return Err(TxError::Abort(api::Error::Unauthenticated));
+        };
+
+        Ok(user)
+    })
+    .await?;
+
+    Ok((
+        StatusCode::OK,
+        Json(UserSettings {
+            locale: Locale::from_stored(&user.locale),
+        }),
+    ))
+}
+
+/// A user's settings, as returned by this API route.
+#[derive(Serialize, ToSchema, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct UserSettings {
+    /// The locale emails to the user are written in, by its lowercase language code. It's set from
+    /// the `Accept-Language` header when the user signs up.
+    pub locale: Locale,
+}
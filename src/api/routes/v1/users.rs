@@ -4,22 +4,28 @@ use axum::{extract::State, http::StatusCode};
 use axum_macros::debug_handler;
 use serde::{Deserialize, Serialize};
 use sqlx::Acquire;
+use utoipa::ToSchema;
 
 use crate::{
     api::{
         self,
+        rate_limit::{ip_subject, RateLimit},
+        routes::v1::email_verification::{check_code, CodeCheck},
         validation::{EmailVerificationCode, NewUserPassword, UserEmail, UserName},
         Json, Response,
     },
-    crypto::{hash_with_salt, verify_hash},
-    db::{self, TxError, TxResult},
+    client_ip::ClientIp,
+    crypto::hash_with_salt,
+    db::{self, TxResult},
     id::NewUserId,
+    locale::PreferredLocale,
     AppState,
 };
 
 /// A `POST` request body for this API route.
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, ToSchema, Debug)]
 #[serde(rename_all = "camelCase", deny_unknown_fields)]
+#[schema(as = users::PostRequest)]
 pub struct PostRequest {
     /// The user's email address.
     pub email: UserEmail,
@@ -34,48 +40,73 @@ pub struct PostRequest {
     pub password: NewUserPassword,
 }
 
-/// Creates a new user.
+/// Creates a new user. Emails to the user are written in the locale preferred by the request's
+/// `Accept-Language` header, until the user changes it through `/api/v1/user-settings`.
 ///
 /// # Errors
 ///
 /// See [`crate::api::Error`].
+#[utoipa::path(
+    post,
+    path = "/api/v1/users",
+    request_body = PostRequest,
+    responses((status = CREATED, description = "The user was created.", body = PostResponse)),
+)]
 #[debug_handler]
 pub async fn post(
     State(state): State<AppState>,
+    ClientIp(client_ip): ClientIp,
+    PreferredLocale(locale): PreferredLocale,
     Json(body): Json<PostRequest>,
 ) -> Response<PostResponse> {
+    // Verification codes are short, so we don't want them to be brute-forced.
+    RateLimit::CODE_PER_IP
+        .check(&state, ip_subject(client_ip))
+        .await?;
+    RateLimit::CODE_PER_EMAIL
+        .check(&state, body.email.as_str().to_lowercase())
+        .await?;
+
     let mut user_id = NewUserId::generate();
 
     let password_hash = hash_with_salt(&body.password);
 
-    db::transaction!(state.db_pool, async |tx| -> TxResult<_, api::Error> {
-        let does_code_match = sqlx::query!(
-            "DELETE FROM unverified_emails
-                WHERE user_id IS NULL AND email = $1
-                RETURNING code_hash",
-            body.email.as_str(),
+    let code_check = db::transaction!(state.db_pool, async |tx| -> TxResult<_, api::Error> {
+        let code_check = check_code(
+            tx.as_mut(),
+            &body.email,
+            &body.email_verification_code,
+            client_ip,
         )
-        .fetch_optional(tx.as_mut())
-        .await?
-        .and_then(|unverified_email| unverified_email.code_hash)
-        .is_some_and(|code_hash| verify_hash(&body.email_verification_code, &code_hash));
+        .await?;
 
-        if !does_code_match {
-            return Err(TxError::Abort(api::Error::EmailVerificationCodeWrong));
+        // Incorrect attempts must be committed rather than aborting the transaction, or else they
+        // wouldn't be counted.
+        if !matches!(code_check, CodeCheck::Correct { .. }) {
+            return Ok(code_check);
         }
 
+        sqlx::query!(
+            "DELETE FROM unverified_emails
+                WHERE user_id IS NULL AND email = $1",
+            body.email.as_str(),
+        )
+        .execute(tx.as_mut())
+        .await?;
+
         loop {
             // If this loop's query fails from an ID conflict, this savepoint is rolled back to
             // rather than aborting the entire transaction.
             let mut savepoint = tx.begin().await?;
 
             match sqlx::query!(
-                "INSERT INTO users (id, email, name, password_hash)
-                    VALUES ($1, $2, $3, $4)",
+                "INSERT INTO users (id, email, name, password_hash, locale)
+                    VALUES ($1, $2, $3, $4, $5)",
                 user_id.as_slice(),
                 body.email.as_str(),
                 *body.name,
                 password_hash,
+                locale.as_str(),
             )
             .execute(savepoint.as_mut())
             .await
@@ -91,17 +122,26 @@ pub async fn post(
             break;
         }
 
-        Ok(())
+        Ok(code_check)
     })
     .await?;
 
+    match code_check {
+        CodeCheck::Correct { .. } => {}
+        CodeCheck::Locked => return Err(api::Error::EmailVerificationCodeLocked),
+        CodeCheck::Wrong | CodeCheck::Missing => {
+            return Err(api::Error::EmailVerificationCodeWrong)
+        }
+    }
+
     // TODO: Set `Location` header.
     Ok((StatusCode::CREATED, Json(PostResponse { id: user_id })))
 }
 
 /// A `POST` response body for this API route.
-#[derive(Serialize, Debug)]
+#[derive(Serialize, ToSchema, Debug)]
 #[serde(rename_all = "camelCase")]
+#[schema(as = users::PostResponse)]
 pub struct PostResponse {
     /// The user's ID.
     pub id: NewUserId,
@@ -0,0 +1,448 @@
+//! See [`Session`].
+
+use std::time::Duration;
+
+use axum::{
+    extract::FromRequestParts,
+    http::{header::AUTHORIZATION, request::Parts, HeaderValue},
+};
+use serde::{Deserialize, Serialize};
+use tower_cookies::Cookies;
+use utoipa::ToSchema;
+
+use crate::{
+    api,
+    crypto::hash_without_salt,
+    db::{self, TxResult},
+    id::Token,
+    AppState,
+};
+
+/// How long after an access token's `last_used_at` was recorded before it's recorded again. This
+/// keeps frequently used access tokens from writing to the database on every request.
+const LAST_USED_AT_INTERVAL: Duration = Duration::from_secs(60);
+
+/// An extractor for the sign-in session of the user making the request, as set by the `token`
+/// cookie. Access tokens can't authenticate it, so it's for actions no [`Scope`] grants.
+///
+/// Fails with [`api::Error::ScopeMissing`] if the request is authenticated by a valid access token
+/// instead, or with [`api::Error::Unauthenticated`] if the request has no valid session.
+#[derive(Clone, Debug)]
+pub struct Session {
+    /// The ID of the signed-in user.
+    pub user_id: Vec<u8>,
+}
+
+impl FromRequestParts<AppState> for Session {
+    type Rejection = api::Error;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        if let Some(authorization) = parts.headers.get(AUTHORIZATION) {
+            let Some(token) = bearer_token(authorization) else {
+                return Err(api::Error::Unauthenticated);
+            };
+
+            // Only fail because of the access token's scopes if it's a valid access token.
+            access_token_session(state, &token).await?;
+            return Err(api::Error::ScopeMissing);
+        }
+
+        sign_in_session(parts, state).await
+    }
+}
+
+/// An extractor for the session of the user making the request, authenticated either by a sign-in
+/// session (see [`Session`]) or by an access token, as set by the `Authorization` header with the
+/// `Bearer` scheme.
+///
+/// The user's ID is only accessible through [`ScopedSession::require_scope`], so every route that
+/// accepts access tokens must check their scopes.
+///
+/// Fails with [`api::Error::Unauthenticated`] if the request has no valid session.
+#[derive(Clone, Debug)]
+pub struct ScopedSession {
+    /// The ID of the signed-in user.
+    user_id: Vec<u8>,
+
+    /// The scopes the session is limited to if it's authenticated by an access token, or `None` if
+    /// it's a sign-in session, which has every scope.
+    scopes: Option<Vec<Scope>>,
+}
+
+impl ScopedSession {
+    /// Checks that the session has a scope. Sign-in sessions have every scope.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`api::Error::ScopeMissing`] if the session is authenticated by an access token
+    /// without the scope.
+    pub fn require_scope(self, scope: Scope) -> Result<Session, api::Error> {
+        match self.scopes {
+            Some(scopes) if !scopes.contains(&scope) => Err(api::Error::ScopeMissing),
+            _ => Ok(Session {
+                user_id: self.user_id,
+            }),
+        }
+    }
+}
+
+impl From<Session> for ScopedSession {
+    fn from(session: Session) -> Self {
+        Self {
+            user_id: session.user_id,
+            scopes: None,
+        }
+    }
+}
+
+impl FromRequestParts<AppState> for ScopedSession {
+    type Rejection = api::Error;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        if let Some(authorization) = parts.headers.get(AUTHORIZATION) {
+            let Some(token) = bearer_token(authorization) else {
+                return Err(api::Error::Unauthenticated);
+            };
+
+            return access_token_session(state, &token).await;
+        }
+
+        Ok(sign_in_session(parts, state).await?.into())
+    }
+}
+
+/// Parses the access token from an `Authorization` header value with the `Bearer` scheme. Returns
+/// `None` if the value isn't in that form, in which case the request can't be authenticated by it.
+pub(crate) fn bearer_token(authorization: &HeaderValue) -> Option<Token> {
+    let (scheme, token) = authorization.to_str().ok()?.split_once(' ')?;
+
+    if !scheme.eq_ignore_ascii_case("Bearer") {
+        return None;
+    }
+
+    token.trim().parse().ok()
+}
+
+/// Gets the sign-in session set by the request's `token` cookie, recording that it was accessed.
+///
+/// # Errors
+///
+/// Returns [`api::Error::Unauthenticated`] if the request has no valid sign-in session.
+async fn sign_in_session(parts: &mut Parts, state: &AppState) -> Result<Session, api::Error> {
+    let cookies = Cookies::from_request_parts(parts, state)
+        .await
+        .map_err(|(_, message)| api::Error::Internal(message.into()))?;
+
+    let Some(token) = cookies
+        .get("token")
+        .and_then(|cookie| cookie.value().parse::<Token>().ok())
+    else {
+        return Err(api::Error::Unauthenticated);
+    };
+
+    let token_hash = hash_without_salt(&token);
+
+    let Some(session) = db::transaction!(state.db_pool, async |tx| -> TxResult<_, api::Error> {
+        Ok(sqlx::query!(
+            "UPDATE sessions
+                SET accessed_at = now()
+                WHERE token_hash = $1
+                RETURNING user_id",
+            token_hash.as_ref(),
+        )
+        .fetch_optional(tx.as_mut())
+        .await?)
+    })
+    .await?
+    else {
+        return Err(api::Error::Unauthenticated);
+    };
+
+    Ok(Session {
+        user_id: session.user_id,
+    })
+}
+
+/// Gets the session for an access token, recording that the token was used if it wasn't already
+/// within [`LAST_USED_AT_INTERVAL`].
+///
+/// # Errors
+///
+/// Returns [`api::Error::Unauthenticated`] if the access token doesn't exist or is expired.
+async fn access_token_session(
+    state: &AppState,
+    token: &Token,
+) -> Result<ScopedSession, api::Error> {
+    let token_hash = hash_without_salt(token);
+
+    let Some(access_token) =
+        db::transaction!(state.db_pool, async |tx| -> TxResult<_, api::Error> {
+            let Some(access_token) = sqlx::query!(
+                r#"SELECT id, user_id, scopes as "scopes: Vec<Scope>",
+                    coalesce(last_used_at < now() - make_interval(secs => $2), true)
+                        as "last_used_at_stale!"
+                    FROM access_tokens
+                    WHERE token_hash = $1 AND (expires_at IS NULL OR expires_at > now())"#,
+                token_hash.as_ref(),
+                LAST_USED_AT_INTERVAL.as_secs_f64(),
+            )
+            .fetch_optional(tx.as_mut())
+            .await?
+            else {
+                return Ok(None);
+            };
+
+            if access_token.last_used_at_stale {
+                sqlx::query!(
+                    "UPDATE access_tokens
+                        SET last_used_at = now()
+                        WHERE id = $1",
+                    access_token.id,
+                )
+                .execute(tx.as_mut())
+                .await?;
+            }
+
+            Ok(Some(access_token))
+        })
+        .await?
+    else {
+        return Err(api::Error::Unauthenticated);
+    };
+
+    Ok(ScopedSession {
+        user_id: access_token.user_id,
+        scopes: Some(access_token.scopes),
+    })
+}
+
+/// A permission an access token can be granted, corresponding to the database's
+/// `access_token_scope` type.
+#[derive(
+    Deserialize, Serialize, ToSchema, sqlx::Type, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug,
+)]
+#[serde(rename_all = "camelCase")]
+#[sqlx(type_name = "access_token_scope", rename_all = "snake_case")]
+pub enum Scope {
+    /// Reading the user's files and folders and their settings.
+    ReadFiles,
+
+    /// Moving and deleting the user's files and folders.
+    WriteFiles,
+
+    /// Changing how the user's files and folders are shared publicly, including sharing settings,
+    /// folder sites, and custom domains.
+    ManageShares,
+}
+
+#[cfg(test)]
+#[expect(clippy::missing_errors_doc, reason = "see rust-lang/rust-clippy#13391")]
+mod tests {
+    use axum::http::Request;
+    use chrono::{DateTime, Utc};
+    use sqlx::PgPool;
+
+    use super::*;
+
+    #[test]
+    fn bearer_tokens_parsed() {
+        let token = Token::generate().to_string();
+
+        // Each case is an `Authorization` header value and whether it has a valid access token.
+        let cases = [
+            (format!("Bearer {token}"), true),
+            (format!("bearer {token}"), true),
+            (format!("BEARER  {token} "), true),
+            (format!("Basic {token}"), false),
+            (format!("Bearer{token}"), false),
+            (format!("Bearer {token}A"), false),
+            (format!("Bearer {}", &token[1..]), false),
+            (format!("Bearer {token} {token}"), false),
+            ("Bearer".into(), false),
+            ("Bearer ".into(), false),
+            (String::new(), false),
+        ];
+
+        for (authorization, valid) in cases {
+            let header_value =
+                HeaderValue::from_str(&authorization).expect("test header should be valid");
+
+            assert_eq!(
+                bearer_token(&header_value).is_some(),
+                valid,
+                "{authorization:?}",
+            );
+        }
+    }
+
+    #[test]
+    fn scopes_required() {
+        let access_token_session = ScopedSession {
+            user_id: vec![1],
+            scopes: Some(vec![Scope::ReadFiles, Scope::ManageShares]),
+        };
+        let sign_in_session = ScopedSession::from(Session { user_id: vec![1] });
+
+        // Each case is a scope and whether the access token session has it.
+        let cases = [
+            (Scope::ReadFiles, true),
+            (Scope::WriteFiles, false),
+            (Scope::ManageShares, true),
+        ];
+
+        for (scope, granted) in cases {
+            let result = access_token_session.clone().require_scope(scope);
+
+            if granted {
+                assert!(
+                    matches!(result, Ok(Session { ref user_id }) if user_id == &[1]),
+                    "{scope:?} should be granted",
+                );
+            } else {
+                assert!(
+                    matches!(result, Err(api::Error::ScopeMissing)),
+                    "{scope:?} should be missing",
+                );
+            }
+
+            assert!(
+                sign_in_session.clone().require_scope(scope).is_ok(),
+                "sign-in sessions should have {scope:?}",
+            );
+        }
+    }
+
+    /// Creates a user with an unexpired and an expired access token, both with only the
+    /// [`Scope::ReadFiles`] scope. Returns the unexpired token, then the expired one.
+    async fn create_access_tokens(pool: &PgPool) -> anyhow::Result<(Token, Token)> {
+        let token = Token::generate();
+        let expired_token = Token::generate();
+
+        sqlx::raw_sql(
+            r"INSERT INTO users (id, email, name, password_hash)
+                VALUES ('\x75', 'user@example.com', 'User', '')",
+        )
+        .execute(pool)
+        .await?;
+
+        for (id, token, expires_at) in [
+            ("a", &token, "infinity"),
+            ("b", &expired_token, "-infinity"),
+        ] {
+            sqlx::query(
+                r"INSERT INTO access_tokens (id, token_hash, user_id, name, scopes, expires_at)
+                    VALUES ($1::bytea, $2, '\x75', 'Token', '{read_files}', $3::timestamptz)",
+            )
+            .bind(id)
+            .bind(hash_without_salt(token).as_ref())
+            .bind(expires_at)
+            .execute(pool)
+            .await?;
+        }
+
+        Ok((token, expired_token))
+    }
+
+    /// Extracts a session from a request with the specified `Authorization` header value, or gets
+    /// the code of the error it fails with.
+    async fn extract<T: FromRequestParts<AppState, Rejection = api::Error>>(
+        pool: &PgPool,
+        authorization: &str,
+    ) -> anyhow::Result<Result<T, &'static str>> {
+        let (mut parts, ()) = Request::builder()
+            .header(AUTHORIZATION, authorization)
+            .body(())?
+            .into_parts();
+
+        let state = AppState {
+            db_pool: pool.clone(),
+        };
+
+        Ok(T::from_request_parts(&mut parts, &state)
+            .await
+            .map_err(|error| error.code()))
+    }
+
+    #[sqlx::test]
+    #[ignore = "requires a database at `DATABASE_URL`"]
+    async fn only_scoped_sessions_authenticated_by_access_tokens(
+        pool: PgPool,
+    ) -> anyhow::Result<()> {
+        let (token, expired_token) = create_access_tokens(&pool).await?;
+
+        let session = extract::<ScopedSession>(&pool, &format!("Bearer {token}")).await?;
+        assert!(matches!(
+            session,
+            Ok(ScopedSession { scopes: Some(ref scopes), .. }) if scopes == &[Scope::ReadFiles],
+        ));
+
+        let session = extract::<ScopedSession>(&pool, &format!("Bearer {expired_token}")).await?;
+        assert!(matches!(session, Err("UNAUTHENTICATED")));
+
+        let session =
+            extract::<ScopedSession>(&pool, &format!("Bearer {}", Token::generate())).await?;
+        assert!(matches!(session, Err("UNAUTHENTICATED")));
+
+        let session = extract::<ScopedSession>(&pool, &format!("Basic {token}")).await?;
+        assert!(matches!(session, Err("UNAUTHENTICATED")));
+
+        // Valid access tokens are missing the scope for sign-in sessions, and invalid ones are
+        // unauthenticated.
+        let session = extract::<Session>(&pool, &format!("Bearer {token}")).await?;
+        assert!(matches!(session, Err("SCOPE_MISSING")));
+
+        let session = extract::<Session>(&pool, &format!("Bearer {expired_token}")).await?;
+        assert!(matches!(session, Err("UNAUTHENTICATED")));
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    #[ignore = "requires a database at `DATABASE_URL`"]
+    async fn access_token_use_recorded_once_per_interval(pool: PgPool) -> anyhow::Result<()> {
+        let (token, _) = create_access_tokens(&pool).await?;
+        let state = AppState {
+            db_pool: pool.clone(),
+        };
+
+        let last_used_at = async || {
+            sqlx::query_scalar::<_, Option<DateTime<Utc>>>(
+                r"SELECT last_used_at FROM access_tokens
+                    WHERE id = '\x61'",
+            )
+            .fetch_one(&pool)
+            .await
+        };
+
+        access_token_session(&state, &token)
+            .await
+            .map_err(|error| anyhow::anyhow!("{error:?}"))?;
+        let first_used_at = last_used_at().await?.expect("token use should be recorded");
+
+        access_token_session(&state, &token)
+            .await
+            .map_err(|error| anyhow::anyhow!("{error:?}"))?;
+        assert_eq!(last_used_at().await?, Some(first_used_at));
+
+        sqlx::query(
+            r"UPDATE access_tokens
+                SET last_used_at = last_used_at - make_interval(secs => $1)",
+        )
+        .bind(LAST_USED_AT_INTERVAL.as_secs_f64())
+        .execute(&pool)
+        .await?;
+
+        access_token_session(&state, &token)
+            .await
+            .map_err(|error| anyhow::anyhow!("{error:?}"))?;
+        assert!(last_used_at().await? >= Some(first_used_at));
+
+        Ok(())
+    }
+}
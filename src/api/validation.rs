@@ -1,7 +1,8 @@
 //! Utilities to help with API request validation.
 
-use std::{borrow::Cow, str::FromStr};
+use std::{borrow::Cow, collections::BTreeMap, str::FromStr};
 
+use axum::http::{HeaderName, HeaderValue};
 use derive_more::derive::{AsRef, Deref, Display};
 use idna::uts46::{self, Uts46};
 use lettre::Address;
@@ -9,6 +10,10 @@ use regex_macro::regex;
 use serde::{Deserialize, Serialize};
 use serde_with::{DeserializeFromStr, SerializeDisplay};
 use thiserror::Error;
+use utoipa::{
+    openapi::{KnownFormat, ObjectBuilder, RefOr, Schema, SchemaFormat, Type},
+    PartialSchema, ToSchema,
+};
 
 /// A user's name.
 pub type UserName = BoundedString<1, 64>;
@@ -25,6 +30,9 @@ pub type EmailVerificationCode = BoundedString<6, 6>;
 /// A CAPTCHA token.
 pub type CaptchaToken = BoundedString<1, 2048>;
 
+/// A name for an access token, to tell it apart from the user's other access tokens.
+pub type AccessTokenName = BoundedString<1, 128>;
+
 /// A [`String`] newtype that guarantees its length is within a certain range.
 #[derive(
     Deref,
@@ -77,6 +85,42 @@ impl<const MIN: usize, const MAX: usize> TryFrom<String> for BoundedString<MIN,
     }
 }
 
+impl<const MIN: usize, const MAX: usize> PartialSchema for BoundedString<MIN, MAX> {
+    fn schema() -> RefOr<Schema> {
+        ObjectBuilder::new()
+            .schema_type(Type::String)
+            .min_length(Some(MIN))
+            .max_length(Some(MAX))
+            .description(Some(
+                "A string whose length in UTF-8 bytes is within the specified range.",
+            ))
+            .into()
+    }
+}
+
+/// Implements [`ToSchema`] for each [`BoundedString`] alias, so each alias's schema is named after
+/// the alias rather than all sharing the name `BoundedString`.
+macro_rules! bounded_string_schemas {
+    ($($alias:ident),+ $(,)?) => {
+        $(
+            impl ToSchema for $alias {
+                fn name() -> Cow<'static, str> {
+                    Cow::Borrowed(stringify!($alias))
+                }
+            }
+        )+
+    };
+}
+
+bounded_string_schemas!(
+    UserName,
+    NewUserPassword,
+    UserPassword,
+    EmailVerificationCode,
+    CaptchaToken,
+    AccessTokenName,
+);
+
 /// A user-inputted email address. Ensures the address uses a domain name with a TLD, and normalizes
 /// the domain name (for non-ASCII characters).
 #[derive(
@@ -166,8 +210,190 @@ impl FromStr for UserEmail {
     }
 }
 
+impl PartialSchema for UserEmail {
+    fn schema() -> RefOr<Schema> {
+        ObjectBuilder::new()
+            .schema_type(Type::String)
+            .format(Some(SchemaFormat::KnownFormat(KnownFormat::IdnEmail)))
+            .max_length(Some(Self::MAX_LENGTH))
+            .description(Some(
+                "An email address. Its domain must be a domain name with a TLD rather than an IP \
+                address. Non-ASCII domain names are normalized.",
+            ))
+            .into()
+    }
+}
+
+impl ToSchema for UserEmail {}
+
+/// A user-inputted domain name for a custom domain. Ensures the domain has a TLD, and normalizes
+/// it to lowercase ASCII (encoding non-ASCII characters with Punycode).
+#[derive(
+    Deref,
+    AsRef,
+    Display,
+    DeserializeFromStr,
+    Serialize,
+    Clone,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    Debug,
+)]
+#[as_ref(forward)]
+pub struct CustomDomainName(String);
+
+impl CustomDomainName {
+    /// Consumes the [`CustomDomainName`], returning the wrapped [`String`].
+    pub fn into_inner(self) -> String {
+        self.0
+    }
+}
+
+/// An error constructing a [`CustomDomainName`].
+#[derive(Error, Copy, Clone, Debug)]
+#[non_exhaustive]
+pub enum CustomDomainNameError {
+    /// The domain name was invalid.
+    #[error("invalid domain name")]
+    Invalid,
+
+    /// The domain name had no TLD, or it was an IP address.
+    #[error("domain name must have a TLD")]
+    NoTld,
+}
+
+impl FromStr for CustomDomainName {
+    type Err = CustomDomainNameError;
+
+    fn from_str(str: &str) -> Result<Self, Self::Err> {
+        // A trailing dot is equivalent to none, so ignore it.
+        let str = str.strip_suffix('.').unwrap_or(str);
+
+        let Ok(domain) = Uts46::new().to_ascii(
+            str.as_bytes(),
+            uts46::AsciiDenyList::URL,
+            uts46::Hyphens::Check,
+            uts46::DnsLength::Verify,
+        ) else {
+            return Err(CustomDomainNameError::Invalid);
+        };
+
+        // No TLD is numeric, so this also rules out IPv4 addresses.
+        match domain.rsplit_once('.') {
+            Some((_, tld)) if !tld.bytes().all(|byte| byte.is_ascii_digit()) => {}
+            _ => return Err(CustomDomainNameError::NoTld),
+        }
+
+        Ok(Self(domain.into_owned()))
+    }
+}
+
+impl PartialSchema for CustomDomainName {
+    fn schema() -> RefOr<Schema> {
+        ObjectBuilder::new()
+            .schema_type(Type::String)
+            .format(Some(SchemaFormat::KnownFormat(KnownFormat::IdnHostname)))
+            .description(Some(
+                "A domain name with a TLD. It's normalized to lowercase ASCII, encoding non-ASCII \
+                characters with Punycode.",
+            ))
+            .into()
+    }
+}
+
+impl ToSchema for CustomDomainName {}
+
+/// Custom response headers for a folder's static site, keyed by lowercase header name.
+///
+/// Only headers in [`SiteHeaders::ALLOWED_NAMES`] can be set. In particular, headers the content
+/// server sets for security (such as `Content-Security-Policy`) can't be overridden.
+#[derive(Deref, Deserialize, Serialize, Clone, PartialEq, Eq, Default, Debug)]
+#[serde(try_from = "BTreeMap<String, String>")]
+pub struct SiteHeaders(BTreeMap<String, String>);
+
+impl SiteHeaders {
+    /// The names of all headers that can be set.
+    pub const ALLOWED_NAMES: [&str; 5] = [
+        "cache-control",
+        "content-language",
+        "link",
+        "referrer-policy",
+        "x-robots-tag",
+    ];
+
+    /// The maximum length of a header value.
+    pub const MAX_VALUE_LENGTH: usize = 1024;
+
+    /// Consumes the [`SiteHeaders`], returning the wrapped map.
+    pub fn into_inner(self) -> BTreeMap<String, String> {
+        self.0
+    }
+}
+
+/// An error constructing [`SiteHeaders`].
+#[derive(Error, Clone, PartialEq, Eq, Debug)]
+#[non_exhaustive]
+pub enum SiteHeadersError {
+    /// The header isn't in [`SiteHeaders::ALLOWED_NAMES`].
+    #[error("header `{0}` can't be set")]
+    NameNotAllowed(String),
+
+    /// The header value isn't a valid header value or is too long.
+    #[error("invalid value for header `{0}`")]
+    InvalidValue(String),
+}
+
+impl TryFrom<BTreeMap<String, String>> for SiteHeaders {
+    type Error = SiteHeadersError;
+
+    fn try_from(headers: BTreeMap<String, String>) -> Result<Self, Self::Error> {
+        headers
+            .into_iter()
+            .map(|(name, value)| {
+                let Ok(name) = HeaderName::try_from(name.as_str()) else {
+                    return Err(SiteHeadersError::NameNotAllowed(name));
+                };
+
+                if !Self::ALLOWED_NAMES.contains(&name.as_str()) {
+                    return Err(SiteHeadersError::NameNotAllowed(name.as_str().into()));
+                }
+
+                if value.len() > Self::MAX_VALUE_LENGTH || HeaderValue::try_from(&value).is_err() {
+                    return Err(SiteHeadersError::InvalidValue(name.as_str().into()));
+                }
+
+                Ok((name.as_str().into(), value))
+            })
+            .collect::<Result<_, _>>()
+            .map(Self)
+    }
+}
+
+impl PartialSchema for SiteHeaders {
+    fn schema() -> RefOr<Schema> {
+        ObjectBuilder::new()
+            .schema_type(Type::Object)
+            .additional_properties(Some(
+                ObjectBuilder::new()
+                    .schema_type(Type::String)
+                    .max_length(Some(Self::MAX_VALUE_LENGTH)),
+            ))
+            .description(Some(format!(
+                "Custom response headers, keyed by lowercase header name. Only these headers can \
+                be set: {}.",
+                Self::ALLOWED_NAMES.join(", "),
+            )))
+            .into()
+    }
+}
+
+impl ToSchema for SiteHeaders {}
+
 /// Normalizes an email address's user portion by removing unnecessary quotes and escapes.
-fn normalize_email_address_user(user: &str) -> Cow<str> {
+fn normalize_email_address_user(user: &str) -> Cow<'_, str> {
     let Some(unquoted_user) = user
         .strip_prefix('"')
         .and_then(|user| user.strip_suffix('"'))
@@ -266,6 +492,72 @@ mod tests {
         }
     }
 
+    #[test]
+    fn custom_domain_name_validation() -> anyhow::Result<()> {
+        let equivalent_domains = [
+            "xn--exampl-gva.com",
+            "examplé.com",
+            "EXAMPLÉ.COM.",
+            "example\u{0301}.com",
+        ];
+
+        for domain in equivalent_domains {
+            assert_eq!(
+                "xn--exampl-gva.com",
+                domain.parse::<CustomDomainName>()?.as_str(),
+                "normalizing {domain:?}",
+            );
+        }
+
+        let invalid_domains = [
+            "",
+            "com",
+            "localhost",
+            "127.0.0.1",
+            "[::1]",
+            "-example.com",
+            "example.com/path",
+            "user@example.com",
+            "example..com",
+        ];
+
+        for domain in invalid_domains {
+            domain
+                .parse::<CustomDomainName>()
+                .expect_err("custom domain name should be invalid");
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn site_headers_validation() {
+        let headers = SiteHeaders::try_from(BTreeMap::from([
+            ("Cache-Control".into(), "public, max-age=3600".into()),
+            ("x-robots-tag".into(), "noindex".into()),
+        ]))
+        .expect("site headers should be valid");
+
+        assert_eq!(
+            headers.keys().collect::<Vec<_>>(),
+            ["cache-control", "x-robots-tag"],
+            "header names should be normalized to lowercase",
+        );
+
+        let invalid_headers = [
+            ("Content-Security-Policy", "default-src *"),
+            ("Access-Control-Allow-Origin", "*"),
+            ("Set-Cookie", "a=b"),
+            ("not a header", "value"),
+            ("Cache-Control", "line\nbreak"),
+        ];
+
+        for (name, value) in invalid_headers {
+            SiteHeaders::try_from(BTreeMap::from([(name.into(), value.into())]))
+                .expect_err("site header should be invalid");
+        }
+    }
+
     /// Ensures users can't sign up multiple times with different forms of the same email.
     #[test]
     fn user_email_normalization() -> anyhow::Result<()> {
@@ -0,0 +1,59 @@
+//! A log of security-relevant events, kept in the database for later review.
+
+use std::net::IpAddr;
+
+use sqlx::PgConnection;
+use strum_macros::IntoStaticStr;
+
+/// A kind of event recorded in the audit log, stored by its `snake_case` name.
+#[derive(IntoStaticStr, Clone, Copy, PartialEq, Eq, Debug)]
+#[strum(serialize_all = "snake_case")]
+#[expect(
+    clippy::enum_variant_names,
+    reason = "only email verification events are recorded so far"
+)]
+pub(crate) enum Event {
+    /// A correct email verification code was entered.
+    EmailVerificationCodeCorrect,
+
+    /// An incorrect email verification code was entered.
+    EmailVerificationCodeWrong,
+
+    /// An email verification code was entered, but the code was invalidated by too many incorrect
+    /// attempts. This is also recorded for the incorrect attempt that invalidates the code.
+    EmailVerificationCodeLocked,
+}
+
+/// An entry to record in the audit log.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Entry<'a> {
+    /// The kind of event.
+    pub(crate) event: Event,
+
+    /// The email address the event concerns, if any.
+    pub(crate) email: Option<&'a str>,
+
+    /// The IP address of the client that caused the event, if any.
+    pub(crate) ip_address: Option<IpAddr>,
+}
+
+/// Records an entry in the audit log.
+///
+/// # Errors
+///
+/// Returns an error if the database query fails.
+pub(crate) async fn record(conn: &mut PgConnection, entry: Entry<'_>) -> sqlx::Result<()> {
+    let event: &str = entry.event.into();
+
+    sqlx::query!(
+        "INSERT INTO audit_log (event, email, ip_address)
+            VALUES ($1, $2, $3)",
+        event,
+        entry.email,
+        entry.ip_address.map(|ip_address| ip_address.to_string()),
+    )
+    .execute(conn)
+    .await?;
+
+    Ok(())
+}
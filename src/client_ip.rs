@@ -0,0 +1,353 @@
+//! See [`ClientIp`].
+
+use std::{
+    net::{IpAddr, SocketAddr},
+    sync::LazyLock,
+};
+
+use axum::{
+    extract::FromRequestParts,
+    http::{header::FORWARDED, request::Parts, HeaderMap, HeaderName},
+};
+use ipnet::IpNet;
+
+use crate::api;
+
+/// The networks of reverse proxies (such as a CDN or load balancer) allowed to report the client's
+/// IP address, configured by the comma-separated `TRUSTED_PROXIES` environment variable. Each entry
+/// is either an IP address or a network in CIDR notation.
+static TRUSTED_PROXIES: LazyLock<Vec<IpNet>> = LazyLock::new(|| {
+    let Ok(trusted_proxies) = dotenvy::var("TRUSTED_PROXIES") else {
+        return Vec::new();
+    };
+
+    trusted_proxies
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            entry
+                .parse::<IpNet>()
+                .or_else(|_| entry.parse::<IpAddr>().map(IpNet::from))
+                .expect(
+                    "environment variable `TRUSTED_PROXIES` should list IP addresses or networks",
+                )
+        })
+        .collect()
+});
+
+/// The `CF-Connecting-IP` header, set by Cloudflare to the IP address of the client connecting to
+/// it.
+static CF_CONNECTING_IP: HeaderName = HeaderName::from_static("cf-connecting-ip");
+
+/// The `X-Forwarded-For` header.
+static X_FORWARDED_FOR: HeaderName = HeaderName::from_static("x-forwarded-for");
+
+/// An extractor for the IP address of the client making the request.
+///
+/// If the peer connecting to the server is a trusted proxy, the client's IP address is taken from
+/// the first of these headers present:
+///
+/// 1. `CF-Connecting-IP`.
+/// 2. `Forwarded`, using the nearest `for` address that isn't a trusted proxy.
+/// 3. `X-Forwarded-For`, using the nearest address that isn't a trusted proxy.
+///
+/// Otherwise, it's the peer's IP address. Trusted proxies must overwrite or remove these headers
+/// when set by clients, or clients can spoof their IP address.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct ClientIp(pub IpAddr);
+
+impl ClientIp {
+    /// Determines the IP address of the client making a request from the peer's address and the
+    /// request headers.
+    pub(crate) fn new(peer: SocketAddr, headers: &HeaderMap) -> Self {
+        Self::with_trusted_proxies(peer, headers, &TRUSTED_PROXIES)
+    }
+
+    /// Determines the IP address of the client making a request like [`ClientIp::new`], but with
+    /// the specified trusted proxy networks.
+    fn with_trusted_proxies(
+        peer: SocketAddr,
+        headers: &HeaderMap,
+        trusted_proxies: &[IpNet],
+    ) -> Self {
+        let is_trusted = |ip| is_trusted(trusted_proxies, ip);
+        let peer_ip = peer.ip().to_canonical();
+
+        if !is_trusted(peer_ip) {
+            return Self(peer_ip);
+        }
+
+        if let Some(ip) = headers
+            .get(&CF_CONNECTING_IP)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| parse_node(value.trim()))
+        {
+            return Self(ip);
+        }
+
+        let forwarded = header_list(headers, &FORWARDED);
+        let hops: Vec<&str> = if forwarded.is_empty() {
+            header_list(headers, &X_FORWARDED_FOR)
+        } else {
+            // An element without a `for` parameter is treated as an unknown hop.
+            forwarded
+                .into_iter()
+                .map(|element| forwarded_for(element).unwrap_or_default())
+                .collect()
+        };
+
+        let mut client_ip = peer_ip;
+
+        for hop in hops.into_iter().rev() {
+            // An unknown or obfuscated hop can't be trusted to have forwarded the real client IP,
+            // so the nearest known address is used instead.
+            let Some(ip) = parse_node(hop) else {
+                break;
+            };
+
+            client_ip = ip;
+
+            if !is_trusted(ip) {
+                break;
+            }
+        }
+
+        Self(client_ip)
+    }
+}
+
+impl<S: Send + Sync> FromRequestParts<S> for ClientIp {
+    type Rejection = api::Error;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        parts
+            .extensions
+            .get::<Self>()
+            .copied()
+            .ok_or_else(|| api::Error::Internal("client IP wasn't determined".into()))
+    }
+}
+
+/// Checks whether an IP address belongs to one of the specified trusted proxy networks.
+fn is_trusted(trusted_proxies: &[IpNet], ip: IpAddr) -> bool {
+    trusted_proxies.iter().any(|network| network.contains(&ip))
+}
+
+/// Returns the comma-separated elements of every instance of a header, in order.
+fn header_list<'a>(headers: &'a HeaderMap, name: &HeaderName) -> Vec<&'a str> {
+    headers
+        .get_all(name)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .collect()
+}
+
+/// Returns the value of the `for` parameter in an element of a `Forwarded` header, without quotes.
+fn forwarded_for(element: &str) -> Option<&str> {
+    element.split(';').find_map(|pair| {
+        let (key, value) = pair.trim().split_once('=')?;
+
+        if !key.eq_ignore_ascii_case("for") {
+            return None;
+        }
+
+        Some(value.trim_matches('"'))
+    })
+}
+
+/// Parses the IP address from a node in a forwarding header, which may have a port and IPv6
+/// brackets. Returns `None` for unknown or obfuscated nodes.
+fn parse_node(node: &str) -> Option<IpAddr> {
+    let ip = if let Some(bracketed) = node.strip_prefix('[') {
+        bracketed.split_once(']')?.0.parse().ok()?
+    } else if let Ok(ip) = node.parse::<IpAddr>() {
+        ip
+    } else {
+        let (ip, _port) = node.rsplit_once(':')?;
+        ip.parse().ok()?
+    };
+
+    Some(ip.to_canonical())
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::HeaderValue;
+
+    use super::*;
+
+    /// Request header names and values, in order.
+    type Headers = &'static [(&'static str, &'static str)];
+
+    #[test]
+    fn nodes_parsed() {
+        // Each case is a node and its parsed IP address.
+        let cases = [
+            ("203.0.113.7", Some("203.0.113.7")),
+            ("203.0.113.7:8080", Some("203.0.113.7")),
+            ("2001:db8::1", Some("2001:db8::1")),
+            ("[2001:db8::1]", Some("2001:db8::1")),
+            ("[2001:db8::1]:443", Some("2001:db8::1")),
+            ("[::ffff:203.0.113.7]:443", Some("203.0.113.7")),
+            ("unknown", None),
+            ("_hidden", None),
+            ("_hidden:8080", None),
+            ("[2001:db8::1", None),
+            ("", None),
+        ];
+
+        for (node, ip) in cases {
+            let ip = ip.map(|ip| ip.parse().expect("test IP should be valid"));
+
+            assert_eq!(parse_node(node), ip, "{node:?}");
+        }
+    }
+
+    #[test]
+    fn forwarded_for_extracted() {
+        // Each case is a `Forwarded` element and its `for` parameter.
+        let cases = [
+            ("for=192.0.2.60", Some("192.0.2.60")),
+            (
+                "for=192.0.2.60;proto=http;by=203.0.113.43",
+                Some("192.0.2.60"),
+            ),
+            (
+                "proto=https; For=\"[2001:db8:cafe::17]:4711\"",
+                Some("[2001:db8:cafe::17]:4711"),
+            ),
+            ("FOR=unknown", Some("unknown")),
+            ("proto=https", None),
+            ("", None),
+        ];
+
+        for (element, node) in cases {
+            assert_eq!(forwarded_for(element), node, "{element:?}");
+        }
+    }
+
+    #[test]
+    fn client_ip_determined_through_trusted_proxies() {
+        let trusted_proxies = ["10.0.0.0/8".parse().expect("test network should be valid")];
+
+        // Each case is the peer's IP address, the request headers, and the client's IP address.
+        let cases: [(&str, Headers, &str); 14] = [
+            // Untrusted peers can't report another IP address.
+            (
+                "198.51.100.1",
+                &[
+                    ("x-forwarded-for", "203.0.113.7"),
+                    ("cf-connecting-ip", "203.0.113.8"),
+                ],
+                "198.51.100.1",
+            ),
+            ("10.0.0.1", &[], "10.0.0.1"),
+            (
+                "10.0.0.1",
+                &[("x-forwarded-for", "203.0.113.7")],
+                "203.0.113.7",
+            ),
+            // Leftmost entries are set by the client, so they're ignored after the nearest untrusted
+            // address.
+            (
+                "10.0.0.1",
+                &[("x-forwarded-for", "192.0.2.1, 203.0.113.7")],
+                "203.0.113.7",
+            ),
+            // Trusted hops are skipped.
+            (
+                "10.0.0.1",
+                &[(
+                    "x-forwarded-for",
+                    "192.0.2.1, 203.0.113.7, 10.0.0.2, 10.0.0.3",
+                )],
+                "203.0.113.7",
+            ),
+            // Multiple header lines are one list, in order.
+            (
+                "10.0.0.1",
+                &[
+                    ("x-forwarded-for", "192.0.2.1"),
+                    ("x-forwarded-for", "203.0.113.7, 10.0.0.2"),
+                ],
+                "203.0.113.7",
+            ),
+            // If every hop is trusted, the farthest one is used.
+            (
+                "10.0.0.1",
+                &[("x-forwarded-for", "10.0.0.3, 10.0.0.2")],
+                "10.0.0.3",
+            ),
+            (
+                "10.0.0.1",
+                &[(
+                    "forwarded",
+                    "for=192.0.2.1, for=\"[2001:db8::1]:4711\";proto=https",
+                )],
+                "2001:db8::1",
+            ),
+            // Unknown and obfuscated hops stop the search at the nearest known address.
+            (
+                "10.0.0.1",
+                &[("forwarded", "for=203.0.113.7, for=unknown")],
+                "10.0.0.1",
+            ),
+            (
+                "10.0.0.1",
+                &[("forwarded", "for=203.0.113.7, for=_hidden, for=10.0.0.2")],
+                "10.0.0.2",
+            ),
+            ("10.0.0.1", &[("forwarded", "proto=https")], "10.0.0.1"),
+            // `Forwarded` takes precedence over `X-Forwarded-For`.
+            (
+                "10.0.0.1",
+                &[
+                    ("x-forwarded-for", "192.0.2.1"),
+                    ("forwarded", "for=203.0.113.7"),
+                ],
+                "203.0.113.7",
+            ),
+            // `CF-Connecting-IP` takes precedence over both.
+            (
+                "10.0.0.1",
+                &[
+                    ("forwarded", "for=192.0.2.1"),
+                    ("cf-connecting-ip", "203.0.113.7"),
+                ],
+                "203.0.113.7",
+            ),
+            // An invalid `CF-Connecting-IP` is ignored.
+            (
+                "10.0.0.1",
+                &[
+                    ("cf-connecting-ip", "unknown"),
+                    ("x-forwarded-for", "203.0.113.7"),
+                ],
+                "203.0.113.7",
+            ),
+        ];
+
+        for (peer_ip, header_pairs, client_ip) in cases {
+            let peer = SocketAddr::new(peer_ip.parse().expect("test IP should be valid"), 1234);
+
+            let mut headers = HeaderMap::new();
+            for (name, value) in header_pairs {
+                headers.append(
+                    HeaderName::from_static(name),
+                    HeaderValue::from_static(value),
+                );
+            }
+
+            assert_eq!(
+                ClientIp::with_trusted_proxies(peer, &headers, &trusted_proxies).0,
+                client_ip
+                    .parse::<IpAddr>()
+                    .expect("test IP should be valid"),
+                "{peer_ip} with {headers:?}",
+            );
+        }
+    }
+}
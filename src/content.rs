@@ -1,23 +1,121 @@
 //! A web server for user-uploaded content. File Garden exposes this via `https://file.garden/`.
 
-use std::borrow::Cow;
+use std::{borrow::Cow, ops::ControlFlow};
 
+use askama::Template;
+use async_compression::tokio::bufread::BrotliDecoder;
 use axum::{
-    extract::Request,
+    body::Body,
+    extract::{Request, State},
     http::{
-        header::{ACCESS_CONTROL_ALLOW_ORIGIN, ALLOW, CONTENT_SECURITY_POLICY},
-        Method, StatusCode,
+        header::{
+            ACCEPT_ENCODING, ACCESS_CONTROL_ALLOW_ORIGIN, ALLOW, CONTENT_DISPOSITION,
+            CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_SECURITY_POLICY, CONTENT_TYPE, LAST_MODIFIED,
+            VARY,
+        },
+        request::Parts,
+        HeaderMap, HeaderValue, Method, StatusCode,
     },
 };
+use index::{IndexEntry, IndexPage};
 use percent_encoding::{percent_decode_str, utf8_percent_encode};
+use sqlx::{
+    types::chrono::{DateTime, Utc},
+    PgConnection,
+};
+use tokio_util::io::ReaderStream;
+
+use crate::{
+    custom_domain,
+    db::{self, TxResult},
+    id::Id,
+    percent_encoding::{COMPONENT_IGNORING_SLASH, EXT_VALUE},
+    response::Response,
+    storage::{self, Encoding},
+    AppState, WEBSITE_ORIGIN,
+};
+
+mod index;
+mod site;
+mod zip;
 
-use crate::{percent_encoding::COMPONENT_IGNORING_SLASH, response::Response, WEBSITE_ORIGIN};
+/// The name of the query parameter specifying a file or folder's ID. Knowing an item's ID grants
+/// access to it, so this is how owners link to their own items.
+const ID_PARAM: &str = "_id";
 
-/// The start of a file ID query parameter.
-const FILE_ID_QUERY_PREFIX: &str = "_id=";
+/// The name of the query parameter specifying a share key of a folder or any of its ancestors.
+const SHARE_KEY_PARAM: &str = "_key";
+
+/// The name of the query parameter requesting a folder be downloaded as a ZIP archive.
+const ZIP_PARAM: &str = "_zip";
+
+/// The name of the file served in place of an index page for a folder with its index enabled.
+const INDEX_FILE_NAME: &str = "index.html";
 
 /// The service function to handle incoming requests for user-uploaded content.
-pub(super) fn handle(request: Request) -> Response {
+pub(super) async fn handle(State(state): State<AppState>, request: Request) -> Response {
+    let (request, path, response) = match prepare(request) {
+        ControlFlow::Continue(prepared) => prepared,
+        ControlFlow::Break(response) => return response,
+    };
+
+    if path == "/" {
+        return response.permanent_redirect(format!("{}/", *WEBSITE_ORIGIN).as_str());
+    }
+
+    let Some((user_identifier, file_path)) = path
+        .strip_prefix('/')
+        .expect("path should start with `/`")
+        .split_once('/')
+    else {
+        return response.plain_error(StatusCode::BAD_REQUEST);
+    };
+
+    let Ok(owner_id) = user_identifier.parse::<Id>() else {
+        return response.plain_error(StatusCode::NOT_FOUND);
+    };
+
+    let names = file_path.split_terminator('/').map(Into::into).collect();
+
+    respond(
+        &state,
+        response,
+        &request,
+        &owner_id,
+        names,
+        &format!("/{file_path}"),
+    )
+    .await
+}
+
+/// The service function to handle incoming requests for a verified custom domain, which serves its
+/// target folder's contents as if the folder were the domain's root.
+pub(super) async fn handle_custom_domain(
+    State(state): State<AppState>,
+    request: Request,
+    target: &custom_domain::Target,
+) -> Response {
+    let (request, path, response) = match prepare(request) {
+        ControlFlow::Continue(prepared) => prepared,
+        ControlFlow::Break(response) => return response,
+    };
+
+    let names = target
+        .name_path
+        .iter()
+        .cloned()
+        .chain(path[1..].split_terminator('/').map(Into::into))
+        .collect();
+
+    respond(&state, response, &request, &target.owner_id, names, &path).await
+}
+
+/// Sets the headers common to all content responses, and checks the request's method and path.
+///
+/// Continues with the request's parts, its percent-decoded path, and the response to build on, or
+/// breaks with the complete response if the request can't be served as is (such as if it has a
+/// disallowed method or its path must be redirected to a normalized form).
+fn prepare(request: Request) -> ControlFlow<Response, (Parts, String, Response)> {
     let (request, _body) = request.into_parts();
     let mut response = Response::new();
 
@@ -39,67 +137,657 @@ pub(super) fn handle(request: Request) -> Response {
             .status(status)
             .header_valid(ALLOW, "GET, HEAD, OPTIONS");
 
-        return response;
+        return ControlFlow::Break(response);
     }
 
     let encoded_path = request.uri.path();
 
-    if encoded_path == "/" {
-        return response.permanent_redirect(format!("{}/", *WEBSITE_ORIGIN).as_str());
-    }
-
     let Ok(path) = percent_decode_str(encoded_path).decode_utf8() else {
-        return response.plain_error(StatusCode::BAD_REQUEST);
+        return ControlFlow::Break(response.plain_error(StatusCode::BAD_REQUEST));
     };
 
     // The above can decode `%00` into a null byte, so disallow null bytes as a defensive measure.
     if path.contains('\x00') {
-        return response.plain_error(StatusCode::BAD_REQUEST);
+        return ControlFlow::Break(response.plain_error(StatusCode::BAD_REQUEST));
     }
 
     let normalized_encoded_path: Cow<str> =
         utf8_percent_encode(&path, COMPONENT_IGNORING_SLASH).into();
 
-    let query = request.uri.query();
-
     if encoded_path != normalized_encoded_path {
         // Redirect to the same URI with normalized path encoding. This reduces how many URLs must
         // be purged from the CDN's cache when a file changes. It's impossible to purge every
         // possible variation of encoding for a URL.
 
-        let normalized_uri = concat_path_and_query(&normalized_encoded_path, query);
+        let normalized_uri = concat_path_and_query(&normalized_encoded_path, request.uri.query());
 
-        return response.permanent_redirect(&normalized_uri);
+        return ControlFlow::Break(response.permanent_redirect(&normalized_uri));
     }
 
-    let Some((user_identifier, file_path)) = path
-        .strip_prefix('/')
-        .expect("path should start with `/`")
-        .split_once('/')
-    else {
-        return response.plain_error(StatusCode::BAD_REQUEST);
+    let path = path.into_owned();
+
+    ControlFlow::Continue((request, path, response))
+}
+
+/// Responds with the file or folder at the specified path of names in the owner's files.
+///
+/// The `path` is the request's percent-decoded path relative to where the names are served from, so
+/// it ends with `/` if and only if a folder is requested.
+async fn respond(
+    state: &AppState,
+    response: Response,
+    request: &Parts,
+    owner_id: &[u8],
+    names: Vec<String>,
+    path: &str,
+) -> Response {
+    let query = request.uri.query();
+    let is_folder_path = path.ends_with('/');
+
+    if query_param(query, ZIP_PARAM).is_none() {
+        let site = match site::find(state, owner_id, &names).await {
+            Ok(site) => site,
+            Err(error) => return response.internal_error(&error),
+        };
+
+        if let Some(site) = site {
+            return site::respond(
+                state,
+                response,
+                request,
+                owner_id,
+                &site,
+                &names,
+                is_folder_path,
+            )
+            .await;
+        }
+    }
+
+    if is_folder_path {
+        if query_param(query, ZIP_PARAM).is_none() {
+            return respond_with_index(state, response, request, owner_id, &names, path).await;
+        }
+
+        let folder = match find_folder(state, owner_id, &names, query).await {
+            Ok(folder) => folder,
+            Err(error) => return response.internal_error(&error),
+        };
+
+        let Some(folder) = folder else {
+            return response.plain_error(StatusCode::NOT_FOUND);
+        };
+
+        return respond_with_zip(state, response, &request.method, owner_id, folder).await;
+    }
+
+    let file = match find_file(state, owner_id, &names, query).await {
+        Ok(file) => file,
+        Err(error) => return response.internal_error(&error),
+    };
+
+    let Some(file) = file else {
+        return response.plain_error(StatusCode::NOT_FOUND);
+    };
+
+    respond_with_file(response, request, &file)
+}
+
+/// Gets the value of a parameter in a URI query, or an empty string if the parameter has no value.
+///
+/// Returns `None` if the parameter isn't in the query.
+fn query_param<'a>(query: Option<&'a str>, name: &str) -> Option<&'a str> {
+    query?
+        .split('&')
+        .find_map(|param| match param.split_once('=') {
+            Some((param_name, value)) => (param_name == name).then_some(value),
+            None => (param == name).then_some(""),
+        })
+}
+
+/// A folder requested from the content server.
+#[derive(Debug)]
+struct Folder {
+    /// The folder's ID.
+    id: Vec<u8>,
+
+    /// The folder's name.
+    name: String,
+
+    /// The IDs of the folder's ancestors, from the top level down.
+    parent_id_path: Vec<Vec<u8>>,
+
+    /// The names of the folder's ancestors, from the top level down.
+    parent_name_path: Vec<String>,
+
+    /// When the folder was created.
+    created_at: DateTime<Utc>,
+}
+
+/// Finds a folder by its owner and path, as long as the request's query grants access to it. Access
+/// is granted by the folder's ID or by the share key of the folder or any of its ancestors.
+///
+/// Returns `None` if the folder doesn't exist or access to it isn't granted, so unauthorized clients
+/// can't tell whether it exists. The top-level folder is never accessible since it can't be shared.
+///
+/// # Errors
+///
+/// Returns an error if a database query fails.
+async fn find_folder(
+    state: &AppState,
+    owner_id: &[u8],
+    folder_names: &[String],
+    query: Option<&str>,
+) -> sqlx::Result<Option<Folder>> {
+    let Some((name, parent_name_path)) = folder_names.split_last() else {
+        return Ok(None);
     };
 
-    let file_id = match query {
-        Some(query) => query
-            .split('&')
-            .find_map(|param| param.strip_prefix(FILE_ID_QUERY_PREFIX)),
-        None => None,
+    let id_param = query_param(query, ID_PARAM).and_then(|id| id.parse::<Id>().ok());
+    let share_key_param =
+        query_param(query, SHARE_KEY_PARAM).and_then(|share_key| share_key.parse::<Id>().ok());
+
+    if id_param.is_none() && share_key_param.is_none() {
+        return Ok(None);
+    }
+
+    db::transaction!(state.db_pool, async |tx| -> TxResult<_, sqlx::Error> {
+        let Some(folder) = sqlx::query_as!(
+            Folder,
+            "SELECT id, name, parent_id_path, parent_name_path, created_at FROM folders
+                WHERE owner_id = $1 AND parent_name_path = $2 AND name = $3",
+            owner_id,
+            parent_name_path,
+            name,
+        )
+        .fetch_optional(tx.as_mut())
+        .await?
+        else {
+            return Ok(None);
+        };
+
+        if id_param.as_deref() == Some(&folder.id) {
+            return Ok(Some(folder));
+        }
+
+        let Some(share_key) = &share_key_param else {
+            return Ok(None);
+        };
+
+        let mut id_path = folder.parent_id_path.clone();
+        id_path.push(folder.id.clone());
+
+        let is_shared = is_shared_by_key(tx.as_mut(), &id_path, share_key).await?;
+
+        Ok(is_shared.then_some(folder))
+    })
+    .await
+}
+
+/// A file requested from the content server.
+#[derive(Debug)]
+struct File {
+    /// The file's ID.
+    id: Vec<u8>,
+
+    /// The IDs of the file's ancestors, from the top level down.
+    parent_id_path: Vec<Vec<u8>>,
+
+    /// Whether the file is shared publicly.
+    shared: bool,
+
+    /// The file's media type.
+    content_type: String,
+
+    /// The size of the file's contents in bytes.
+    size: i64,
+
+    /// The size of the file's contents in bytes as stored with its encoding.
+    encoded_size: i64,
+
+    /// The encoding the file's contents are stored with.
+    encoding: Option<Encoding>,
+
+    /// How many parts the file's contents are stored in.
+    parts: i32,
+
+    /// When the file was last modified.
+    modified_at: DateTime<Utc>,
+}
+
+/// Finds a file by its owner and path, as long as it's shared or the request's query grants access
+/// to it. Access is granted by the file's ID or by the share key of any of its ancestors.
+///
+/// Returns `None` if the file doesn't exist or access to it isn't granted, so unauthorized clients
+/// can't tell whether it exists.
+///
+/// # Errors
+///
+/// Returns an error if a database query fails.
+async fn find_file(
+    state: &AppState,
+    owner_id: &[u8],
+    file_names: &[String],
+    query: Option<&str>,
+) -> sqlx::Result<Option<File>> {
+    let Some((name, parent_name_path)) = file_names.split_last() else {
+        return Ok(None);
+    };
+
+    let id_param = query_param(query, ID_PARAM).and_then(|id| id.parse::<Id>().ok());
+    let share_key_param =
+        query_param(query, SHARE_KEY_PARAM).and_then(|share_key| share_key.parse::<Id>().ok());
+
+    db::transaction!(state.db_pool, async |tx| -> TxResult<_, sqlx::Error> {
+        let Some(file) = query_file(tx.as_mut(), owner_id, parent_name_path, name).await? else {
+            return Ok(None);
+        };
+
+        if file.shared || id_param.as_deref() == Some(&file.id) {
+            return Ok(Some(file));
+        }
+
+        let Some(share_key) = &share_key_param else {
+            return Ok(None);
+        };
+
+        let is_shared = is_shared_by_key(tx.as_mut(), &file.parent_id_path, share_key).await?;
+
+        Ok(is_shared.then_some(file))
+    })
+    .await
+}
+
+/// Gets a file by its owner and path, regardless of whether it's accessible.
+///
+/// # Errors
+///
+/// Returns an error if the database query fails.
+async fn query_file(
+    conn: &mut PgConnection,
+    owner_id: &[u8],
+    parent_name_path: &[String],
+    name: &str,
+) -> sqlx::Result<Option<File>> {
+    sqlx::query_as!(
+        File,
+        r#"SELECT id, parent_id_path, shared, type as content_type, size, encoded_size,
+            encoding as "encoding: Encoding", parts, modified_at
+            FROM files
+            WHERE owner_id = $1 AND parent_name_path = $2 AND name = $3"#,
+        owner_id,
+        parent_name_path,
+        name,
+    )
+    .fetch_optional(conn)
+    .await
+}
+
+/// Checks whether any of the specified folders has the specified share key.
+///
+/// # Errors
+///
+/// Returns an error if the database query fails.
+async fn is_shared_by_key(
+    conn: &mut PgConnection,
+    folder_ids: &[Vec<u8>],
+    share_key: &[u8],
+) -> sqlx::Result<bool> {
+    sqlx::query_scalar!(
+        r#"SELECT EXISTS (
+            SELECT 1 FROM folders
+                WHERE id = ANY($1) AND share_key = $2
+        ) as "is_shared!""#,
+        folder_ids,
+        share_key,
+    )
+    .fetch_one(conn)
+    .await
+}
+
+/// Responds with a file's contents, using its stored encoding if the client accepts it and decoding
+/// it otherwise.
+fn respond_with_file(mut response: Response, request: &Parts, file: &File) -> Response {
+    let content_type = HeaderValue::from_str(&file.content_type)
+        .unwrap_or(HeaderValue::from_static("application/octet-stream"));
+
+    response.header(CONTENT_TYPE, content_type).header_valid(
+        LAST_MODIFIED,
+        file.modified_at
+            .format("%a, %d %b %Y %H:%M:%S GMT")
+            .to_string(),
+    );
+
+    let send_encoded = match file.encoding {
+        Some(encoding) => {
+            response.header_valid(VARY, "Accept-Encoding");
+
+            let encoding = match encoding {
+                Encoding::Br => "br",
+            };
+
+            let send_encoded = accepts_encoding(&request.headers, encoding);
+
+            if send_encoded {
+                response.header_valid(CONTENT_ENCODING, encoding);
+            }
+
+            send_encoded
+        }
+        None => false,
     };
 
-    // response
-    //     .header_valid(CONTENT_LENGTH, 0)
-    //     .header_valid(CONTENT_TYPE, "")
-    //     .header_valid(LAST_MODIFIED, "");
+    response.header_valid(
+        CONTENT_LENGTH,
+        if send_encoded {
+            file.encoded_size
+        } else {
+            file.size
+        },
+    );
 
     if request.method == Method::HEAD {
         return response;
     }
 
-    response.body(format!(
-        "{user_identifier} - {file_path} - {}",
-        file_id.unwrap_or("None")
-    ))
+    let contents = storage::read(&file.id, file.parts);
+
+    let body = match file.encoding {
+        Some(Encoding::Br) if !send_encoded => {
+            Body::from_stream(ReaderStream::new(BrotliDecoder::new(contents)))
+        }
+        _ => Body::from_stream(ReaderStream::new(contents)),
+    };
+
+    response.body(body)
+}
+
+/// Checks whether a request's `Accept-Encoding` header accepts the specified content coding.
+fn accepts_encoding(headers: &HeaderMap, encoding: &str) -> bool {
+    headers
+        .get_all(ACCEPT_ENCODING)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|coding| {
+            let mut params = coding.split(';').map(str::trim);
+
+            params
+                .next()
+                .is_some_and(|name| name.eq_ignore_ascii_case(encoding))
+                && !params.any(|param| {
+                    param
+                        .strip_prefix("q=")
+                        .and_then(|quality| quality.parse::<f32>().ok())
+                        .is_some_and(|quality| quality <= 0.0)
+                })
+        })
+}
+
+/// The response for a folder with its index enabled.
+#[derive(Debug)]
+enum Index {
+    /// The folder's index file.
+    File(File),
+
+    /// A generated page listing the folder's shared files and subfolders with their indexes
+    /// enabled.
+    Page {
+        /// Whether the folder's parent also has its index enabled.
+        has_parent: bool,
+
+        /// The listed items.
+        entries: Vec<IndexEntry>,
+    },
+}
+
+/// Responds with a folder's index, which is its shared `index.html` file if it has one, or else a
+/// generated page listing its contents.
+///
+/// Only folders whose owners enabled their indexes have one, and the index is public. Responds with
+/// `404 Not Found` for any other folder.
+async fn respond_with_index(
+    state: &AppState,
+    mut response: Response,
+    request: &Parts,
+    owner_id: &[u8],
+    folder_names: &[String],
+    folder_path: &str,
+) -> Response {
+    let Some((name, parent_name_path)) = folder_names.split_last() else {
+        return response.plain_error(StatusCode::NOT_FOUND);
+    };
+
+    let index = db::transaction!(state.db_pool, async |tx| -> TxResult<_, sqlx::Error> {
+        let Some(folder) = sqlx::query!(
+            "SELECT id, parent_id_path FROM folders
+                WHERE owner_id = $1 AND parent_name_path = $2 AND name = $3 AND index_enabled",
+            owner_id,
+            parent_name_path,
+            name,
+        )
+        .fetch_optional(tx.as_mut())
+        .await?
+        else {
+            return Ok(None);
+        };
+
+        let mut contents_id_path = folder.parent_id_path;
+        contents_id_path.push(folder.id);
+
+        let index_file = sqlx::query_as!(
+            File,
+            r#"SELECT id, parent_id_path, shared, type as content_type, size, encoded_size,
+                encoding as "encoding: Encoding", parts, modified_at
+                FROM files
+                WHERE owner_id = $1 AND parent_id_path = $2 AND name = $3 AND shared"#,
+            owner_id,
+            &contents_id_path,
+            INDEX_FILE_NAME,
+        )
+        .fetch_optional(tx.as_mut())
+        .await?;
+
+        if let Some(index_file) = index_file {
+            return Ok(Some(Index::File(index_file)));
+        }
+
+        // A folder served as a custom domain's root has no parent on that domain.
+        let parent_id = contents_id_path
+            .iter()
+            .rev()
+            .nth(1)
+            .filter(|_| folder_path != "/");
+
+        let has_parent = match parent_id {
+            Some(parent_id) => sqlx::query_scalar!(
+                "SELECT index_enabled FROM folders
+                    WHERE id = $1",
+                parent_id,
+            )
+            .fetch_optional(tx.as_mut())
+            .await?
+            .unwrap_or(false),
+            None => false,
+        };
+
+        let subfolders = sqlx::query!(
+            "SELECT name FROM folders
+                WHERE owner_id = $1 AND parent_id_path = $2 AND index_enabled
+                ORDER BY name",
+            owner_id,
+            &contents_id_path,
+        )
+        .fetch_all(tx.as_mut())
+        .await?;
+
+        let files = sqlx::query!(
+            "SELECT name, size, modified_at FROM files
+                WHERE owner_id = $1 AND parent_id_path = $2 AND shared
+                ORDER BY name",
+            owner_id,
+            &contents_id_path,
+        )
+        .fetch_all(tx.as_mut())
+        .await?;
+
+        let entries = subfolders
+            .iter()
+            .map(|subfolder| IndexEntry::folder(&subfolder.name))
+            .chain(
+                files
+                    .iter()
+                    .map(|file| IndexEntry::file(&file.name, file.size, file.modified_at)),
+            )
+            .collect();
+
+        Ok(Some(Index::Page {
+            has_parent,
+            entries,
+        }))
+    })
+    .await;
+
+    let index = match index {
+        Ok(index) => index,
+        Err(error) => return response.internal_error(&error),
+    };
+
+    match index {
+        None => response.plain_error(StatusCode::NOT_FOUND),
+
+        Some(Index::File(index_file)) => respond_with_file(response, request, &index_file),
+
+        Some(Index::Page {
+            has_parent,
+            entries,
+        }) => {
+            let page = IndexPage {
+                path: folder_path,
+                has_parent,
+                entries,
+            };
+
+            let html = match page.render() {
+                Ok(html) => html,
+                Err(error) => return response.internal_error(&error),
+            };
+
+            response.header_valid(CONTENT_TYPE, "text/html; charset=utf-8");
+
+            if request.method == Method::HEAD {
+                return response;
+            }
+
+            response.body(html)
+        }
+    }
+}
+
+/// Responds with a folder and all its contents as a ZIP archive streamed as it's generated.
+async fn respond_with_zip(
+    state: &AppState,
+    mut response: Response,
+    method: &Method,
+    owner_id: &[u8],
+    folder: Folder,
+) -> Response {
+    let filename = format!("{}.zip", folder.name);
+
+    let entries = match list_zip_entries(state, owner_id, folder).await {
+        Ok(entries) => entries,
+        Err(error) => return response.internal_error(&error),
+    };
+
+    response
+        .header_valid(CONTENT_TYPE, "application/zip")
+        .header_valid(
+            CONTENT_DISPOSITION,
+            format!(
+                "attachment; filename*=UTF-8''{}",
+                utf8_percent_encode(&filename, EXT_VALUE),
+            ),
+        );
+
+    if method == Method::HEAD {
+        return response;
+    }
+
+    response.body(zip::stream(entries))
+}
+
+/// Lists a folder and everything in it as ZIP entries, with paths starting from the folder's name.
+///
+/// # Errors
+///
+/// Returns an error if a database query fails.
+async fn list_zip_entries(
+    state: &AppState,
+    owner_id: &[u8],
+    folder: Folder,
+) -> sqlx::Result<Vec<zip::Entry>> {
+    let mut contents_id_path = folder.parent_id_path;
+    contents_id_path.push(folder.id);
+
+    // Only the ancestor names below this are included in each entry's path.
+    let base_depth = folder.parent_name_path.len();
+
+    let entry_path = |parent_name_path: &[String], name: &str| {
+        let mut path = parent_name_path[base_depth..].join("/");
+        path.push('/');
+        path.push_str(name);
+        path
+    };
+
+    let (subfolders, files) =
+        db::transaction!(state.db_pool, async |tx| -> TxResult<_, sqlx::Error> {
+            let subfolders = sqlx::query!(
+                "SELECT name, parent_name_path, created_at FROM folders
+                    WHERE owner_id = $1 AND parent_id_path[:cardinality($2::bytea[])] = $2
+                    ORDER BY parent_name_path, name",
+                owner_id,
+                &contents_id_path,
+            )
+            .fetch_all(tx.as_mut())
+            .await?;
+
+            let files = sqlx::query!(
+                r#"SELECT id, name, parent_name_path, parts, encoding as "encoding: Encoding",
+                    modified_at
+                    FROM files
+                    WHERE owner_id = $1 AND parent_id_path[:cardinality($2::bytea[])] = $2
+                    ORDER BY parent_name_path, name"#,
+                owner_id,
+                &contents_id_path,
+            )
+            .fetch_all(tx.as_mut())
+            .await?;
+
+            Ok((subfolders, files))
+        })
+        .await?;
+
+    let mut entries = Vec::with_capacity(1 + subfolders.len() + files.len());
+
+    entries.push(zip::Entry::Folder {
+        path: folder.name,
+        created_at: folder.created_at,
+    });
+
+    entries.extend(subfolders.into_iter().map(|subfolder| zip::Entry::Folder {
+        path: entry_path(&subfolder.parent_name_path, &subfolder.name),
+        created_at: subfolder.created_at,
+    }));
+
+    entries.extend(files.into_iter().map(|file| zip::Entry::File {
+        path: entry_path(&file.parent_name_path, &file.name),
+        id: file.id,
+        parts: file.parts,
+        encoding: file.encoding,
+        modified_at: file.modified_at,
+    }));
+
+    Ok(entries)
 }
 
 /// Joins a path and a query into one string, separated by a `?` if there exists a query.
@@ -113,3 +801,105 @@ fn concat_path_and_query<'a>(path: &'a str, query: Option<&'a str>) -> Cow<'a, s
 
     path_and_query
 }
+
+#[cfg(test)]
+#[expect(clippy::missing_errors_doc, reason = "see rust-lang/rust-clippy#13391")]
+mod tests {
+    use axum::{http, response::IntoResponse};
+    use sqlx::PgPool;
+
+    use super::*;
+
+    /// The first path segment of the test user's content, which is the user's ID `u` in `base64url`.
+    pub(super) const USER: &str = "dQ";
+
+    /// Creates the test user, whose ID is `u`.
+    pub(super) async fn create_user(pool: &PgPool) -> sqlx::Result<()> {
+        sqlx::raw_sql(
+            r"INSERT INTO users (id, email, name, password_hash)
+                VALUES ('\x75', 'user@example.com', 'User', '');",
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Sends a `GET` request to the content server, returning the response's status, headers, and
+    /// body.
+    pub(super) async fn get(
+        pool: &PgPool,
+        uri: &str,
+    ) -> anyhow::Result<(StatusCode, HeaderMap, String)> {
+        let request = http::Request::builder().uri(uri).body(Body::empty())?;
+
+        let state = AppState {
+            db_pool: pool.clone(),
+        };
+        let (parts, body) = handle(State(state), request)
+            .await
+            .into_response()
+            .into_parts();
+
+        let body = axum::body::to_bytes(body, usize::MAX).await?;
+
+        Ok((
+            parts.status,
+            parts.headers,
+            String::from_utf8(body.to_vec())?,
+        ))
+    }
+
+    #[sqlx::test]
+    #[ignore = "requires a database at `DATABASE_URL`"]
+    async fn index_pages_list_public_contents(pool: PgPool) -> anyhow::Result<()> {
+        create_user(&pool).await?;
+
+        // Folder `a` has its index enabled and contains folders `b` (with its index enabled) and
+        // `c`, and the files `shared.txt` and `unshared.txt`. Folder `d` doesn't have its index
+        // enabled.
+        sqlx::raw_sql(
+            r"INSERT INTO folders (id, name, owner_id, parent_id_path, parent_name_path,
+                    index_enabled)
+                VALUES ('\x61', 'a', '\x75', '{}', '{}', TRUE),
+                    ('\x62', 'b', '\x75', '{\\x61}', '{a}', TRUE),
+                    ('\x63', 'c', '\x75', '{\\x61}', '{a}', FALSE),
+                    ('\x64', 'd', '\x75', '{}', '{}', FALSE);
+
+            INSERT INTO files (id, name, owner_id, parent_id_path, parent_name_path, size,
+                    encoded_size, type, shared)
+                VALUES ('\x65', 'shared.txt', '\x75', '{\\x61}', '{a}', 0, 0, 'text/plain', TRUE),
+                    ('\x66', 'unshared.txt', '\x75', '{\\x61}', '{a}', 0, 0, 'text/plain', FALSE);",
+        )
+        .execute(&pool)
+        .await?;
+
+        for path in ["d/", "a/c/", "x/"] {
+            let (status, _, _) = get(&pool, &format!("/{USER}/{path}")).await?;
+            assert_eq!(status, StatusCode::NOT_FOUND, "{path}");
+        }
+
+        let (status, headers, body) = get(&pool, &format!("/{USER}/a/")).await?;
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(headers[CONTENT_TYPE], "text/html; charset=utf-8");
+
+        assert!(body.contains(r#"<a href="b/">b/</a>"#), "{body}");
+        assert!(
+            body.contains(r#"<a href="shared.txt">shared.txt</a>"#),
+            "{body}",
+        );
+        assert!(!body.contains("c/"), "{body}");
+        assert!(!body.contains("unshared.txt"), "{body}");
+
+        // `a` is at the top level, so it has no parent to link to.
+        assert!(!body.contains("../"), "{body}");
+
+        let (status, _, body) = get(&pool, &format!("/{USER}/a/b/")).await?;
+
+        assert_eq!(status, StatusCode::OK);
+        assert!(body.contains(r#"<a href="../">../</a>"#), "{body}");
+
+        Ok(())
+    }
+}
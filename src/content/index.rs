@@ -0,0 +1,61 @@
+//! Auto-generated index pages listing a folder's contents.
+
+use askama::Template;
+use percent_encoding::utf8_percent_encode;
+use sqlx::types::chrono::{DateTime, Utc};
+
+use crate::percent_encoding::COMPONENT;
+
+/// An HTML page listing the contents of a folder.
+#[derive(Template, Debug)]
+#[template(path = "content/index.html")]
+pub(super) struct IndexPage<'a> {
+    /// The folder's path, starting and ending with `/`.
+    pub(super) path: &'a str,
+
+    /// Whether to link to the parent folder's index page.
+    pub(super) has_parent: bool,
+
+    /// The items to list, with folders first.
+    pub(super) entries: Vec<IndexEntry>,
+}
+
+/// An item listed on an [`IndexPage`].
+#[derive(Debug)]
+pub(super) struct IndexEntry {
+    /// The item's displayed name. Folder names end with `/`.
+    name: String,
+
+    /// The item's URL, relative to the index page.
+    href: String,
+
+    /// The item's displayed size, or empty for folders.
+    size: String,
+
+    /// The item's displayed modification time, or empty for folders.
+    modified_at: String,
+}
+
+impl IndexEntry {
+    /// Constructs an [`IndexEntry`] for a subfolder.
+    pub(super) fn folder(name: &str) -> Self {
+        let href = format!("{}/", utf8_percent_encode(name, COMPONENT));
+
+        Self {
+            name: format!("{name}/"),
+            href,
+            size: String::new(),
+            modified_at: String::new(),
+        }
+    }
+
+    /// Constructs an [`IndexEntry`] for a file.
+    pub(super) fn file(name: &str, size: i64, modified_at: DateTime<Utc>) -> Self {
+        Self {
+            name: name.into(),
+            href: utf8_percent_encode(name, COMPONENT).to_string(),
+            size: size.to_string(),
+            modified_at: modified_at.format("%Y-%m-%d %H:%M UTC").to_string(),
+        }
+    }
+}
@@ -0,0 +1,278 @@
+//! Static-site hosting for folders with site mode enabled.
+
+use std::collections::BTreeMap;
+
+use axum::http::{request::Parts, HeaderName, HeaderValue, StatusCode};
+use sqlx::types::Json;
+
+use super::{concat_path_and_query, query_file, respond_with_file, INDEX_FILE_NAME};
+use crate::{
+    api::validation::SiteHeaders,
+    db::{self, TxResult},
+    response::Response,
+    AppState,
+};
+
+/// The name of the file in a site's folder that's served for paths not found in the site.
+const NOT_FOUND_FILE_NAME: &str = "404.html";
+
+/// The extension tried for extensionless paths in a site with clean URLs.
+const CLEAN_URL_EXTENSION: &str = ".html";
+
+/// A folder with site mode enabled.
+#[derive(Debug)]
+pub(super) struct Site {
+    /// The site folder's own path of names, from the top level down to and including its name.
+    name_path: Vec<String>,
+
+    /// Whether extensionless paths in the site resolve to `.html` files.
+    clean_urls: bool,
+
+    /// Custom headers set on every response from the site. Only headers in
+    /// [`SiteHeaders::ALLOWED_NAMES`] are set.
+    headers: Json<BTreeMap<String, String>>,
+}
+
+/// Finds the innermost folder with site mode enabled that contains the specified path.
+///
+/// # Errors
+///
+/// Returns an error if the database query fails.
+pub(super) async fn find(
+    state: &AppState,
+    owner_id: &[u8],
+    names: &[String],
+) -> sqlx::Result<Option<Site>> {
+    db::transaction!(state.db_pool, async |tx| -> TxResult<_, sqlx::Error> {
+        Ok(sqlx::query_as!(
+            Site,
+            r#"SELECT parent_name_path || name as "name_path!", site_clean_urls as clean_urls,
+                site_headers as "headers: Json<BTreeMap<String, String>>"
+                FROM folders
+                WHERE owner_id = $1 AND site_enabled
+                    AND parent_name_path || name = ($2::text[])[:cardinality(parent_name_path) + 1]
+                ORDER BY cardinality(parent_name_path) DESC
+                LIMIT 1"#,
+            owner_id,
+            names,
+        )
+        .fetch_optional(tx.as_mut())
+        .await?)
+    })
+    .await
+}
+
+/// The outcome of resolving a path in a site.
+#[derive(Debug)]
+enum Resolution {
+    /// A file was found for the path.
+    File(super::File),
+
+    /// The path is a folder, so it should be redirected to with a trailing slash.
+    Folder,
+
+    /// Nothing was found for the path, so the site's not found file is served if it has one.
+    NotFound(Option<super::File>),
+}
+
+/// Responds to a request for a path in a site.
+///
+/// Everything in a site is public. Folder paths serve the folder's `index.html` file, and paths
+/// that aren't found serve the site's `404.html` file if it has one. If the site has clean URLs
+/// enabled, extensionless paths that aren't found resolve to `.html` files.
+pub(super) async fn respond(
+    state: &AppState,
+    mut response: Response,
+    request: &Parts,
+    owner_id: &[u8],
+    site: &Site,
+    names: &[String],
+    is_folder_path: bool,
+) -> Response {
+    for (name, value) in site.headers.iter() {
+        let (Ok(name), Ok(value)) = (
+            HeaderName::try_from(name.as_str()),
+            HeaderValue::try_from(value.as_str()),
+        ) else {
+            continue;
+        };
+
+        // This is already prevented by validation, but it's checked again since headers like
+        // `Content-Security-Policy` are critical for security, and headers stored before the
+        // allowlist changed could otherwise still be set.
+        if !SiteHeaders::ALLOWED_NAMES.contains(&name.as_str()) {
+            continue;
+        }
+
+        response.header(name, value);
+    }
+
+    let resolution = db::transaction!(state.db_pool, async |tx| -> TxResult<_, sqlx::Error> {
+        let file = if is_folder_path {
+            query_file(tx.as_mut(), owner_id, names, INDEX_FILE_NAME).await?
+        } else {
+            let (name, parent_name_path) = names
+                .split_last()
+                .expect("file paths should have a file name");
+
+            let mut file = query_file(tx.as_mut(), owner_id, parent_name_path, name).await?;
+
+            if file.is_none() && site.clean_urls && !name.contains('.') {
+                let name = format!("{name}{CLEAN_URL_EXTENSION}");
+                file = query_file(tx.as_mut(), owner_id, parent_name_path, &name).await?;
+            }
+
+            if file.is_none() {
+                let is_folder = sqlx::query_scalar!(
+                    r#"SELECT EXISTS (
+                        SELECT 1 FROM folders
+                            WHERE owner_id = $1 AND parent_name_path = $2 AND name = $3
+                    ) as "is_folder!""#,
+                    owner_id,
+                    parent_name_path,
+                    name,
+                )
+                .fetch_one(tx.as_mut())
+                .await?;
+
+                if is_folder {
+                    return Ok(Resolution::Folder);
+                }
+            }
+
+            file
+        };
+
+        Ok(match file {
+            Some(file) => Resolution::File(file),
+            None => Resolution::NotFound(
+                query_file(tx.as_mut(), owner_id, &site.name_path, NOT_FOUND_FILE_NAME).await?,
+            ),
+        })
+    })
+    .await;
+
+    let resolution = match resolution {
+        Ok(resolution) => resolution,
+        Err(error) => return response.internal_error(&error),
+    };
+
+    match resolution {
+        Resolution::File(file) => respond_with_file(response, request, &file),
+
+        Resolution::Folder => {
+            let folder_path = format!("{}/", request.uri.path());
+
+            response.permanent_redirect(&concat_path_and_query(&folder_path, request.uri.query()))
+        }
+
+        Resolution::NotFound(Some(not_found_file)) => {
+            let mut response = respond_with_file(response, request, &not_found_file);
+            response.status(StatusCode::NOT_FOUND);
+            response
+        }
+
+        Resolution::NotFound(None) => response.plain_error(StatusCode::NOT_FOUND),
+    }
+}
+
+#[cfg(test)]
+#[expect(clippy::missing_errors_doc, reason = "see rust-lang/rust-clippy#13391")]
+mod tests {
+    use axum::http::header::{CACHE_CONTROL, CONTENT_SECURITY_POLICY, LOCATION, X_FRAME_OPTIONS};
+    use sqlx::PgPool;
+
+    use super::*;
+    use crate::{
+        content::tests::{create_user, get, USER},
+        storage,
+    };
+
+    /// Creates the test user's site folder `s` with clean URLs and custom headers, containing the
+    /// files `a.html` and `404.html`, and the folder `b` containing `index.html`. Each file's
+    /// contents are its ID.
+    async fn create_site(pool: &PgPool) -> anyhow::Result<()> {
+        create_user(pool).await?;
+
+        // `X-Frame-Options` and `Content-Security-Policy` aren't allowed, but they could still be
+        // stored from before the allowlist changed.
+        sqlx::raw_sql(
+            r#"INSERT INTO folders (id, name, owner_id, parent_id_path, parent_name_path,
+                    site_enabled, site_clean_urls, site_headers)
+                VALUES ('\x73', 's', '\x75', '{}', '{}', TRUE, TRUE,
+                        '{"cache-control": "max-age=60", "x-frame-options": "DENY",
+                            "content-security-policy": "default-src *"}'),
+                    ('\x62', 'b', '\x75', '{\\x73}', '{s}', FALSE, FALSE, '{}');
+
+            INSERT INTO files (id, name, owner_id, parent_id_path, parent_name_path, size,
+                    encoded_size, type)
+                VALUES ('site-a', 'a.html', '\x75', '{\\x73}', '{s}', 6, 6, 'text/html'),
+                    ('site-404', '404.html', '\x75', '{\\x73}', '{s}', 8, 8, 'text/html'),
+                    ('site-b', 'index.html', '\x75', '{\\x73,\\x62}', '{s,b}', 6, 6,
+                        'text/html');"#,
+        )
+        .execute(pool)
+        .await?;
+
+        // No other test stores contents for these IDs.
+        for id in ["site-a", "site-404", "site-b"] {
+            storage::write_part(id.as_bytes(), 0, id.as_bytes()).await?;
+        }
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    #[ignore = "requires a database at `DATABASE_URL`"]
+    async fn paths_resolved(pool: PgPool) -> anyhow::Result<()> {
+        create_site(&pool).await?;
+
+        // Each case is a path in the site, and the expected status and body.
+        let cases = [
+            ("a.html", StatusCode::OK, "site-a"),
+            ("a", StatusCode::OK, "site-a"),
+            ("b/", StatusCode::OK, "site-b"),
+            ("b/index", StatusCode::OK, "site-b"),
+            ("", StatusCode::NOT_FOUND, "site-404"),
+            ("x", StatusCode::NOT_FOUND, "site-404"),
+            ("x.html", StatusCode::NOT_FOUND, "site-404"),
+            ("b/x/", StatusCode::NOT_FOUND, "site-404"),
+        ];
+
+        for case @ (path, status, body) in cases {
+            let (actual_status, _, actual_body) = get(&pool, &format!("/{USER}/s/{path}")).await?;
+
+            assert_eq!(actual_status, status, "{case:?}");
+            assert_eq!(actual_body, body, "{case:?}");
+        }
+
+        // A folder requested without a trailing slash is redirected to its index.
+        let (status, headers, _) = get(&pool, &format!("/{USER}/s/b?x")).await?;
+
+        assert_eq!(status, StatusCode::PERMANENT_REDIRECT);
+        assert_eq!(headers[LOCATION], format!("/{USER}/s/b/?x"));
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    #[ignore = "requires a database at `DATABASE_URL`"]
+    async fn only_allowed_headers_set(pool: PgPool) -> anyhow::Result<()> {
+        create_site(&pool).await?;
+
+        for path in ["a", "x"] {
+            let (_, headers, _) = get(&pool, &format!("/{USER}/s/{path}")).await?;
+
+            assert_eq!(headers[CACHE_CONTROL], "max-age=60", "{path}");
+            assert!(!headers.contains_key(X_FRAME_OPTIONS), "{path}");
+            assert_eq!(
+                headers.get_all(CONTENT_SECURITY_POLICY).iter().count(),
+                1,
+                "{path}",
+            );
+            assert_ne!(headers[CONTENT_SECURITY_POLICY], "default-src *", "{path}");
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,252 @@
+//! Streams folders as ZIP archives generated on the fly.
+
+use std::io;
+
+use async_compression::tokio::bufread::BrotliDecoder;
+use async_zip::{tokio::write::ZipFileWriter, Compression, ZipDateTime, ZipEntryBuilder};
+use axum::body::Body;
+use futures_util::{stream, StreamExt};
+use sqlx::types::chrono::{DateTime, Utc};
+use tokio::{
+    io::{AsyncRead, AsyncWrite, DuplexStream},
+    sync::oneshot,
+};
+use tokio_util::{compat::FuturesAsyncWriteCompatExt, io::ReaderStream};
+
+use crate::storage::{self, Encoding};
+
+/// The maximum number of archive bytes buffered between the ZIP writer and the response body.
+const BUFFER_SIZE: usize = 64 * 1024;
+
+/// An entry to write into a ZIP archive.
+#[derive(Debug)]
+pub(super) enum Entry {
+    /// A folder, written as an empty directory entry so empty folders are kept.
+    Folder {
+        /// The folder's path within the archive, with each component separated by `/`.
+        path: String,
+
+        /// When the folder was created.
+        created_at: DateTime<Utc>,
+    },
+
+    /// A file, written with its decoded contents.
+    File {
+        /// The file's path within the archive, with each component separated by `/`.
+        path: String,
+
+        /// The file's ID.
+        id: Vec<u8>,
+
+        /// How many parts the file's contents are stored in.
+        parts: i32,
+
+        /// The encoding the file's contents are stored with.
+        encoding: Option<Encoding>,
+
+        /// When the file was last modified.
+        modified_at: DateTime<Utc>,
+    },
+}
+
+/// Returns a response body that streams a ZIP archive of the specified entries as it's generated.
+///
+/// The archive is never fully buffered in memory or on disk. If generating it fails partway through,
+/// the body stream ends with an error so the client can tell the download is incomplete.
+pub(super) fn stream(entries: Vec<Entry>) -> Body {
+    let (writer, reader) = tokio::io::duplex(BUFFER_SIZE);
+    let (result_sender, result_receiver) = oneshot::channel();
+
+    tokio::spawn(async move {
+        // If sending fails, the response body was dropped, so there's nothing to report to.
+        let _ = result_sender.send(write(writer, entries).await);
+    });
+
+    let result = stream::once(result_receiver).filter_map(async |result| match result {
+        Ok(Ok(())) => None,
+        Ok(Err(error)) => Some(Err(error)),
+        Err(error) => Some(Err(io::Error::other(error))),
+    });
+
+    Body::from_stream(ReaderStream::new(reader).chain(result))
+}
+
+/// Writes a ZIP archive of the specified entries.
+///
+/// # Errors
+///
+/// Returns an error if a file's contents can't be read or the archive can't be written.
+async fn write(writer: DuplexStream, entries: Vec<Entry>) -> io::Result<()> {
+    let mut zip = ZipFileWriter::with_tokio(writer);
+
+    for entry in entries {
+        match entry {
+            Entry::Folder { path, created_at } => {
+                let entry = ZipEntryBuilder::new(format!("{path}/").into(), Compression::Stored)
+                    .last_modification_date(ZipDateTime::from_chrono(&created_at));
+
+                zip.write_entry_whole(entry, &[])
+                    .await
+                    .map_err(io::Error::other)?;
+            }
+
+            Entry::File {
+                path,
+                id,
+                parts,
+                encoding,
+                modified_at,
+            } => {
+                let contents = storage::read(&id, parts);
+
+                // ZIP doesn't support Brotli, so Brotli-encoded contents must be decoded and
+                // recompressed. Files stored without an encoding weren't worth compressing, so
+                // they're stored in the archive as is.
+                match encoding {
+                    Some(Encoding::Br) => {
+                        let entry = ZipEntryBuilder::new(path.into(), Compression::Deflate)
+                            .last_modification_date(ZipDateTime::from_chrono(&modified_at));
+
+                        write_entry(&mut zip, entry, BrotliDecoder::new(contents)).await?;
+                    }
+                    None => {
+                        let entry = ZipEntryBuilder::new(path.into(), Compression::Stored)
+                            .last_modification_date(ZipDateTime::from_chrono(&modified_at));
+
+                        write_entry(&mut zip, entry, contents).await?;
+                    }
+                }
+            }
+        }
+    }
+
+    zip.close().await.map_err(io::Error::other)?;
+
+    Ok(())
+}
+
+/// Writes a streamed entry into a ZIP archive.
+///
+/// # Errors
+///
+/// Returns an error if the contents can't be read or the entry can't be written.
+async fn write_entry<W, R>(
+    zip: &mut ZipFileWriter<W>,
+    entry: ZipEntryBuilder,
+    mut contents: R,
+) -> io::Result<()>
+where
+    W: AsyncWrite + Unpin,
+    R: AsyncRead + Unpin,
+{
+    let mut entry_writer = zip
+        .write_entry_stream(entry)
+        .await
+        .map_err(io::Error::other)?
+        .compat_write();
+
+    tokio::io::copy(&mut contents, &mut entry_writer).await?;
+
+    entry_writer
+        .into_inner()
+        .close()
+        .await
+        .map_err(io::Error::other)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+#[expect(clippy::missing_errors_doc, reason = "see rust-lang/rust-clippy#13391")]
+mod tests {
+    use async_compression::tokio::bufread::BrotliEncoder;
+    use async_zip::base::read::mem::ZipFileReader;
+    use tokio::io::AsyncReadExt;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn folders_archived() -> anyhow::Result<()> {
+        let brotli_contents = b"Brotli-encoded contents".repeat(100);
+        let stored_contents = b"Stored contents";
+
+        // No other test stores contents for these IDs.
+        let brotli_id = b"zip-brotli";
+        let stored_id = b"zip-stored";
+
+        let mut encoded = Vec::new();
+        BrotliEncoder::new(brotli_contents.as_slice())
+            .read_to_end(&mut encoded)
+            .await?;
+
+        // Split the encoded contents across two parts to check they're joined.
+        let (first_part, second_part) = encoded.split_at(encoded.len() / 2);
+        storage::write_part(brotli_id, 0, first_part).await?;
+        storage::write_part(brotli_id, 1, second_part).await?;
+        storage::write_part(stored_id, 0, stored_contents).await?;
+
+        let date = DateTime::UNIX_EPOCH;
+        let entries = vec![
+            Entry::Folder {
+                path: "a".into(),
+                created_at: date,
+            },
+            Entry::File {
+                path: "a/brotli.txt".into(),
+                id: brotli_id.to_vec(),
+                parts: 2,
+                encoding: Some(Encoding::Br),
+                modified_at: date,
+            },
+            Entry::File {
+                path: "a/stored.txt".into(),
+                id: stored_id.to_vec(),
+                parts: 1,
+                encoding: None,
+                modified_at: date,
+            },
+            Entry::Folder {
+                path: "a/b".into(),
+                created_at: date,
+            },
+        ];
+
+        let archive = axum::body::to_bytes(stream(entries), usize::MAX).await?;
+        let zip = ZipFileReader::new(archive.to_vec()).await?;
+
+        let mut archived = Vec::new();
+        for (index, entry) in zip.file().entries().iter().enumerate() {
+            let mut contents = Vec::new();
+            zip.reader_with_entry(index)
+                .await?
+                .read_to_end_checked(&mut contents)
+                .await?;
+
+            archived.push((
+                entry.filename().as_str()?.to_owned(),
+                entry.compression(),
+                contents,
+            ));
+        }
+
+        assert_eq!(
+            archived,
+            [
+                ("a/".to_owned(), Compression::Stored, Vec::new()),
+                (
+                    "a/brotli.txt".to_owned(),
+                    Compression::Deflate,
+                    brotli_contents
+                ),
+                (
+                    "a/stored.txt".to_owned(),
+                    Compression::Stored,
+                    stored_contents.to_vec()
+                ),
+                ("a/b/".to_owned(), Compression::Stored, Vec::new()),
+            ],
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,501 @@
+//! Ownership verification and request routing for users' custom domains.
+
+use std::{
+    error::Error,
+    net::{IpAddr, Ipv4Addr, SocketAddr, ToSocketAddrs},
+    sync::{Arc, LazyLock},
+    time::Duration,
+};
+
+use hickory_resolver::{
+    config::{NameServerConfigGroup, ResolverConfig},
+    name_server::TokioConnectionProvider,
+    proto::rr::rdata::TXT,
+    TokioResolver,
+};
+use moka::future::Cache;
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+
+use crate::{
+    api::validation::CustomDomainName,
+    db::{self, TxResult},
+    AppState,
+};
+
+/// The prefix of the DNS name a domain's TXT challenge record is set on.
+pub(crate) const TXT_CHALLENGE_PREFIX: &str = "_filegarden-challenge.";
+
+/// The path a domain's HTTP challenge file is served from.
+pub(crate) const HTTP_CHALLENGE_PATH: &str = "/.well-known/filegarden-challenge";
+
+/// How long an HTTP challenge request can take before it fails.
+const HTTP_CHALLENGE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The maximum size of an HTTP challenge response body that's checked.
+const HTTP_CHALLENGE_MAX_SIZE: usize = 1024;
+
+/// How long a domain's routing target is cached before it's looked up again.
+///
+/// Changes made through the API invalidate the cache immediately, but changes elsewhere (such as
+/// moving or renaming the target folder) can take this long to apply.
+const CACHE_TTL: Duration = Duration::from_secs(60);
+
+/// The maximum number of domains whose routing targets are cached.
+const CACHE_CAPACITY: u64 = 10_000;
+
+/// How long a domain with no routing target is cached before it's looked up again.
+const NEGATIVE_CACHE_TTL: Duration = Duration::from_secs(10);
+
+/// The maximum number of domains with no routing target that are cached.
+const NEGATIVE_CACHE_CAPACITY: u64 = 1_000;
+
+/// The DNS resolver used to verify domains. This is the system's configured resolver unless the
+/// `DNS_NAMESERVER` environment variable is set to a socket address, in which case only that name
+/// server is used (for example, a local stand-in for testing).
+static RESOLVER: LazyLock<TokioResolver> = LazyLock::new(|| {
+    let Ok(nameserver) = dotenvy::var("DNS_NAMESERVER") else {
+        return TokioResolver::builder_tokio()
+            .expect("system DNS configuration should be valid")
+            .build();
+    };
+
+    let nameserver = nameserver
+        .to_socket_addrs()
+        .ok()
+        .and_then(|mut addresses| addresses.next())
+        .expect("environment variable `DNS_NAMESERVER` should be a valid socket address");
+
+    let config = ResolverConfig::from_parts(
+        None,
+        Vec::new(),
+        NameServerConfigGroup::from_ips_clear(&[nameserver.ip()], nameserver.port(), true),
+    );
+
+    TokioResolver::builder_with_config(config, TokioConnectionProvider::default()).build()
+});
+
+/// The HTTP client used for HTTP challenges, which resolves domains with [`ChallengeResolver`].
+static HTTP_CLIENT: LazyLock<reqwest::Client> = LazyLock::new(|| {
+    reqwest::Client::builder()
+        .dns_resolver(Arc::new(ChallengeResolver))
+        .redirect(reqwest::redirect::Policy::none())
+        .timeout(HTTP_CHALLENGE_TIMEOUT)
+        .build()
+        .expect("HTTP challenge client should be valid")
+});
+
+/// A cache of domains' routing targets.
+static CACHE: LazyLock<Cache<String, Arc<Target>>> = LazyLock::new(|| {
+    Cache::builder()
+        .max_capacity(CACHE_CAPACITY)
+        .time_to_live(CACHE_TTL)
+        .build()
+});
+
+/// A cache of domains with no routing target. It's separate from [`CACHE`] so requests to many
+/// unknown domains can't push real domains out of it.
+static NEGATIVE_CACHE: LazyLock<Cache<String, ()>> = LazyLock::new(|| {
+    Cache::builder()
+        .max_capacity(NEGATIVE_CACHE_CAPACITY)
+        .time_to_live(NEGATIVE_CACHE_TTL)
+        .build()
+});
+
+/// The folder a verified custom domain serves.
+#[derive(Debug)]
+pub(crate) struct Target {
+    /// The ID of the user who owns the folder.
+    pub(crate) owner_id: Vec<u8>,
+
+    /// The folder's own path of names, from the top level down to and including its name.
+    pub(crate) name_path: Vec<String>,
+}
+
+/// Gets the routing target of a verified custom domain, or `None` if the domain isn't a verified
+/// custom domain.
+///
+/// The domain should already be lowercase, without a port or trailing dot. Domains that couldn't
+/// have been added as custom domains are never looked up.
+///
+/// # Errors
+///
+/// Returns an error if the database query fails.
+pub(crate) async fn find_target(
+    state: &AppState,
+    domain: &str,
+) -> sqlx::Result<Option<Arc<Target>>> {
+    if !is_valid(domain) {
+        return Ok(None);
+    }
+
+    if let Some(target) = CACHE.get(domain).await {
+        return Ok(Some(target));
+    }
+
+    if NEGATIVE_CACHE.contains_key(domain) {
+        return Ok(None);
+    }
+
+    let target = db::transaction!(state.db_pool, async |tx| -> TxResult<_, sqlx::Error> {
+        Ok(sqlx::query_as!(
+            Target,
+            r#"SELECT folders.owner_id,
+                folders.parent_name_path || folders.name as "name_path!"
+                FROM custom_domains
+                JOIN folders ON folders.id = custom_domains.folder_id
+                WHERE custom_domains.domain = $1
+                    AND custom_domains.verified_at IS NOT NULL"#,
+            domain,
+        )
+        .fetch_optional(tx.as_mut())
+        .await?
+        .map(Arc::new))
+    })
+    .await?;
+
+    match &target {
+        Some(target) => CACHE.insert(domain.to_owned(), Arc::clone(target)).await,
+        None => NEGATIVE_CACHE.insert(domain.to_owned(), ()).await,
+    }
+
+    Ok(target)
+}
+
+/// Returns whether a domain could be a custom domain, meaning it's already in the normalized form
+/// custom domains are stored in.
+fn is_valid(domain: &str) -> bool {
+    domain
+        .parse::<CustomDomainName>()
+        .is_ok_and(|custom_domain| *custom_domain == domain)
+}
+
+/// Discards a domain's cached routing target so the next request to it is looked up again.
+pub(crate) async fn invalidate(domain: &str) {
+    CACHE.invalidate(domain).await;
+    NEGATIVE_CACHE.invalidate(domain).await;
+}
+
+/// Returns whether the specified challenge token proves ownership of a domain, either by a TXT
+/// record on the domain's challenge name or by an HTTP challenge file served by the domain.
+///
+/// The HTTP challenge is checked against whatever server the domain currently points to, so it
+/// proves ownership before the domain is pointed at File Garden. Domains already pointed at File
+/// Garden must use the TXT challenge, since File Garden never serves challenge files itself.
+pub(crate) async fn verify(domain: &str, challenge_token: &str) -> bool {
+    verify_txt(domain, challenge_token).await || verify_http(domain, challenge_token).await
+}
+
+/// Returns whether a domain has a TXT challenge record set to the specified challenge token.
+async fn verify_txt(domain: &str, challenge_token: &str) -> bool {
+    let Ok(lookup) = RESOLVER
+        .txt_lookup(format!("{TXT_CHALLENGE_PREFIX}{domain}."))
+        .await
+    else {
+        return false;
+    };
+
+    has_challenge_record(lookup.iter(), challenge_token)
+}
+
+/// Returns whether any of a name's TXT records is set to the specified challenge token. A record's
+/// value is all its strings concatenated, since long values are split into multiple strings.
+fn has_challenge_record<'a>(
+    records: impl IntoIterator<Item = &'a TXT>,
+    challenge_token: &str,
+) -> bool {
+    records
+        .into_iter()
+        .any(|txt| txt.txt_data().concat() == challenge_token.as_bytes())
+}
+
+/// Returns whether a domain serves an HTTP challenge file containing the specified challenge token.
+async fn verify_http(domain: &str, challenge_token: &str) -> bool {
+    serves_challenge_file(
+        &format!("http://{domain}{HTTP_CHALLENGE_PATH}/{challenge_token}"),
+        challenge_token,
+    )
+    .await
+}
+
+/// Returns whether a URL serves an HTTP challenge file containing the specified challenge token.
+async fn serves_challenge_file(url: &str, challenge_token: &str) -> bool {
+    let Ok(mut response) = HTTP_CLIENT.get(url).send().await else {
+        return false;
+    };
+
+    if !response.status().is_success() {
+        return false;
+    }
+
+    // Read the body incrementally so a malicious server can't make it buffer a huge response.
+    let mut body = Vec::new();
+
+    loop {
+        match response.chunk().await {
+            Ok(Some(chunk)) if body.len() + chunk.len() <= HTTP_CHALLENGE_MAX_SIZE => {
+                body.extend_from_slice(&chunk);
+            }
+            Ok(None) => break,
+            _ => return false,
+        }
+    }
+
+    body.trim_ascii() == challenge_token.as_bytes()
+}
+
+/// Resolves hostnames for [`HTTP_CLIENT`] using [`RESOLVER`], so HTTP challenges see the same DNS
+/// as TXT challenges.
+///
+/// Only public IP addresses are resolved. Otherwise, anyone could add a domain pointing to an
+/// internal address and use HTTP challenges to probe the server's internal network.
+#[derive(Debug)]
+struct ChallengeResolver;
+
+impl Resolve for ChallengeResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        Box::pin(async move {
+            let lookup = RESOLVER.lookup_ip(name.as_str()).await?;
+
+            let addresses = lookup
+                .into_iter()
+                .filter(|ip| is_public(*ip))
+                .map(|ip| SocketAddr::new(ip, 0))
+                .collect::<Vec<_>>();
+
+            if addresses.is_empty() {
+                return Err(format!("`{}` has no public IP addresses", name.as_str()).into());
+            }
+
+            Ok::<Addrs, Box<dyn Error + Send + Sync>>(Box::new(addresses.into_iter()))
+        })
+    }
+}
+
+/// Returns whether an IP address is publicly routable, ruling out loopback, private, link-local,
+/// unique local, shared (CGNAT), unspecified, broadcast, multicast, documentation, and benchmarking
+/// addresses. NAT64 addresses are judged by the IPv4 address they embed.
+fn is_public(ip: IpAddr) -> bool {
+    let ip = ip.to_canonical();
+
+    if ip.is_loopback() || ip.is_unspecified() || ip.is_multicast() {
+        return false;
+    }
+
+    match ip {
+        IpAddr::V4(ip) => {
+            let [first_octet, second_octet, ..] = ip.octets();
+
+            // `Ipv4Addr::is_shared` and `Ipv4Addr::is_benchmarking` aren't stable, so
+            // `100.64.0.0/10` and `198.18.0.0/15` are checked manually. The "this network" block
+            // `0.0.0.0/8` is also ruled out.
+            let is_shared = first_octet == 100 && (second_octet & 0b1100_0000) == 64;
+            let is_benchmarking = first_octet == 198 && (second_octet & 0b1111_1110) == 18;
+
+            !(ip.is_private()
+                || ip.is_link_local()
+                || ip.is_broadcast()
+                || ip.is_documentation()
+                || is_shared
+                || is_benchmarking
+                || first_octet == 0)
+        }
+        IpAddr::V6(ip) => {
+            let segments = ip.segments();
+
+            // The well-known NAT64 prefix `64:ff9b::/96` can translate to any IPv4 address,
+            // including internal ones.
+            if segments[..6] == [0x64, 0xff9b, 0, 0, 0, 0] {
+                let [.., high, low] = segments;
+                return is_public(IpAddr::V4(Ipv4Addr::from(
+                    (u32::from(high) << 16) | u32::from(low),
+                )));
+            }
+
+            // `Ipv6Addr::is_documentation` isn't stable, so `2001:db8::/32` is checked manually.
+            // The local-use NAT64 prefix `64:ff9b:1::/48` is also ruled out, since it translates
+            // to addresses within a private network.
+            let is_documentation = segments[..2] == [0x2001, 0xdb8];
+            let is_local_nat64 = segments[..3] == [0x64, 0xff9b, 1];
+
+            !(ip.is_unique_local()
+                || ip.is_unicast_link_local()
+                || is_documentation
+                || is_local_nat64)
+        }
+    }
+}
+
+#[cfg(test)]
+#[expect(clippy::missing_errors_doc, reason = "see rust-lang/rust-clippy#13391")]
+mod tests {
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    use axum::{http::StatusCode, response::Redirect, routing::get, Router};
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    #[test]
+    fn internal_addresses_not_public() {
+        let public = [
+            IpAddr::V4(Ipv4Addr::new(93, 184, 215, 14)),
+            IpAddr::V4(Ipv4Addr::new(100, 128, 0, 1)),
+            IpAddr::V4(Ipv4Addr::new(198, 20, 0, 1)),
+            IpAddr::V6(Ipv6Addr::new(
+                0x2606, 0x2800, 0x21f, 0xcb07, 0, 0, 0, 0x6820,
+            )),
+            IpAddr::V6(Ipv6Addr::new(0x64, 0xff9b, 0, 0, 0, 0, 0x5db8, 0xd70e)),
+            IpAddr::V6(Ipv6Addr::new(0x2001, 0xdb9, 0, 0, 0, 0, 0, 1)),
+        ];
+
+        let internal = [
+            IpAddr::V4(Ipv4Addr::LOCALHOST),
+            IpAddr::V4(Ipv4Addr::new(127, 1, 2, 3)),
+            IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)),
+            IpAddr::V4(Ipv4Addr::new(172, 16, 0, 1)),
+            IpAddr::V4(Ipv4Addr::new(192, 168, 1, 1)),
+            IpAddr::V4(Ipv4Addr::new(169, 254, 169, 254)),
+            IpAddr::V4(Ipv4Addr::new(100, 64, 0, 1)),
+            IpAddr::V4(Ipv4Addr::new(100, 127, 255, 254)),
+            IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            IpAddr::V4(Ipv4Addr::new(0, 1, 2, 3)),
+            IpAddr::V4(Ipv4Addr::BROADCAST),
+            IpAddr::V4(Ipv4Addr::new(224, 0, 0, 1)),
+            IpAddr::V6(Ipv6Addr::LOCALHOST),
+            IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+            IpAddr::V6(Ipv6Addr::new(0xfc00, 0, 0, 0, 0, 0, 0, 1)),
+            IpAddr::V6(Ipv6Addr::new(0xfd12, 0x3456, 0, 0, 0, 0, 0, 1)),
+            IpAddr::V6(Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 1)),
+            IpAddr::V6(Ipv6Addr::new(0xff02, 0, 0, 0, 0, 0, 0, 1)),
+            IpAddr::V6(Ipv4Addr::LOCALHOST.to_ipv6_mapped()),
+            IpAddr::V6(Ipv4Addr::new(169, 254, 169, 254).to_ipv6_mapped()),
+            IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1)),
+            IpAddr::V4(Ipv4Addr::new(198, 51, 100, 1)),
+            IpAddr::V4(Ipv4Addr::new(203, 0, 113, 1)),
+            IpAddr::V4(Ipv4Addr::new(198, 18, 0, 1)),
+            IpAddr::V4(Ipv4Addr::new(198, 19, 255, 254)),
+            IpAddr::V6(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1)),
+            IpAddr::V6(Ipv6Addr::new(0x2001, 0xdb8, 0xffff, 0, 0, 0, 0, 1)),
+            IpAddr::V6(Ipv6Addr::new(0x64, 0xff9b, 0, 0, 0, 0, 0x7f00, 1)),
+            IpAddr::V6(Ipv6Addr::new(0x64, 0xff9b, 0, 0, 0, 0, 0x0a00, 1)),
+            IpAddr::V6(Ipv6Addr::new(0x64, 0xff9b, 0, 0, 0, 0, 0xa9fe, 0xa9fe)),
+            IpAddr::V6(Ipv6Addr::new(0x64, 0xff9b, 0, 0, 0, 0, 0xc0a8, 0x0101)),
+            IpAddr::V6(Ipv6Addr::new(0x64, 0xff9b, 1, 0, 0, 0, 0x5db8, 0xd70e)),
+        ];
+
+        for ip in public {
+            assert!(is_public(ip), "{ip} should be public");
+        }
+
+        for ip in internal {
+            assert!(!is_public(ip), "{ip} shouldn't be public");
+        }
+    }
+
+    #[test]
+    fn only_valid_domains_looked_up() {
+        for domain in ["example.com", "sub.example.co.uk", "xn--bcher-kva.example"] {
+            assert!(is_valid(domain), "{domain} should be valid");
+        }
+
+        for domain in [
+            "",
+            "localhost",
+            "127.0.0.1",
+            "Example.com",
+            "example.com.",
+            "bücher.example",
+            "-example.com",
+            "exa mple.com",
+        ] {
+            assert!(!is_valid(domain), "{domain:?} shouldn't be valid");
+        }
+    }
+
+    #[test]
+    fn txt_challenge_checked() {
+        let token = "abc123";
+
+        // Each case is a name's TXT records (each a list of strings) and whether they prove
+        // ownership.
+        let cases: [(&[&[&str]], bool); 5] = [
+            (&[], false),
+            (&[&["abc123"]], true),
+            (&[&["unrelated"], &["abc123"]], true),
+            (&[&["abc", "123"]], true),
+            (&[&["abc1234"], &["ABC123"], &[" abc123"]], false),
+        ];
+
+        for (records, verified) in cases {
+            let records = records
+                .iter()
+                .map(|strings| TXT::new(strings.iter().map(ToString::to_string).collect()))
+                .collect::<Vec<_>>();
+
+            assert_eq!(
+                has_challenge_record(&records, token),
+                verified,
+                "{records:?}",
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn http_challenge_checked() -> anyhow::Result<()> {
+        let token = "abc123";
+
+        let router = Router::new()
+            .route("/correct", get(|| async { "abc123\n" }))
+            .route("/wrong", get(|| async { "abc1234" }))
+            .route(
+                "/error",
+                get(|| async { (StatusCode::NOT_FOUND, "abc123") }),
+            )
+            .route(
+                "/too-large",
+                get(|| async { format!("abc123{}", " ".repeat(HTTP_CHALLENGE_MAX_SIZE)) }),
+            )
+            .route("/redirect", get(|| async { Redirect::to("/correct") }));
+
+        // The server is on a loopback address, but IP addresses in URLs aren't resolved, so
+        // `ChallengeResolver` doesn't reject it. Domains can never be IP addresses.
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await?;
+        let address = listener.local_addr()?;
+        tokio::spawn(async move { axum::serve(listener, router).await });
+
+        // Each case is a path and whether it proves ownership.
+        let cases = [
+            ("/correct", true),
+            ("/wrong", false),
+            ("/error", false),
+            ("/too-large", false),
+            ("/redirect", false),
+            ("/nonexistent", false),
+        ];
+
+        for (path, verified) in cases {
+            assert_eq!(
+                serves_challenge_file(&format!("http://{address}{path}"), token).await,
+                verified,
+                "{path}",
+            );
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn internal_domains_not_resolved() {
+        let Err(error) = ChallengeResolver
+            .resolve("localhost".parse().expect("name should be valid"))
+            .await
+        else {
+            panic!("`localhost` shouldn't resolve");
+        };
+
+        assert!(
+            error.to_string().contains("no public IP addresses"),
+            "{error}"
+        );
+    }
+}
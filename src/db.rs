@@ -71,7 +71,8 @@ where
 pub(crate) type TxResult<T, E> = Result<T, TxError<E>>;
 
 /// Begins a database transaction with the maximum isolation level (`SERIALIZABLE`), retrying if the
-/// database detects a race condition (serialization failure).
+/// database detects a race condition (serialization failure). Retries are counted in the
+/// `db_transaction_retries_total` metric.
 ///
 /// Maximum isolation is used to minimize the possibility of data races. This generally greatly
 /// simplifies database operations and reduces the mental overhead of working with them.
@@ -109,7 +110,9 @@ macro_rules! transaction {
                 match callback().await {
                     Ok(value) => break Ok(value),
                     Err($crate::db::TxError::Abort(error)) => break Err(error),
-                    Err($crate::db::TxError::Retry) => {}
+                    Err($crate::db::TxError::Retry) => {
+                        ::metrics::counter!("db_transaction_retries_total").increment(1);
+                    }
                 }
             }
         }
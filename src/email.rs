@@ -1,16 +1,24 @@
 //! Utilities for sending emails.
 
-use std::{env::VarError, sync::LazyLock};
+use std::{net::IpAddr, sync::LazyLock};
 
 use askama::Template;
-use html2text::render::text_renderer::TrivialDecorator;
+use axum::http::{header::USER_AGENT, HeaderMap};
+use chrono::{DateTime, Utc};
 use lettre::{
     message::{Mailbox, MultiPart},
-    transport::smtp::{authentication::Credentials, extension::ClientId},
-    AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+    Message,
 };
+use sqlx::PgConnection;
+use transport::MAILER;
 
-use crate::WEBSITE_ORIGIN;
+use crate::locale::Locale;
+
+mod dkim;
+pub(crate) mod outbox;
+mod plain_text;
+pub(crate) mod preview;
+mod transport;
 
 /// An email template asking a user to verify their email.
 #[derive(Template, Debug)]
@@ -21,11 +29,21 @@ pub(crate) struct VerificationMessage<'a> {
 
     /// The URL the user must visit to verify their email.
     pub(crate) verification_url: &'a str,
+
+    /// The origin of the website the message links to.
+    pub(crate) website_origin: &'a str,
+
+    /// The locale to write the message in.
+    pub(crate) locale: Locale,
 }
 
 impl MessageTemplate for VerificationMessage<'_> {
     fn subject(&self) -> String {
-        "Verify your email".into()
+        match self.locale {
+            Locale::En => "Verify your email",
+            Locale::Es => "Verifica tu correo",
+        }
+        .into()
     }
 }
 
@@ -36,11 +54,21 @@ impl MessageTemplate for VerificationMessage<'_> {
 pub(crate) struct EmailTakenMessage<'a> {
     /// The email address used to try to sign up.
     pub(crate) email: &'a str,
+
+    /// The origin of the website the message links to.
+    pub(crate) website_origin: &'a str,
+
+    /// The locale to write the message in.
+    pub(crate) locale: Locale,
 }
 
 impl MessageTemplate for EmailTakenMessage<'_> {
     fn subject(&self) -> String {
-        "Sign-up failed for existing account".into()
+        match self.locale {
+            Locale::En => "Sign-up failed for existing account",
+            Locale::Es => "Registro fallido para una cuenta existente",
+        }
+        .into()
     }
 }
 
@@ -53,11 +81,21 @@ pub(crate) struct PasswordResetMessage<'a> {
 
     /// The URL the user must visit to reset their password.
     pub(crate) password_reset_url: &'a str,
+
+    /// The origin of the website the message links to.
+    pub(crate) website_origin: &'a str,
+
+    /// The locale to write the message in.
+    pub(crate) locale: Locale,
 }
 
 impl MessageTemplate for PasswordResetMessage<'_> {
     fn subject(&self) -> String {
-        "Reset your password?".into()
+        match self.locale {
+            Locale::En => "Reset your password?",
+            Locale::Es => "¿Restablecer tu contraseña?",
+        }
+        .into()
     }
 }
 
@@ -68,11 +106,118 @@ impl MessageTemplate for PasswordResetMessage<'_> {
 pub(crate) struct PasswordResetFailedMessage<'a> {
     /// The email address that the password reset was submitted with.
     pub(crate) email: &'a str,
+
+    /// The origin of the website the message links to.
+    pub(crate) website_origin: &'a str,
+
+    /// The locale to write the message in.
+    pub(crate) locale: Locale,
 }
 
 impl MessageTemplate for PasswordResetFailedMessage<'_> {
     fn subject(&self) -> String {
-        "Password reset failed".into()
+        match self.locale {
+            Locale::En => "Password reset failed",
+            Locale::Es => "Restablecimiento de contraseña fallido",
+        }
+        .into()
+    }
+}
+
+/// An email template notifying a user that their password was changed.
+#[derive(Template, Debug)]
+#[template(path = "email/password_changed.html")]
+pub(crate) struct PasswordChangedMessage<'a> {
+    /// The email address of the user whose password was changed.
+    pub(crate) email: &'a str,
+
+    /// When the password was changed.
+    pub(crate) time: DateTime<Utc>,
+
+    /// The client that changed the password.
+    pub(crate) client: &'a ClientInfo,
+
+    /// The origin of the website the message links to.
+    pub(crate) website_origin: &'a str,
+
+    /// The locale to write the message in.
+    pub(crate) locale: Locale,
+}
+
+impl MessageTemplate for PasswordChangedMessage<'_> {
+    fn subject(&self) -> String {
+        match self.locale {
+            Locale::En => "Your password was changed",
+            Locale::Es => "Tu contraseña fue cambiada",
+        }
+        .into()
+    }
+}
+
+/// An email template notifying a user that their account was signed into from a device or IP
+/// address that none of their sessions were created from.
+#[derive(Template, Debug)]
+#[template(path = "email/new_sign_in.html")]
+pub(crate) struct NewSignInMessage<'a> {
+    /// The email address of the user who was signed into.
+    pub(crate) email: &'a str,
+
+    /// When the user was signed into.
+    pub(crate) time: DateTime<Utc>,
+
+    /// The client that signed in.
+    pub(crate) client: &'a ClientInfo,
+
+    /// The origin of the website the message links to.
+    pub(crate) website_origin: &'a str,
+
+    /// The locale to write the message in.
+    pub(crate) locale: Locale,
+}
+
+impl MessageTemplate for NewSignInMessage<'_> {
+    fn subject(&self) -> String {
+        match self.locale {
+            Locale::En => "New sign-in to your account",
+            Locale::Es => "Nuevo inicio de sesión en tu cuenta",
+        }
+        .into()
+    }
+}
+
+/// Approximate information about the client that caused an account event, as shown in security
+/// notifications.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub(crate) struct ClientInfo {
+    /// The client's IP address.
+    pub(crate) ip_address: IpAddr,
+
+    /// The client's `User-Agent` header, if it sent a valid one. It's truncated to
+    /// [`ClientInfo::MAX_USER_AGENT_LENGTH`] characters.
+    pub(crate) user_agent: Option<String>,
+}
+
+impl ClientInfo {
+    /// The maximum number of characters kept from a `User-Agent` header, so clients can't make us
+    /// store or send arbitrarily long ones.
+    const MAX_USER_AGENT_LENGTH: usize = 256;
+
+    /// Gets information about a request's client from its IP address and the request's headers.
+    pub(crate) fn new(ip_address: IpAddr, headers: &HeaderMap) -> Self {
+        let user_agent = headers
+            .get(USER_AGENT)
+            .and_then(|user_agent| user_agent.to_str().ok())
+            .map(|user_agent| {
+                user_agent
+                    .chars()
+                    .take(Self::MAX_USER_AGENT_LENGTH)
+                    .collect()
+            });
+
+        Self {
+            ip_address,
+            user_agent,
+        }
     }
 }
 
@@ -86,7 +231,7 @@ static FROM_MAILBOX: LazyLock<Mailbox> = LazyLock::new(|| {
 
 /// An HTML [`Template`] for an email message.
 pub(crate) trait MessageTemplate: Template {
-    /// Gets the message's subject line.
+    /// Gets the message's subject line, in the message's locale.
     fn subject(&self) -> String;
 
     /// Generates a subject and multipart HTML and plain text body for the email message template.
@@ -95,9 +240,7 @@ pub(crate) trait MessageTemplate: Template {
         subject.push_str(" | File Garden");
 
         let html = self.to_string();
-        let plain = html2text::config::with_decorator(TrivialDecorator::new())
-            .string_from_read(html.as_bytes(), usize::MAX)
-            .expect("message HTML should be convertible to text");
+        let plain = to_plain_text(&html);
 
         Message::builder()
             .from(FROM_MAILBOX.clone())
@@ -108,45 +251,102 @@ pub(crate) trait MessageTemplate: Template {
     }
 }
 
-/// The SMTP transport used to send automated emails.
-static MAILER: LazyLock<AsyncSmtpTransport<Tokio1Executor>> = LazyLock::new(|| {
-    let hostname = dotenvy::var("SMTP_HOSTNAME")
-        .expect("environment variable `SMTP_HOSTNAME` should be a valid string");
-    let username = dotenvy::var("SMTP_USERNAME")
-        .expect("environment variable `SMTP_USERNAME` should be a valid string");
-    let password = dotenvy::var("SMTP_PASSWORD")
-        .expect("environment variable `SMTP_PASSWORD` should be a valid string");
+/// Converts a message's HTML to plain text for clients that don't display HTML. Links are written as
+/// their text alone, so any URL the recipient needs must also be written out as text. List items
+/// are prefixed with markers.
+fn to_plain_text(html: &str) -> String {
+    html2text::config::with_decorator(plain_text::Decorator::new())
+        .string_from_read(html.as_bytes(), usize::MAX)
+        .expect("message HTML should be convertible to text")
+}
 
-    let mut smtp_transport = AsyncSmtpTransport::<Tokio1Executor>::relay(&hostname)
-        .expect("SMTP relay couldn't be initialized")
-        .credentials(Credentials::new(username, password));
+/// Checks whether the mail server is reachable.
+pub(crate) async fn is_reachable() -> bool {
+    MAILER.is_reachable().await
+}
 
-    match dotenvy::var("SMTP_HELO_DOMAIN") {
-        // If the environment variable is unset, let `lettre` default to using the OS hostname.
-        Err(dotenvy::Error::EnvVar(VarError::NotPresent)) => {}
+/// A trait for sending messages using the mail transport configuration from `.env`.
+pub(crate) trait SendMessage {
+    /// Signs the message with DKIM if enabled, then queues it in the [`outbox`] to be sent in the
+    /// background once the transaction on the specified connection commits.
+    ///
+    /// Delivery errors never propagate to end users. Otherwise, users could tell if an email sent
+    /// successfully or not, which can allow for user enumeration in some circumstances.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the message can't be queued.
+    async fn send(self, conn: &mut PgConnection) -> sqlx::Result<()>;
+}
 
-        helo_domain => {
-            let helo_domain = helo_domain
-                .expect("environment variable `SMTP_HELO_DOMAIN` should be a valid string if set");
+impl SendMessage for Message {
+    async fn send(mut self, conn: &mut PgConnection) -> sqlx::Result<()> {
+        dkim::sign(&mut self);
 
-            smtp_transport = smtp_transport.hello_name(ClientId::Domain(helo_domain));
-        }
+        outbox::enqueue(conn, &self).await
     }
+}
 
-    smtp_transport.build()
-});
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-/// A trait for sending messages using the SMTP configuration from `.env`.
-pub(crate) trait SendMessage {
-    /// Sends the message in the background.
-    ///
-    /// Errors are ignored so they can't propagate to end users. Otherwise, users could tell if an
-    /// email sent successfully or not, which can allow for user enumeration in some circumstances.
-    fn send(self);
-}
+    #[test]
+    fn messages_have_clean_plain_text() {
+        let message = VerificationMessage {
+            email: "user@example.com",
+            verification_url: "https://example.com/verify?code=123",
+            website_origin: "https://example.com",
+            locale: Locale::En,
+        };
 
-impl SendMessage for Message {
-    fn send(self) {
-        tokio::spawn(MAILER.send(self));
+        assert_eq!(
+            to_plain_text(&message.to_string()),
+            "File Garden
+
+Hi there,
+
+To verify the email user@example.com for your File Garden account, click the button below:
+
+Verify email
+
+If the button doesn't work, visit the following link:
+
+https://example.com/verify?code=123
+
+If you didn't request this email, you can safely ignore it.
+
+Thanks for using File Garden. :)
+
+You received this email because someone used the address user@example.com on File Garden. We \
+only send emails about your account, so there's no mailing list to unsubscribe from.
+",
+        );
+
+        // Every message in every locale is checked for leftovers from its HTML. Their exact text is
+        // checked by the preview snapshots.
+        for sample in preview::Sample::ALL {
+            for locale in Locale::ALL {
+                let name = format!("{} in {}", sample.name(), locale.as_str());
+                let plain = sample.render(
+                    preview::SAMPLE_WEBSITE_ORIGIN,
+                    locale,
+                    preview::Format::Text,
+                );
+
+                assert!(
+                    !plain.contains(['<', '>', '{', '}']),
+                    "{name} shouldn't have markup",
+                );
+                assert!(
+                    !plain.contains("\n\n\n"),
+                    "{name} shouldn't have consecutive blank lines",
+                );
+
+                for line in plain.lines() {
+                    assert_eq!(line, line.trim(), "{name} shouldn't have padded lines");
+                }
+            }
+        }
     }
 }
@@ -0,0 +1,308 @@
+//! A durable queue of emails to send, stored in the database. Emails are queued by [`enqueue`] in the
+//! same transaction as the change that triggers them, then delivered by [`run`].
+
+use std::time::{Duration, Instant};
+
+use lettre::{address::Envelope, Message};
+use sqlx::{postgres::PgListener, types::Json as SqlJson, PgConnection, PgPool};
+use tokio_util::sync::CancellationToken;
+
+use super::transport::MAILER;
+use crate::db::{self, TxResult};
+
+/// The Postgres notification channel workers listen to for newly queued emails.
+const CHANNEL: &str = "email_outbox";
+
+/// How often the outbox is checked for emails due to be sent, in case a notification is missed or
+/// a failed email is due to be retried.
+const POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+/// How long a worker has to send an email after claiming it. If the worker doesn't record the
+/// outcome by then (such as from crashing), the email can be claimed again.
+const LEASE: Duration = Duration::from_secs(5 * 60);
+
+/// The number of failed delivery attempts after which an email is dead and never retried.
+const MAX_ATTEMPTS: i32 = 10;
+
+/// The delay before retrying an email after its first failed attempt. The delay doubles after each
+/// further failed attempt.
+const BASE_RETRY_DELAY: Duration = Duration::from_secs(30);
+
+/// The maximum delay before retrying an email.
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60 * 60);
+
+/// How long dead emails are kept for review before they're purged from the outbox.
+const DEAD_RETENTION: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// How often each worker purges dead emails past [`DEAD_RETENTION`].
+const PURGE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// An email claimed from the outbox for delivery.
+#[derive(Debug)]
+struct OutboxEmail {
+    /// The email's ID in the outbox.
+    id: i64,
+
+    /// The SMTP envelope to send the email with.
+    envelope: SqlJson<Envelope>,
+
+    /// The formatted email message.
+    message: Vec<u8>,
+
+    /// The number of delivery attempts, including the current one.
+    attempts: i32,
+}
+
+/// Queues a message in the outbox. It's only sent once the transaction on the specified connection
+/// commits.
+///
+/// # Errors
+///
+/// Returns an error if a database query fails.
+pub(super) async fn enqueue(conn: &mut PgConnection, message: &Message) -> sqlx::Result<()> {
+    sqlx::query!(
+        "INSERT INTO email_outbox (envelope, message)
+            VALUES ($1, $2)",
+        SqlJson(message.envelope()) as _,
+        message.formatted(),
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    // Postgres only delivers this notification once the transaction commits.
+    sqlx::query!("SELECT pg_notify($1, '')", CHANNEL)
+        .execute(conn)
+        .await?;
+
+    Ok(())
+}
+
+/// Delivers emails from the outbox until cancelled, retrying failed deliveries with exponential
+/// backoff. Any number of workers can run at once, even across server instances.
+///
+/// Dead emails are periodically purged once they're older than [`DEAD_RETENTION`].
+///
+/// When cancelled, this finishes sending the email it's currently sending before returning. Unsent
+/// emails stay in the outbox for the next time a worker runs.
+pub(crate) async fn run(db_pool: PgPool, cancellation: CancellationToken) {
+    let mut listener = match listen(&db_pool).await {
+        Ok(listener) => Some(listener),
+        Err(error) => {
+            tracing::warn!(%error, "Couldn't listen for queued emails, so only polling");
+            None
+        }
+    };
+
+    let mut purged_at = None::<Instant>;
+
+    while !cancellation.is_cancelled() {
+        if purged_at.is_none_or(|purged_at| purged_at.elapsed() >= PURGE_INTERVAL) {
+            purged_at = Some(Instant::now());
+
+            if let Err(error) = purge(&db_pool).await {
+                tracing::error!(%error, "Couldn't purge dead emails from outbox");
+            }
+        }
+
+        match claim(&db_pool).await {
+            Ok(Some(email)) => {
+                deliver(&db_pool, email).await;
+                continue;
+            }
+            Ok(None) => {}
+            Err(error) => tracing::error!(%error, "Couldn't claim email from outbox"),
+        }
+
+        let notification = async {
+            match &mut listener {
+                Some(listener) => listener.recv().await.map(|_| ()),
+                None => std::future::pending().await,
+            }
+        };
+
+        // Wait until an email may be due.
+        tokio::select! {
+            () = cancellation.cancelled() => {}
+            () = tokio::time::sleep(POLL_INTERVAL) => {}
+            result = notification => {
+                if let Err(error) = result {
+                    tracing::warn!(%error, "Stopped listening for queued emails, so only polling");
+                    listener = None;
+                }
+            }
+        }
+    }
+}
+
+/// Connects a listener for notifications of newly queued emails.
+///
+/// # Errors
+///
+/// Returns an error if the listener fails to connect.
+async fn listen(db_pool: &PgPool) -> sqlx::Result<PgListener> {
+    let mut listener = PgListener::connect_with(db_pool).await?;
+    listener.listen(CHANNEL).await?;
+
+    Ok(listener)
+}
+
+/// Claims the email in the outbox that's been due to be sent for the longest, if any, leasing it so
+/// no other worker claims it while it's being sent.
+///
+/// # Errors
+///
+/// Returns an error if a database query fails.
+async fn claim(db_pool: &PgPool) -> sqlx::Result<Option<OutboxEmail>> {
+    db::transaction!(db_pool, async |tx| -> TxResult<_, sqlx::Error> {
+        Ok(sqlx::query_as!(
+            OutboxEmail,
+            r#"UPDATE email_outbox
+                SET attempts = attempts + 1,
+                    next_attempt_at = now() + make_interval(secs => $1)
+                WHERE id = (
+                    SELECT id FROM email_outbox
+                        WHERE dead_at IS NULL AND next_attempt_at <= now()
+                        ORDER BY next_attempt_at
+                        LIMIT 1
+                        FOR UPDATE SKIP LOCKED
+                )
+                RETURNING id, envelope as "envelope: SqlJson<Envelope>", message as "message!",
+                    attempts"#,
+            LEASE.as_secs_f64(),
+        )
+        .fetch_optional(tx.as_mut())
+        .await?)
+    })
+    .await
+}
+
+/// Sends a claimed email, then removes it from the outbox if it was sent, or schedules it to be
+/// retried if it wasn't. If it has no attempts left, it's marked dead instead, and its message is
+/// dropped.
+async fn deliver(db_pool: &PgPool, email: OutboxEmail) {
+    let result = MAILER.send_raw(&email.envelope, &email.message).await;
+
+    let record_result = match result {
+        Ok(_) => {
+            metrics::counter!("emails_sent_total", "outcome" => "success").increment(1);
+
+            db::transaction!(db_pool, async |tx| -> TxResult<_, sqlx::Error> {
+                sqlx::query!(
+                    "DELETE FROM email_outbox
+                        WHERE id = $1",
+                    email.id,
+                )
+                .execute(tx.as_mut())
+                .await?;
+
+                Ok(())
+            })
+            .await
+        }
+        Err(error) => {
+            let dead = email.attempts >= MAX_ATTEMPTS;
+
+            if dead {
+                tracing::error!(%error, id = email.id, "Gave up sending email");
+                metrics::counter!("emails_sent_total", "outcome" => "dead").increment(1);
+            } else {
+                tracing::warn!(%error, id = email.id, "Couldn't send email, so retrying later");
+                metrics::counter!("emails_sent_total", "outcome" => "failure").increment(1);
+            }
+
+            let error = error.to_string();
+            let retry_delay = retry_delay(email.attempts);
+
+            db::transaction!(db_pool, async |tx| -> TxResult<_, sqlx::Error> {
+                sqlx::query!(
+                    "UPDATE email_outbox
+                        SET last_error = $1,
+                            next_attempt_at = now() + make_interval(secs => $2),
+                            dead_at = CASE WHEN $3 THEN now() END,
+                            message = CASE WHEN $3 THEN NULL ELSE message END
+                        WHERE id = $4",
+                    error,
+                    retry_delay.as_secs_f64(),
+                    dead,
+                    email.id,
+                )
+                .execute(tx.as_mut())
+                .await?;
+
+                Ok(())
+            })
+            .await
+        }
+    };
+
+    if let Err(error) = record_result {
+        // The email's lease will expire, so it'll be claimed again. That may send it twice, but
+        // that's better than never sending it.
+        tracing::error!(%error, id = email.id, "Couldn't record email delivery outcome");
+    }
+}
+
+/// Deletes dead emails older than [`DEAD_RETENTION`] from the outbox.
+///
+/// # Errors
+///
+/// Returns an error if a database query fails.
+async fn purge(db_pool: &PgPool) -> sqlx::Result<()> {
+    db::transaction!(db_pool, async |tx| -> TxResult<_, sqlx::Error> {
+        let purged = sqlx::query!(
+            "DELETE FROM email_outbox
+                WHERE dead_at < now() - make_interval(secs => $1)",
+            DEAD_RETENTION.as_secs_f64(),
+        )
+        .execute(tx.as_mut())
+        .await?
+        .rows_affected();
+
+        if purged > 0 {
+            tracing::info!(purged, "Purged dead emails from outbox");
+        }
+
+        Ok(())
+    })
+    .await
+}
+
+/// Returns how long to wait before retrying an email after the specified number of failed attempts.
+fn retry_delay(attempts: i32) -> Duration {
+    let exponent = u32::try_from(attempts.saturating_sub(1))
+        .unwrap_or(0)
+        .min(16);
+
+    BASE_RETRY_DELAY
+        .saturating_mul(2_u32.pow(exponent))
+        .min(MAX_RETRY_DELAY)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[sqlx::test]
+    #[ignore = "requires a database at `DATABASE_URL`"]
+    async fn only_expired_dead_emails_purged(pool: PgPool) -> anyhow::Result<()> {
+        sqlx::raw_sql(
+            "INSERT INTO email_outbox (envelope, message, dead_at) VALUES
+                ('{}', 'pending', NULL),
+                ('{}', NULL, now() - interval '1 day'),
+                ('{}', NULL, now() - interval '8 days')",
+        )
+        .execute(&pool)
+        .await?;
+
+        purge(&pool).await?;
+
+        let remaining: Vec<Option<Vec<u8>>> =
+            sqlx::query_scalar("SELECT message FROM email_outbox ORDER BY id")
+                .fetch_all(&pool)
+                .await?;
+
+        assert_eq!(remaining, [Some(b"pending".to_vec()), None]);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,96 @@
+//! See [`Decorator`].
+
+use html2text::render::text_renderer::{TaggedLine, TextDecorator, TrivialDecorator};
+
+/// Decorates the plain text generated from a message's HTML. Markup is left out like with
+/// [`TrivialDecorator`], except list items are prefixed with markers so they're still readable as a
+/// list.
+#[derive(Clone, Debug)]
+pub(super) struct Decorator(TrivialDecorator);
+
+impl Decorator {
+    /// Constructs a new [`Decorator`].
+    pub(super) fn new() -> Self {
+        Self(TrivialDecorator::new())
+    }
+}
+
+impl TextDecorator for Decorator {
+    type Annotation = ();
+
+    fn decorate_link_start(&mut self, url: &str) -> (String, Self::Annotation) {
+        self.0.decorate_link_start(url)
+    }
+
+    fn decorate_link_end(&mut self) -> String {
+        self.0.decorate_link_end()
+    }
+
+    fn decorate_em_start(&self) -> (String, Self::Annotation) {
+        self.0.decorate_em_start()
+    }
+
+    fn decorate_em_end(&self) -> String {
+        self.0.decorate_em_end()
+    }
+
+    fn decorate_strong_start(&self) -> (String, Self::Annotation) {
+        self.0.decorate_strong_start()
+    }
+
+    fn decorate_strong_end(&self) -> String {
+        self.0.decorate_strong_end()
+    }
+
+    fn decorate_strikeout_start(&self) -> (String, Self::Annotation) {
+        self.0.decorate_strikeout_start()
+    }
+
+    fn decorate_strikeout_end(&self) -> String {
+        self.0.decorate_strikeout_end()
+    }
+
+    fn decorate_code_start(&self) -> (String, Self::Annotation) {
+        self.0.decorate_code_start()
+    }
+
+    fn decorate_code_end(&self) -> String {
+        self.0.decorate_code_end()
+    }
+
+    fn decorate_preformat_first(&self) -> Self::Annotation {
+        self.0.decorate_preformat_first();
+    }
+
+    fn decorate_preformat_cont(&self) -> Self::Annotation {
+        self.0.decorate_preformat_cont();
+    }
+
+    fn decorate_image(&mut self, src: &str, title: &str) -> (String, Self::Annotation) {
+        self.0.decorate_image(src, title)
+    }
+
+    fn header_prefix(&self, level: usize) -> String {
+        self.0.header_prefix(level)
+    }
+
+    fn quote_prefix(&self) -> String {
+        self.0.quote_prefix()
+    }
+
+    fn unordered_item_prefix(&self) -> String {
+        "- ".into()
+    }
+
+    fn ordered_item_prefix(&self, i: i64) -> String {
+        format!("{i}. ")
+    }
+
+    fn make_subblock_decorator(&self) -> Self {
+        Self::new()
+    }
+
+    fn finalise(&mut self, links: Vec<String>) -> Vec<TaggedLine<Self::Annotation>> {
+        self.0.finalise(links)
+    }
+}
@@ -0,0 +1,243 @@
+//! Renders each email template with sample data, so templates can be previewed without triggering
+//! the flows that send them. See [`command`].
+
+use std::net::{IpAddr, Ipv4Addr};
+
+use anyhow::bail;
+use chrono::{DateTime, TimeZone, Utc};
+use lettre::message::Mailbox;
+
+use super::{
+    to_plain_text, ClientInfo, EmailTakenMessage, MessageTemplate, NewSignInMessage,
+    PasswordChangedMessage, PasswordResetFailedMessage, PasswordResetMessage, VerificationMessage,
+};
+use crate::{locale::Locale, WEBSITE_ORIGIN};
+
+/// The email address every sample is sent to.
+const SAMPLE_EMAIL: &str = "user@example.com";
+
+/// The website origin samples link to in tests, so snapshots don't depend on the environment.
+#[cfg(test)]
+pub(super) const SAMPLE_WEBSITE_ORIGIN: &str = "https://example.com";
+
+/// An email template that can be previewed.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(super) enum Sample {
+    /// See [`VerificationMessage`].
+    Verification,
+
+    /// See [`EmailTakenMessage`].
+    EmailTaken,
+
+    /// See [`PasswordResetMessage`].
+    PasswordReset,
+
+    /// See [`PasswordResetFailedMessage`].
+    PasswordResetFailed,
+
+    /// See [`PasswordChangedMessage`].
+    PasswordChanged,
+
+    /// See [`NewSignInMessage`].
+    NewSignIn,
+}
+
+impl Sample {
+    /// Every sample.
+    pub(super) const ALL: [Self; 6] = [
+        Self::Verification,
+        Self::EmailTaken,
+        Self::PasswordReset,
+        Self::PasswordResetFailed,
+        Self::PasswordChanged,
+        Self::NewSignIn,
+    ];
+
+    /// Gets the sample's name, which is its template's file name without the extension.
+    pub(super) fn name(self) -> &'static str {
+        match self {
+            Self::Verification => "verification",
+            Self::EmailTaken => "email_taken",
+            Self::PasswordReset => "password_reset",
+            Self::PasswordResetFailed => "password_reset_failed",
+            Self::PasswordChanged => "password_changed",
+            Self::NewSignIn => "new_sign_in",
+        }
+    }
+
+    /// Renders the sample's template with sample data in the specified locale and format, linking
+    /// to the specified website origin.
+    pub(super) fn render(self, website_origin: &str, locale: Locale, format: Format) -> String {
+        let verification_url = format!("{website_origin}/verify-email?token=SAMPLE_TOKEN");
+        let password_reset_url = format!("{website_origin}/password-reset?token=SAMPLE_TOKEN");
+        let client = ClientInfo {
+            ip_address: IpAddr::V4(Ipv4Addr::new(203, 0, 113, 7)),
+            user_agent: Some(
+                "Mozilla/5.0 (X11; Linux x86_64; rv:131.0) Gecko/20100101 Firefox/131.0".into(),
+            ),
+        };
+        let time = sample_time();
+
+        match self {
+            Self::Verification => format.render(&VerificationMessage {
+                email: SAMPLE_EMAIL,
+                verification_url: &verification_url,
+                website_origin,
+                locale,
+            }),
+            Self::EmailTaken => format.render(&EmailTakenMessage {
+                email: SAMPLE_EMAIL,
+                website_origin,
+                locale,
+            }),
+            Self::PasswordReset => format.render(&PasswordResetMessage {
+                email: SAMPLE_EMAIL,
+                password_reset_url: &password_reset_url,
+                website_origin,
+                locale,
+            }),
+            Self::PasswordResetFailed => format.render(&PasswordResetFailedMessage {
+                email: SAMPLE_EMAIL,
+                website_origin,
+                locale,
+            }),
+            Self::PasswordChanged => format.render(&PasswordChangedMessage {
+                email: SAMPLE_EMAIL,
+                time,
+                client: &client,
+                website_origin,
+                locale,
+            }),
+            Self::NewSignIn => format.render(&NewSignInMessage {
+                email: SAMPLE_EMAIL,
+                time,
+                client: &client,
+                website_origin,
+                locale,
+            }),
+        }
+    }
+}
+
+/// A form an email is sent in.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(super) enum Format {
+    /// The message's HTML body.
+    Html,
+
+    /// The message's plain text body, as generated from its HTML.
+    Text,
+
+    /// The full MIME message. Its date, message ID, and multipart boundary are different each
+    /// time.
+    Mime,
+}
+
+impl Format {
+    /// Renders a message template in this format.
+    fn render(self, template: &impl MessageTemplate) -> String {
+        match self {
+            Self::Html => template.to_string(),
+            Self::Text => to_plain_text(&template.to_string()),
+            Self::Mime => {
+                let message = template.to(Mailbox::new(
+                    None,
+                    SAMPLE_EMAIL.parse().expect("sample email should be valid"),
+                ));
+
+                String::from_utf8_lossy(&message.formatted()).into_owned()
+            }
+        }
+    }
+}
+
+/// The time every sample security notification is sent at, so previews are deterministic.
+fn sample_time() -> DateTime<Utc> {
+    Utc.with_ymd_and_hms(2026, 1, 2, 3, 4, 5)
+        .single()
+        .expect("sample time should be valid")
+}
+
+/// Runs the `preview-email` command, which prints an email template rendered with sample data.
+/// It's meant for developing email templates.
+///
+/// ```sh
+/// backend preview-email <template> [<locale>] [html|text|mime]
+/// ```
+///
+/// The locale defaults to the default locale, and the format defaults to `html`. Without any
+/// arguments, the names of the templates are listed instead.
+///
+/// # Errors
+///
+/// Returns an error if the arguments are invalid.
+pub(crate) fn command(mut args: impl Iterator<Item = String>) -> anyhow::Result<()> {
+    let Some(name) = args.next() else {
+        for sample in Sample::ALL {
+            println!("{}", sample.name());
+        }
+
+        return Ok(());
+    };
+
+    let Some(sample) = Sample::ALL.into_iter().find(|sample| sample.name() == name) else {
+        bail!("unknown email template `{name}`");
+    };
+
+    let locale = match args.next() {
+        Some(locale) => Locale::from_language_code(&locale)
+            .ok_or_else(|| anyhow::anyhow!("unsupported locale `{locale}`"))?,
+        None => Locale::default(),
+    };
+
+    let format = match args.next().as_deref() {
+        None | Some("html") => Format::Html,
+        Some("text") => Format::Text,
+        Some("mime") => Format::Mime,
+        Some(format) => bail!("unknown format `{format}`, expected `html`, `text`, or `mime`"),
+    };
+
+    println!("{}", sample.render(&WEBSITE_ORIGIN, locale, format));
+
+    Ok(())
+}
+
+#[cfg(test)]
+#[expect(clippy::missing_errors_doc, reason = "see rust-lang/rust-clippy#13391")]
+mod tests {
+    use std::{env, fs, path::PathBuf};
+
+    use super::*;
+
+    /// Checks each template's plain text in each locale against its snapshot in
+    /// `src/email/preview/snapshots/`. Set the `UPDATE_SNAPSHOTS` environment variable to overwrite
+    /// the snapshots with the current output instead.
+    #[test]
+    fn plain_text_matches_snapshots() -> anyhow::Result<()> {
+        let snapshot_directory =
+            PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("src/email/preview/snapshots");
+        let update = env::var_os("UPDATE_SNAPSHOTS").is_some();
+
+        for sample in Sample::ALL {
+            for locale in Locale::ALL {
+                let path =
+                    snapshot_directory.join(format!("{}.{}.txt", sample.name(), locale.as_str()));
+                let plain = sample.render(SAMPLE_WEBSITE_ORIGIN, locale, Format::Text);
+
+                if update {
+                    fs::create_dir_all(&snapshot_directory)?;
+                    fs::write(&path, plain)?;
+                } else {
+                    assert_eq!(
+                        plain,
+                        fs::read_to_string(&path)?,
+                        "{} doesn't match",
+                        path.display(),
+                    );
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
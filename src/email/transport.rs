@@ -0,0 +1,178 @@
+//! See [`Transport`].
+
+use std::{env::VarError, error::Error, sync::LazyLock};
+
+use lettre::{
+    address::Envelope,
+    transport::{
+        smtp::{authentication::Credentials, extension::ClientId, AsyncSmtpTransportBuilder},
+        stub::AsyncStubTransport,
+    },
+    AsyncFileTransport, AsyncSmtpTransport, AsyncTransport, Tokio1Executor,
+};
+
+/// The transport used to send automated emails, configured by the `MAIL_TRANSPORT` environment
+/// variable:
+///
+/// - `smtp` (the default) sends through an SMTP relay with TLS, authenticated by `SMTP_USERNAME`
+///   and `SMTP_PASSWORD`.
+/// - `smtp-plain` sends through an SMTP server without TLS or authentication, such as a local mail
+///   catcher. `SMTP_PORT` defaults to 25.
+/// - `file` writes each email to an `.eml` file in `MAIL_DIRECTORY`.
+/// - `memory` keeps each email in memory.
+///
+/// Both SMTP transports connect to `SMTP_HOSTNAME`.
+pub(super) static MAILER: LazyLock<Transport> =
+    LazyLock::new(|| match dotenvy::var("MAIL_TRANSPORT").as_deref() {
+        Err(_) | Ok("smtp") => Transport::Smtp(smtp_relay()),
+        Ok("smtp-plain") => Transport::Smtp(smtp_plain()),
+        Ok("file") => Transport::File(AsyncFileTransport::new(
+            dotenvy::var("MAIL_DIRECTORY")
+                .expect("environment variable `MAIL_DIRECTORY` should be a valid string"),
+        )),
+        Ok("memory") => Transport::Memory(AsyncStubTransport::new_ok()),
+        Ok(_) => panic!(
+            "environment variable `MAIL_TRANSPORT` should be `smtp`, `smtp-plain`, `file`, or \
+            `memory`"
+        ),
+    });
+
+/// A way to send emails.
+#[derive(Debug)]
+pub(super) enum Transport {
+    /// Sends emails through an SMTP server.
+    Smtp(AsyncSmtpTransport<Tokio1Executor>),
+
+    /// Writes emails to files instead of sending them. Only for local development.
+    File(AsyncFileTransport<Tokio1Executor>),
+
+    /// Keeps emails in memory instead of sending them, so tests can inspect them.
+    Memory(AsyncStubTransport),
+}
+
+impl Transport {
+    /// Sends a formatted email message.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the message fails to send.
+    pub(super) async fn send_raw(
+        &self,
+        envelope: &Envelope,
+        message: &[u8],
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        match self {
+            Self::Smtp(transport) => {
+                transport.send_raw(envelope, message).await?;
+            }
+            Self::File(transport) => {
+                transport.send_raw(envelope, message).await?;
+            }
+            Self::Memory(transport) => transport.send_raw(envelope, message).await?,
+        }
+
+        Ok(())
+    }
+
+    /// Checks whether the transport can send emails. Only SMTP transports can be unreachable.
+    pub(super) async fn is_reachable(&self) -> bool {
+        match self {
+            Self::Smtp(transport) => transport.test_connection().await.unwrap_or(false),
+            Self::File(_) | Self::Memory(_) => true,
+        }
+    }
+
+    /// Gets the envelope and formatted message of every email sent through an in-memory transport,
+    /// in the order they were sent. Returns `None` for other transports.
+    #[cfg(test)]
+    pub(super) async fn sent(&self) -> Option<Vec<(Envelope, String)>> {
+        match self {
+            Self::Memory(transport) => Some(transport.messages().await),
+            Self::Smtp(_) | Self::File(_) => None,
+        }
+    }
+}
+
+/// Builds an SMTP transport for a relay with TLS and authentication.
+fn smtp_relay() -> AsyncSmtpTransport<Tokio1Executor> {
+    let hostname = dotenvy::var("SMTP_HOSTNAME")
+        .expect("environment variable `SMTP_HOSTNAME` should be a valid string");
+    let username = dotenvy::var("SMTP_USERNAME")
+        .expect("environment variable `SMTP_USERNAME` should be a valid string");
+    let password = dotenvy::var("SMTP_PASSWORD")
+        .expect("environment variable `SMTP_PASSWORD` should be a valid string");
+
+    let smtp_transport = AsyncSmtpTransport::<Tokio1Executor>::relay(&hostname)
+        .expect("SMTP relay couldn't be initialized")
+        .credentials(Credentials::new(username, password));
+
+    with_helo_domain(smtp_transport).build()
+}
+
+/// Builds an SMTP transport for a server without TLS or authentication.
+fn smtp_plain() -> AsyncSmtpTransport<Tokio1Executor> {
+    let hostname = dotenvy::var("SMTP_HOSTNAME")
+        .expect("environment variable `SMTP_HOSTNAME` should be a valid string");
+    let port = dotenvy::var("SMTP_PORT").map_or(25, |port| {
+        port.parse()
+            .expect("environment variable `SMTP_PORT` should be a valid port")
+    });
+
+    let smtp_transport =
+        AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(hostname).port(port);
+
+    with_helo_domain(smtp_transport).build()
+}
+
+/// Sets an SMTP transport's HELO domain from the `SMTP_HELO_DOMAIN` environment variable, if set.
+fn with_helo_domain(smtp_transport: AsyncSmtpTransportBuilder) -> AsyncSmtpTransportBuilder {
+    match dotenvy::var("SMTP_HELO_DOMAIN") {
+        // If the environment variable is unset, let `lettre` default to using the OS hostname.
+        Err(dotenvy::Error::EnvVar(VarError::NotPresent)) => smtp_transport,
+
+        helo_domain => {
+            let helo_domain = helo_domain
+                .expect("environment variable `SMTP_HELO_DOMAIN` should be a valid string if set");
+
+            smtp_transport.hello_name(ClientId::Domain(helo_domain))
+        }
+    }
+}
+
+#[cfg(test)]
+#[expect(clippy::missing_errors_doc, reason = "see rust-lang/rust-clippy#13391")]
+mod tests {
+    use lettre::Message;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn memory_transport_keeps_sent_emails() -> anyhow::Result<()> {
+        let transport = Transport::Memory(AsyncStubTransport::new_ok());
+
+        let message = Message::builder()
+            .from("File Garden <noreply@example.com>".parse()?)
+            .to("user@example.com".parse()?)
+            .subject("Test")
+            .body(String::from("Hello!"))?;
+
+        transport
+            .send_raw(message.envelope(), &message.formatted())
+            .await
+            .map_err(|error| anyhow::anyhow!(error))?;
+
+        let sent = transport
+            .sent()
+            .await
+            .expect("transport should be in memory");
+
+        assert_eq!(sent.len(), 1);
+
+        let (envelope, formatted) = &sent[0];
+        assert_eq!(envelope, message.envelope());
+        assert!(formatted.contains("Subject: Test"));
+        assert!(formatted.ends_with("Hello!"));
+
+        Ok(())
+    }
+}
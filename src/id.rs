@@ -10,6 +10,10 @@ use derive_more::derive::{AsMut, AsRef, Deref, DerefMut};
 use rand::RngCore;
 use serde_with::{DeserializeFromStr, SerializeDisplay};
 use thiserror::Error;
+use utoipa::{
+    openapi::{ObjectBuilder, RefOr, Schema, Type},
+    PartialSchema, ToSchema,
+};
 
 /// The type to create new user IDs with.
 pub(crate) type NewUserId = Id<[u8; 8]>;
@@ -17,6 +21,15 @@ pub(crate) type NewUserId = Id<[u8; 8]>;
 /// A 128-byte token.
 pub type Token = Id<[u8; 128]>;
 
+/// The type to create new folder share keys with.
+pub(crate) type NewShareKey = Id<[u8; 16]>;
+
+/// The type to create new custom domain challenge tokens with.
+pub(crate) type NewChallengeToken = Id<[u8; 16]>;
+
+/// The type to create new access token IDs with.
+pub(crate) type NewAccessTokenId = Id<[u8; 8]>;
+
 /// An ID that can be deserialized from and serialized to `base64url` (without padding).
 #[derive(
     Deref,
@@ -60,6 +73,19 @@ impl<T: AsRef<[u8]>> Display for Id<T> {
     }
 }
 
+impl<T> PartialSchema for Id<T> {
+    fn schema() -> RefOr<Schema> {
+        ObjectBuilder::new()
+            .schema_type(Type::String)
+            .pattern(Some("^[A-Za-z0-9_-]*$"))
+            .description(Some("An ID encoded as `base64url` (without padding)."))
+            .into()
+    }
+}
+
+// Every ID is encoded the same way, so they can all share one schema regardless of their size.
+impl<T> ToSchema for Id<T> {}
+
 impl<T> From<T> for Id<T> {
     fn from(value: T) -> Self {
         Self(value)
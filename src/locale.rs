@@ -0,0 +1,147 @@
+//! See [`Locale`].
+
+use std::convert::Infallible;
+
+use axum::{
+    extract::FromRequestParts,
+    http::{header::ACCEPT_LANGUAGE, request::Parts},
+};
+use serde::{Deserialize, Serialize};
+use strum_macros::IntoStaticStr;
+use utoipa::ToSchema;
+
+/// A locale that emails can be written in. Each is stored by its lowercase language code.
+#[derive(
+    Deserialize, Serialize, ToSchema, IntoStaticStr, Clone, Copy, PartialEq, Eq, Default, Debug,
+)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum Locale {
+    /// English. Used when no other locale is supported.
+    #[default]
+    En,
+
+    /// Spanish.
+    Es,
+}
+
+impl Locale {
+    /// Every supported locale.
+    ///
+    /// Adding a locale requires translating every email template in `templates/email/` and every
+    /// email subject in [`crate::email`].
+    pub(crate) const ALL: [Self; 2] = [Self::En, Self::Es];
+
+    /// Gets the locale with the specified language code, ignoring case.
+    pub(crate) fn from_language_code(code: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|locale| locale.as_str().eq_ignore_ascii_case(code))
+    }
+
+    /// Parses a stored locale, falling back to the default locale if it's no longer supported.
+    pub(crate) fn from_stored(locale: &str) -> Self {
+        Self::from_language_code(locale).unwrap_or_default()
+    }
+
+    /// Gets the locale's lowercase language code, as it's stored.
+    pub(crate) fn as_str(self) -> &'static str {
+        self.into()
+    }
+}
+
+/// An extractor for the most preferred supported locale in the request's `Accept-Language` header,
+/// or the default locale if none are supported.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct PreferredLocale(pub Locale);
+
+impl<S: Send + Sync> FromRequestParts<S> for PreferredLocale {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let accept_language = parts
+            .headers
+            .get_all(ACCEPT_LANGUAGE)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .collect::<Vec<_>>()
+            .join(",");
+
+        Ok(Self(preferred_locale(&accept_language)))
+    }
+}
+
+/// Gets the most preferred supported locale in an `Accept-Language` header value, or the default
+/// locale if none are supported. Only the primary language subtag of each language range is used.
+fn preferred_locale(accept_language: &str) -> Locale {
+    let mut best: Option<(Locale, f32)> = None;
+
+    for language_range in accept_language.split(',') {
+        let mut params = language_range.split(';').map(str::trim);
+
+        let Some(tag) = params.next() else {
+            continue;
+        };
+        let primary_subtag = tag.split('-').next().unwrap_or(tag);
+
+        let Some(locale) = Locale::from_language_code(primary_subtag) else {
+            continue;
+        };
+
+        let quality = params
+            .find_map(|param| param.strip_prefix("q="))
+            .map_or(Some(1.0), |quality| quality.trim().parse().ok())
+            .unwrap_or(0.0);
+
+        // Earlier language ranges win ties, since they're listed in order of preference.
+        if quality > 0.0 && best.is_none_or(|(_, best_quality)| quality > best_quality) {
+            best = Some((locale, quality));
+        }
+    }
+
+    best.map_or_else(Locale::default, |(locale, _)| locale)
+}
+
+#[cfg(test)]
+#[expect(clippy::missing_errors_doc, reason = "see rust-lang/rust-clippy#13391")]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn locales_serialized_as_stored() -> anyhow::Result<()> {
+        for locale in Locale::ALL {
+            let json = format!("\"{}\"", locale.as_str());
+
+            assert_eq!(serde_json::to_string(&locale)?, json);
+            assert_eq!(serde_json::from_str::<Locale>(&json)?, locale);
+        }
+
+        serde_json::from_str::<Locale>("\"fr\"").expect_err("unsupported locales should fail");
+
+        Ok(())
+    }
+
+    #[test]
+    fn preferred_locale_parsing() {
+        let cases = [
+            ("", Locale::En),
+            ("*", Locale::En),
+            ("fr-FR, de;q=0.9", Locale::En),
+            ("es", Locale::Es),
+            ("ES-mx", Locale::Es),
+            ("fr-FR, es;q=0.8, en;q=0.5", Locale::Es),
+            ("en;q=0.5, es-419;q=0.9", Locale::Es),
+            ("en, es", Locale::En),
+            ("es;q=0, en;q=0.1", Locale::En),
+            ("es;q=invalid, en;q=0.1", Locale::En),
+        ];
+
+        for (accept_language, locale) in cases {
+            assert_eq!(
+                preferred_locale(accept_language),
+                locale,
+                "parsing {accept_language:?}",
+            );
+        }
+    }
+}
@@ -0,0 +1,34 @@
+//! See [`initialize`].
+
+use tracing_subscriber::EnvFilter;
+
+/// Initializes the global `tracing` subscriber, which writes logs to stdout.
+///
+/// Which logs are written is configured by the `RUST_LOG` environment variable (see
+/// [`EnvFilter`]), defaulting to `info`. The output format is configured by the `LOG_FORMAT`
+/// environment variable, which is either `pretty` (the default) for humans or `json` for log
+/// collectors.
+///
+/// # Panics
+///
+/// Panics if `RUST_LOG` or `LOG_FORMAT` is invalid.
+pub(crate) fn initialize() {
+    let filter = dotenvy::var("RUST_LOG").map_or_else(
+        |_| EnvFilter::new("info"),
+        |directives| {
+            EnvFilter::try_new(directives)
+                .expect("environment variable `RUST_LOG` should be a valid log filter")
+        },
+    );
+    let subscriber = tracing_subscriber::fmt().with_env_filter(filter);
+
+    match dotenvy::var("LOG_FORMAT").as_deref() {
+        Err(_) | Ok("pretty") => subscriber.pretty().init(),
+        Ok("json") => subscriber
+            .json()
+            .flatten_event(true)
+            .with_span_list(false)
+            .init(),
+        Ok(_) => panic!("environment variable `LOG_FORMAT` should be `pretty` or `json`"),
+    }
+}
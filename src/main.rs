@@ -1,19 +1,27 @@
 //! File Garden's backend web server.
 
-use std::sync::LazyLock;
+use std::{future::IntoFuture, net::SocketAddr, sync::LazyLock, time::Duration};
 
 use axum::handler::Handler;
-use tokio::net::TcpListener;
+use tokio::{net::TcpListener, time::Instant};
+use tokio_util::sync::CancellationToken;
 
 pub mod api;
+mod audit;
+mod client_ip;
 mod content;
 mod crypto;
+mod custom_domain;
 mod db;
 mod email;
 pub mod id;
+mod locale;
+mod logging;
+mod monitoring;
 mod percent_encoding;
 mod response;
 mod router;
+mod storage;
 mod website;
 
 /// The URI origin for user-uploaded content.
@@ -28,6 +36,30 @@ pub(crate) static WEBSITE_ORIGIN: LazyLock<String> = LazyLock::new(|| {
         .expect("environment variable `WEBSITE_ORIGIN` should be a valid string")
 });
 
+/// How long to wait for in-flight requests and background email sends to finish when shutting
+/// down, configured by the `SHUTDOWN_TIMEOUT` environment variable in seconds.
+static SHUTDOWN_TIMEOUT: LazyLock<Duration> = LazyLock::new(|| {
+    dotenvy::var("SHUTDOWN_TIMEOUT").map_or(Duration::from_secs(30), |timeout| {
+        Duration::from_secs(
+            timeout
+                .parse()
+                .expect("environment variable `SHUTDOWN_TIMEOUT` should be a number of seconds"),
+        )
+    })
+});
+
+/// How long to keep serving requests after reporting not ready when shutting down, so load
+/// balancers polling the readiness check stop sending traffic before the listener closes.
+/// Configured by the `SHUTDOWN_DRAIN_DELAY` environment variable in seconds.
+static SHUTDOWN_DRAIN_DELAY: LazyLock<Duration> =
+    LazyLock::new(|| {
+        dotenvy::var("SHUTDOWN_DRAIN_DELAY").map_or(Duration::from_secs(5), |delay| {
+            Duration::from_secs(delay.parse().expect(
+                "environment variable `SHUTDOWN_DRAIN_DELAY` should be a number of seconds",
+            ))
+        })
+    });
+
 /// The state passed to all of the routes.
 #[derive(Clone, Debug)]
 pub struct AppState {
@@ -40,26 +72,120 @@ pub struct AppState {
 /// See implementation.
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
+    let mut args = std::env::args().skip(1);
+    if args.next().as_deref() == Some("preview-email") {
+        return email::preview::command(args);
+    }
+
+    logging::initialize();
+
     let db_url = dotenvy::var("DATABASE_URL")?;
     let address = dotenvy::var("ADDRESS")?;
 
-    println!("Initializing database...");
+    if let Ok(monitoring_address) = dotenvy::var("MONITORING_ADDRESS") {
+        let monitoring_router = monitoring::initialize()?;
+        let monitoring_listener = TcpListener::bind(&monitoring_address).await?;
+
+        tracing::info!("Serving monitoring on {monitoring_address}...");
+
+        tokio::spawn(async move {
+            if let Err(error) = axum::serve(monitoring_listener, monitoring_router).await {
+                tracing::error!(%error, "Monitoring server failed");
+            }
+        });
+    }
+
+    tracing::info!("Initializing database...");
 
     let db_pool = db::initialize(&db_url).await?;
 
-    println!("Listening to {address}...");
+    tracing::info!("Listening to {address}...");
 
     let listener = TcpListener::bind(address).await?;
 
-    println!("Ready!");
+    monitoring::set_db_pool(db_pool.clone());
+
+    tracing::info!("Ready!");
+
+    let outbox_cancellation = CancellationToken::new();
+    let outbox_worker = tokio::spawn(email::outbox::run(
+        db_pool.clone(),
+        outbox_cancellation.clone(),
+    ));
+
+    let shutdown = CancellationToken::new();
+    let mut server = tokio::spawn(
+        axum::serve(
+            listener,
+            router::handle
+                .with_state(AppState { db_pool })
+                .into_make_service_with_connect_info::<SocketAddr>(),
+        )
+        .with_graceful_shutdown(shutdown.clone().cancelled_owned())
+        .into_future(),
+    );
+
+    tokio::select! {
+        result = &mut server => {
+            result??;
+            return Ok(());
+        }
+        () = shutdown_signal() => {}
+    }
 
-    axum::serve(
-        listener,
-        router::handle
-            .with_state(AppState { db_pool })
-            .into_make_service(),
-    )
-    .await?;
+    tracing::info!("Shutting down...");
+
+    // Stop receiving traffic, then give in-flight requests and the email being sent a chance to
+    // finish. Other queued emails stay in the outbox until a worker runs again.
+    monitoring::set_draining();
+    tokio::time::sleep(*SHUTDOWN_DRAIN_DELAY).await;
+    shutdown.cancel();
+
+    let deadline = Instant::now() + *SHUTDOWN_TIMEOUT;
+
+    if let Ok(result) = tokio::time::timeout_at(deadline, &mut server).await {
+        result??;
+    } else {
+        tracing::warn!("Timed out waiting for in-flight requests before shutdown");
+    }
+
+    outbox_cancellation.cancel();
+
+    if tokio::time::timeout_at(deadline, outbox_worker)
+        .await
+        .is_err()
+    {
+        tracing::warn!("Timed out sending email before shutdown, so it'll be retried later");
+    }
 
     Ok(())
 }
+
+/// Waits for a signal to shut down the server, which is either `SIGINT` (Ctrl+C) or `SIGTERM`.
+///
+/// # Panics
+///
+/// Panics if the signal handlers can't be installed.
+async fn shutdown_signal() {
+    let interrupt = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("Ctrl+C handler should be installable");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("`SIGTERM` handler should be installable")
+            .recv()
+            .await;
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        () = interrupt => {}
+        () = terminate => {}
+    }
+}
@@ -0,0 +1,253 @@
+//! An internal server for monitoring, which is never exposed publicly. It serves:
+//!
+//! - Prometheus metrics at `/metrics`.
+//! - A liveness check at `/health/live`, which passes whenever the server is running.
+//! - A readiness check at `/health/ready`, which passes once the server is initialized and can
+//!   handle requests. See [`ready`].
+
+use std::{
+    collections::BTreeMap,
+    future::Future,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        LazyLock, OnceLock,
+    },
+    time::Duration,
+};
+
+use axum::{extract::State, http::StatusCode, routing::get, Json, Router};
+use metrics_exporter_prometheus::{BuildError, Matcher, PrometheusBuilder, PrometheusHandle};
+use serde::Serialize;
+use sqlx::PgPool;
+
+use crate::{email, website};
+
+/// The histogram buckets for request durations, in seconds.
+const DURATION_BUCKETS: [f64; 12] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0,
+];
+
+/// How long each readiness check can take before it fails.
+const CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The optional readiness checks to run, configured by the comma-separated `READINESS_CHECKS`
+/// environment variable. Valid checks are `website` (the internal website server is reachable) and
+/// `smtp` (the SMTP server is reachable).
+static OPTIONAL_CHECKS: LazyLock<Vec<OptionalCheck>> = LazyLock::new(|| {
+    let Ok(checks) = dotenvy::var("READINESS_CHECKS") else {
+        return Vec::new();
+    };
+
+    checks
+        .split(',')
+        .map(str::trim)
+        .filter(|check| !check.is_empty())
+        .map(|check| match check {
+            "website" => OptionalCheck::Website,
+            "smtp" => OptionalCheck::Smtp,
+            _ => panic!(
+                "environment variable `READINESS_CHECKS` should only list `website` or `smtp`"
+            ),
+        })
+        .collect()
+});
+
+/// The database pool, set once the database is initialized.
+static DB_POOL: OnceLock<PgPool> = OnceLock::new();
+
+/// Whether the server is shutting down and should stop receiving traffic.
+static DRAINING: AtomicBool = AtomicBool::new(false);
+
+/// An optional readiness check.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum OptionalCheck {
+    /// The internal website server is reachable.
+    Website,
+
+    /// The SMTP server is reachable.
+    Smtp,
+}
+
+/// The response body of the readiness check.
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct ReadyResponse {
+    /// Whether every check passed.
+    ready: bool,
+
+    /// Whether each check passed, by name.
+    checks: BTreeMap<&'static str, bool>,
+}
+
+/// Installs the global metrics recorder, returning a router for the monitoring server.
+///
+/// Metrics recorded before this is called are discarded, so it should be called as early as
+/// possible.
+///
+/// # Errors
+///
+/// Returns an error if a global metrics recorder is already installed.
+pub(crate) fn initialize() -> Result<Router, BuildError> {
+    let handle = PrometheusBuilder::new()
+        .set_buckets_for_metric(
+            Matcher::Full("http_request_duration_seconds".into()),
+            &DURATION_BUCKETS,
+        )?
+        .install_recorder()?;
+
+    Ok(router(handle))
+}
+
+/// Returns a router for the monitoring server, rendering metrics from the specified handle.
+fn router(handle: PrometheusHandle) -> Router {
+    Router::new()
+        .route("/metrics", get(render_metrics))
+        .route("/health/live", get(|| async { StatusCode::OK }))
+        .route("/health/ready", get(ready))
+        .with_state(handle)
+}
+
+/// Marks the database initialized, so the server can become ready.
+///
+/// # Panics
+///
+/// Panics if the database was already marked initialized.
+pub(crate) fn set_db_pool(db_pool: PgPool) {
+    DB_POOL
+        .set(db_pool)
+        .expect("database pool should only be set once");
+}
+
+/// Marks the server as shutting down, so it's never ready again and orchestrators stop sending it
+/// traffic.
+pub(crate) fn set_draining() {
+    DRAINING.store(true, Ordering::Relaxed);
+}
+
+/// Renders the recorded metrics, updating the database pool usage first since it's only known when
+/// requested.
+async fn render_metrics(State(handle): State<PrometheusHandle>) -> String {
+    if let Some(db_pool) = DB_POOL.get() {
+        let size = db_pool.size();
+        let idle = u32::try_from(db_pool.num_idle()).unwrap_or(u32::MAX);
+
+        metrics::gauge!("db_pool_connections", "state" => "active").set(size.saturating_sub(idle));
+        metrics::gauge!("db_pool_connections", "state" => "idle").set(idle);
+        metrics::gauge!("db_pool_max_connections").set(db_pool.options().get_max_connections());
+    }
+
+    handle.render()
+}
+
+/// Checks whether the server is ready to handle requests. It's ready once the database is
+/// initialized, as long as the server isn't shutting down, the database is reachable, all
+/// migrations are applied, and every [optional check](OPTIONAL_CHECKS) passes.
+async fn ready() -> (StatusCode, Json<ReadyResponse>) {
+    let mut checks = BTreeMap::new();
+
+    checks.insert("running", !DRAINING.load(Ordering::Relaxed));
+
+    match DB_POOL.get() {
+        Some(db_pool) => {
+            checks.insert("database", check(db_ping(db_pool)).await);
+            checks.insert("migrations", check(migrations_applied(db_pool)).await);
+        }
+        None => {
+            checks.insert("database", false);
+        }
+    }
+
+    for optional_check in OPTIONAL_CHECKS.iter() {
+        match optional_check {
+            OptionalCheck::Website => {
+                checks.insert("website", check(website::is_reachable()).await);
+            }
+            OptionalCheck::Smtp => {
+                checks.insert("smtp", check(email::is_reachable()).await);
+            }
+        }
+    }
+
+    let ready = checks.values().all(|passed| *passed);
+    let status = if ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+
+    (status, Json(ReadyResponse { ready, checks }))
+}
+
+/// Runs a check, failing it if it takes longer than [`CHECK_TIMEOUT`].
+async fn check(future: impl Future<Output = bool>) -> bool {
+    tokio::time::timeout(CHECK_TIMEOUT, future)
+        .await
+        .unwrap_or(false)
+}
+
+/// Checks whether the database is reachable.
+async fn db_ping(db_pool: &PgPool) -> bool {
+    sqlx::query!("SELECT 1 as ping")
+        .fetch_one(db_pool)
+        .await
+        .is_ok()
+}
+
+/// Checks whether every migration embedded in this build has been applied to the database.
+async fn migrations_applied(db_pool: &PgPool) -> bool {
+    // This isn't checked at compile time since SQLx creates this table itself, so it isn't in the
+    // migrations.
+    let Ok(applied) = sqlx::query_scalar::<_, i64>(
+        "SELECT version
+            FROM _sqlx_migrations
+            WHERE success",
+    )
+    .fetch_all(db_pool)
+    .await
+    else {
+        return false;
+    };
+
+    sqlx::migrate!()
+        .iter()
+        .filter(|migration| migration.migration_type.is_up_migration())
+        .all(|migration| applied.contains(&migration.version))
+}
+
+#[cfg(test)]
+#[expect(clippy::missing_errors_doc, reason = "see rust-lang/rust-clippy#13391")]
+mod tests {
+    use axum::{body::Body, http::Request};
+    use tower::ServiceExt;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn only_liveness_passes_while_draining() -> anyhow::Result<()> {
+        // The recorder isn't installed globally, so this doesn't conflict with anything else.
+        let router = router(PrometheusBuilder::new().build_recorder().handle());
+
+        // No other test depends on whether the server is draining.
+        set_draining();
+
+        let response = router
+            .clone()
+            .oneshot(Request::get("/health/ready").body(Body::empty())?)
+            .await?;
+
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await?;
+        let body: serde_json::Value = serde_json::from_slice(&body)?;
+        assert_eq!(body["ready"], false);
+        assert_eq!(body["checks"]["running"], false);
+
+        let response = router
+            .oneshot(Request::get("/health/live").body(Body::empty())?)
+            .await?;
+
+        assert_eq!(response.status(), StatusCode::OK);
+
+        Ok(())
+    }
+}
@@ -24,3 +24,20 @@ pub(crate) const COMPONENT: &AsciiSet = &NON_ALPHANUMERIC
 /// [`encodeURIComponent`](https://developer.mozilla.org/docs/Web/JavaScript/Reference/Global_Objects/encodeURIComponent),
 /// with the exception that `/` characters are left alone rather than percent-encoded.
 pub(crate) const COMPONENT_IGNORING_SLASH: &AsciiSet = &COMPONENT.remove(b'/');
+
+/// All ASCII characters that must be percent-encoded in an [RFC 8187 extended parameter
+/// value](https://www.rfc-editor.org/rfc/rfc8187#section-3.2.1), such as a `filename*` parameter in a
+/// `Content-Disposition` header.
+pub(crate) const EXT_VALUE: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'!')
+    .remove(b'#')
+    .remove(b'$')
+    .remove(b'&')
+    .remove(b'+')
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'^')
+    .remove(b'_')
+    .remove(b'`')
+    .remove(b'|')
+    .remove(b'~');
@@ -1,5 +1,7 @@
 //! See [`Response`].
 
+use std::{error::Error, iter};
+
 use axum::{
     body::Body,
     http::{
@@ -83,6 +85,19 @@ impl Response {
 
         self.body(status.to_string())
     }
+
+    /// Logs an unexpected error along with its source chain, and sets the response to a plain
+    /// `500 Internal Server Error` (see [`Response::plain_error`]). The error is never exposed to
+    /// clients.
+    pub(crate) fn internal_error(self, error: &dyn Error) -> Self {
+        let sources: Vec<String> = iter::successors(error.source(), |&error| error.source())
+            .map(ToString::to_string)
+            .collect();
+
+        tracing::error!(error = sources.join(": "), "{error}");
+
+        self.plain_error(StatusCode::INTERNAL_SERVER_ERROR)
+    }
 }
 
 impl axum::response::IntoResponse for Response {
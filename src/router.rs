@@ -1,15 +1,21 @@
 //! See [`handle`].
 
-use std::sync::LazyLock;
+use std::{net::SocketAddr, sync::LazyLock, time::Instant};
 
 use axum::{
-    extract::{Request, State},
-    http::{header::HOST, StatusCode},
+    extract::{ConnectInfo, MatchedPath, Request, State},
+    http::{header::HOST, HeaderName, HeaderValue, Method, StatusCode},
     response::{IntoResponse, Response},
 };
 use axum_macros::debug_handler;
+use strum_macros::IntoStaticStr;
+use tracing::Instrument;
+use uuid::Uuid;
 
-use crate::{api, content, website, AppState, CONTENT_ORIGIN, WEBSITE_ORIGIN};
+use crate::{
+    api, client_ip::ClientIp, content, custom_domain, website, AppState, CONTENT_ORIGIN,
+    WEBSITE_ORIGIN,
+};
 
 /// The URI host for user-uploaded content.
 static CONTENT_HOST: LazyLock<&str> = LazyLock::new(|| host_from_origin(&CONTENT_ORIGIN));
@@ -17,27 +23,175 @@ static CONTENT_HOST: LazyLock<&str> = LazyLock::new(|| host_from_origin(&CONTENT
 /// The URI host for the website.
 static WEBSITE_HOST: LazyLock<&str> = LazyLock::new(|| host_from_origin(&WEBSITE_ORIGIN));
 
+/// The response header containing the unique ID of the request, which is also attached to the
+/// request's logs.
+static X_REQUEST_ID: HeaderName = HeaderName::from_static("x-request-id");
+
+/// A service requests are routed to, used to label request metrics.
+#[derive(IntoStaticStr, Clone, Copy, PartialEq, Eq, Debug)]
+#[strum(serialize_all = "snake_case")]
+enum Service {
+    /// The HTTP API.
+    Api,
+
+    /// User-uploaded content.
+    Content,
+
+    /// The proxy to the website's internal server.
+    Website,
+
+    /// A folder served from a custom domain.
+    CustomDomain,
+
+    /// No service, since the request's host is unknown.
+    None,
+}
+
 /// Handles all incoming requests and routes them to other services based on the request URI.
+///
+/// Requests to any host other than the content or website host are routed to the folder of the
+/// verified custom domain with that host, if there is one.
+///
+/// The [`ClientIp`] of every request is determined here, before any routing. Each request is also
+/// given a unique ID, which is returned in the `X-Request-ID` response header and attached to
+/// every log written while handling the request. Request counts and durations are recorded as
+/// metrics.
 #[debug_handler]
-pub(super) async fn handle(State(state): State<AppState>, request: Request) -> Response {
+pub(super) async fn handle(
+    State(state): State<AppState>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    mut request: Request,
+) -> Response {
+    let client_ip = ClientIp::new(peer, request.headers());
+    request.extensions_mut().insert(client_ip);
+
+    let request_id = Uuid::new_v4().to_string();
+
+    // The query isn't logged since it can contain secrets, such as email verification codes.
+    let span = tracing::info_span!(
+        "request",
+        id = request_id,
+        method = %request.method(),
+        host = request.headers().get(HOST).and_then(|host| host.to_str().ok()),
+        path = request.uri().path(),
+        client_ip = %client_ip.0,
+    );
+
+    let method = metric_method(request.method());
+
+    let start = Instant::now();
+    let (service, mut response) = route(state, request).instrument(span.clone()).await;
+    let duration = start.elapsed();
+
+    span.in_scope(|| {
+        tracing::info!(
+            status = response.status().as_u16(),
+            latency_ms = duration.as_secs_f64() * 1000.0,
+            "Responded",
+        );
+    });
+
+    // Only API routes are labeled, since other paths are unbounded.
+    let route = response
+        .extensions()
+        .get::<MatchedPath>()
+        .map_or_else(String::new, |path| path.as_str().into());
+    let service: &str = service.into();
+
+    metrics::counter!(
+        "http_requests_total",
+        "service" => service,
+        "route" => route.clone(),
+        "method" => method,
+        "status" => response.status().as_str().to_owned(),
+    )
+    .increment(1);
+    metrics::histogram!(
+        "http_request_duration_seconds",
+        "service" => service,
+        "route" => route,
+        "method" => method,
+    )
+    .record(duration);
+
+    response.headers_mut().insert(
+        X_REQUEST_ID.clone(),
+        HeaderValue::from_str(&request_id).expect("request ID should be a valid header value"),
+    );
+
+    response
+}
+
+/// Routes a request to the service for its host, returning which service it was routed to.
+async fn route(state: AppState, request: Request) -> (Service, Response) {
     let host = request
         .headers()
         .get(HOST)
         .and_then(|host| host.to_str().ok());
 
     if host == Some(*CONTENT_HOST) {
-        return content::handle(request).into_response();
+        return (
+            Service::Content,
+            content::handle(State(state), request).await.into_response(),
+        );
     }
 
     if host == Some(*WEBSITE_HOST) {
         if request.uri().path().starts_with("/api/") {
-            return api::handle(State(state), request).await;
+            return (Service::Api, api::handle(State(state), request).await);
         }
 
-        return website::handle(request).await;
+        return (Service::Website, website::handle(request).await);
     }
 
-    StatusCode::MISDIRECTED_REQUEST.into_response()
+    let Some(host) = host else {
+        return (
+            Service::None,
+            StatusCode::MISDIRECTED_REQUEST.into_response(),
+        );
+    };
+
+    // Custom domains are stored without a port or trailing dot, and in lowercase.
+    let domain = host.rsplit_once(':').map_or(host, |(domain, _port)| domain);
+    let domain = domain
+        .strip_suffix('.')
+        .unwrap_or(domain)
+        .to_ascii_lowercase();
+
+    match custom_domain::find_target(&state, &domain).await {
+        Ok(Some(target)) => (
+            Service::CustomDomain,
+            content::handle_custom_domain(State(state), request, &target)
+                .await
+                .into_response(),
+        ),
+        Ok(None) => (
+            Service::None,
+            StatusCode::MISDIRECTED_REQUEST.into_response(),
+        ),
+        Err(error) => {
+            tracing::error!(%error, "Couldn't find custom domain");
+            (
+                Service::CustomDomain,
+                StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+            )
+        }
+    }
+}
+
+/// Returns a request method's metric label. Nonstandard methods are grouped together since they're
+/// unbounded.
+fn metric_method(method: &Method) -> &'static str {
+    match *method {
+        Method::GET => "GET",
+        Method::HEAD => "HEAD",
+        Method::POST => "POST",
+        Method::PUT => "PUT",
+        Method::PATCH => "PATCH",
+        Method::DELETE => "DELETE",
+        Method::OPTIONS => "OPTIONS",
+        _ => "OTHER",
+    }
 }
 
 /// Returns the host from an origin URI string.
@@ -0,0 +1,94 @@
+//! Storage for the contents of user-uploaded files.
+
+use std::{io, path::PathBuf, sync::LazyLock};
+
+use futures_util::{stream, StreamExt, TryStreamExt};
+use tokio::{
+    fs::{self, File},
+    io::AsyncBufRead,
+};
+use tokio_util::io::{ReaderStream, StreamReader};
+
+use crate::id::Id;
+
+/// The local directory that file contents are stored in.
+static DIRECTORY: LazyLock<PathBuf> = LazyLock::new(|| {
+    // Tests store file contents in a temporary directory of their own.
+    if cfg!(test) {
+        return std::env::temp_dir().join(format!("storage-test-{}", std::process::id()));
+    }
+
+    dotenvy::var("STORAGE_DIRECTORY")
+        .expect("environment variable `STORAGE_DIRECTORY` should be a valid string")
+        .into()
+});
+
+/// The encoding a file's contents are stored with, corresponding to the database's `encoding` type.
+#[derive(sqlx::Type, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+#[sqlx(type_name = "encoding", rename_all = "lowercase")]
+pub(crate) enum Encoding {
+    /// [Brotli](https://developer.mozilla.org/docs/Glossary/Brotli_compression) compression.
+    Br,
+}
+
+/// Returns the path that one part of a file's contents is stored at.
+fn part_path(file_id: &[u8], part: i32) -> PathBuf {
+    DIRECTORY.join(format!("{}.{part}", Id::from(file_id)))
+}
+
+/// Opens a file's stored contents for reading, joining all of its parts in order.
+///
+/// The contents are read exactly as stored, so they're still in the file's stored [`Encoding`]. Each
+/// part is only opened once the previous one has been fully read.
+pub(crate) fn read(file_id: &[u8], parts: i32) -> impl AsyncBufRead + Send + Unpin + use<> {
+    let file_id = file_id.to_vec();
+
+    let chunks = stream::iter(0..parts)
+        .then(move |part| File::open(part_path(&file_id, part)))
+        .map_ok(ReaderStream::new)
+        .try_flatten();
+
+    StreamReader::new(Box::pin(chunks))
+}
+
+/// Deletes a file's stored contents.
+///
+/// This is best effort: a part that fails to be deleted is logged and left behind, since the file
+/// is already gone from the database either way. A part that's already missing is ignored.
+pub(crate) async fn delete(file_id: &[u8], parts: i32) {
+    for part in 0..parts {
+        let path = part_path(file_id, part);
+
+        match fs::remove_file(&path).await {
+            Err(error) if error.kind() != io::ErrorKind::NotFound => {
+                tracing::error!(
+                    %error,
+                    path = %path.display(),
+                    "Failed to delete stored file contents",
+                );
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Stores one part of a file's contents, for tests.
+///
+/// # Errors
+///
+/// Returns an error if the part can't be written.
+#[cfg(test)]
+pub(crate) async fn write_part(file_id: &[u8], part: i32, contents: &[u8]) -> io::Result<()> {
+    fs::create_dir_all(&*DIRECTORY).await?;
+    fs::write(part_path(file_id, part), contents).await
+}
+
+/// Checks if one part of a file's contents is stored, for tests.
+///
+/// # Errors
+///
+/// Returns an error if the part's existence can't be checked.
+#[cfg(test)]
+pub(crate) async fn part_exists(file_id: &[u8], part: i32) -> io::Result<bool> {
+    fs::try_exists(part_path(file_id, part)).await
+}
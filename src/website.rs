@@ -28,6 +28,15 @@ static INTERNAL_CLIENT: LazyLock<reqwest::Client> = LazyLock::new(|| {
         .expect("internal website client should build")
 });
 
+/// Checks whether the website's internal server is reachable.
+pub(crate) async fn is_reachable() -> bool {
+    INTERNAL_CLIENT
+        .head(format!("http://{}/", *INTERNAL_ADDRESS))
+        .send()
+        .await
+        .is_ok()
+}
+
 /// The service function to handle incoming requests for the website, proxying them to the website's
 /// internal server.
 pub(super) async fn handle(request: Request) -> Response {
@@ -48,8 +57,12 @@ pub(super) async fn handle(request: Request) -> Response {
     .try_into()
     .expect("internal website request should be valid");
 
-    let Ok(response) = INTERNAL_CLIENT.execute(request).await else {
-        return StatusCode::SERVICE_UNAVAILABLE.into_response();
+    let response = match INTERNAL_CLIENT.execute(request).await {
+        Ok(response) => response,
+        Err(error) => {
+            tracing::error!(%error, "Couldn't reach the internal website server");
+            return StatusCode::SERVICE_UNAVAILABLE.into_response();
+        }
     };
 
     let mut response_builder = Response::builder().status(response.status());